@@ -0,0 +1,180 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion};
+use did_toolkit::prelude::*;
+use either::Either;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+use url::Url;
+
+const SIZES: [usize; 2] = [1_000, 10_000];
+const THREADS: usize = 4;
+
+fn did(i: usize) -> DID {
+    DID::parse(&format!("did:bench:{:08}", i)).unwrap()
+}
+
+// a document of roughly realistic size: a few keys, relationships and services
+fn document(i: usize) -> Document {
+    let id = did(i);
+
+    let methods: Vec<VerificationMethod> = (0..3)
+        .map(|k| VerificationMethod {
+            id: URL::parse(&format!("{}#key-{}", id, k)).unwrap(),
+            controller: id.clone(),
+            typ: VerificationMethodType::Ed255192018,
+            public_key_multibase: Some(MultiBase::new(vec![k as u8; 32])),
+            ..Default::default()
+        })
+        .collect();
+
+    Document {
+        id: id.clone(),
+        controller: Some(Controller(Either::Left(did((i + 1) % 100)))),
+        authentication: Some(VerificationMethods(
+            methods
+                .iter()
+                .map(|vm| VerificationMethodEither(Either::Right(vm.id.clone())))
+                .collect(),
+        )),
+        verification_method: Some(methods.into_iter().collect()),
+        service: Some(
+            (0..2)
+                .map(|s| ServiceEndpoint {
+                    id: Url::parse(&format!("https://example.org/{}/{}", i, s)).unwrap(),
+                    typ: ServiceTypes(Either::Left(ServiceType::LinkedDomains)),
+                    endpoint: ServiceEndpoints(Either::Left(
+                        Url::parse("https://example.org").unwrap(),
+                    )),
+                })
+                .collect(),
+        ),
+        ..Default::default()
+    }
+}
+
+fn registry(size: usize) -> Registry {
+    let mut reg = Registry::default();
+    for i in 0..size {
+        reg.insert(document(i)).unwrap();
+    }
+
+    reg
+}
+
+fn bench_get(c: &mut Criterion) {
+    let mut group = c.benchmark_group("get");
+
+    for size in SIZES {
+        let mutex = Mutex::new(registry(size));
+        let shared = SharedRegistry::from(registry(size));
+        let key = did(size / 2);
+
+        group.bench_with_input(BenchmarkId::new("mutex", size), &key, |b, key| {
+            b.iter(|| mutex.lock().unwrap().get(key))
+        });
+
+        group.bench_with_input(BenchmarkId::new("shared", size), &key, |b, key| {
+            b.iter(|| shared.get(key))
+        });
+    }
+
+    group.finish();
+}
+
+// THREADS readers each perform the requested number of lookups, while one writer keeps updating
+// documents; the time until all readers are done is measured.
+fn contended(iters: u64, read: impl Fn(&DID) + Sync, write: impl Fn(usize) + Sync) -> Duration {
+    let done = std::sync::atomic::AtomicBool::new(false);
+
+    std::thread::scope(|s| {
+        s.spawn(|| {
+            let mut i = 0;
+            while !done.load(std::sync::atomic::Ordering::Relaxed) {
+                write(i);
+                i += 1;
+                std::thread::sleep(Duration::from_micros(100));
+            }
+        });
+
+        let start = Instant::now();
+        let readers: Vec<_> = (0..THREADS)
+            .map(|t| {
+                let read = &read;
+                s.spawn(move || {
+                    for i in 0..iters {
+                        read(&did((i as usize * 7 + t) % 1_000));
+                    }
+                })
+            })
+            .collect();
+
+        for reader in readers {
+            reader.join().unwrap();
+        }
+
+        let elapsed = start.elapsed();
+        done.store(true, std::sync::atomic::Ordering::Relaxed);
+        elapsed
+    })
+}
+
+fn bench_contended(c: &mut Criterion) {
+    let mut group = c.benchmark_group("contended_get");
+    group.sample_size(20);
+
+    let mutex = Mutex::new(registry(1_000));
+    group.bench_function("mutex", |b| {
+        b.iter_custom(|iters| {
+            contended(
+                iters,
+                |did| {
+                    mutex.lock().unwrap().get(did);
+                },
+                |i| {
+                    mutex.lock().unwrap().update(document(i % 1_000)).unwrap();
+                },
+            )
+        })
+    });
+
+    let shared = SharedRegistry::from(registry(1_000));
+    group.bench_function("shared", |b| {
+        b.iter_custom(|iters| {
+            contended(
+                iters,
+                |did| {
+                    shared.get(did);
+                },
+                |i| {
+                    shared.update(document(i % 1_000)).unwrap();
+                },
+            )
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_write(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+
+    for size in SIZES {
+        let mut reg = registry(size);
+        let shared = SharedRegistry::from(registry(size));
+        let doc = document(size / 2);
+
+        group.bench_with_input(BenchmarkId::new("registry", size), &doc, |b, doc| {
+            b.iter(|| reg.update(doc.clone()).unwrap())
+        });
+
+        group.bench_with_input(BenchmarkId::new("shared", size), &doc, |b, doc| {
+            b.iter(|| shared.update(doc.clone()).unwrap())
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_get, bench_contended, bench_write);
+criterion_main!(benches);
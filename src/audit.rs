@@ -0,0 +1,490 @@
+use crate::{
+    did::DID,
+    document::{AlsoKnownAsEither, Document, VerificationRelationship},
+    index::{KeyMaterial, ReverseIndex},
+    registry::Registry,
+    url::URL,
+};
+use either::Either;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+};
+
+/// Finding is a registry-level problem reported by [Registry::audit]. These are problems that
+/// checking each document on its own with [Document::valid] does not catch. Documents in the
+/// remote cache count as present while they are fresh, as they do for lookups.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Finding {
+    /// A document names a controller which is not in the registry.
+    MissingController { id: DID, controller: DID },
+    /// A document names another in `alsoKnownAs`, but the other is missing, or does not name it
+    /// back.
+    OneWayAlsoKnownAs { id: DID, also_known_as: DID },
+    /// A verification relationship refers by [URL] to a verification method which does not
+    /// exist.
+    DanglingVerificationMethod {
+        id: DID,
+        relationship: VerificationRelationship,
+        url: URL,
+    },
+    /// The [KeyMaterial] of a verification method cannot be determined, because its JWK cannot
+    /// be thumbprinted, so it is not checked for [Finding::SharedKeyMaterial].
+    UnreadableKeyMaterial {
+        id: DID,
+        method: URL,
+        reason: String,
+    },
+    /// The same [KeyMaterial] is carried by the documents of several [DID]s.
+    SharedKeyMaterial {
+        key: KeyMaterial,
+        dids: BTreeSet<DID>,
+    },
+    /// The documents of these [DID]s control each other, directly or through one another. A
+    /// document naming itself as controller is not a cycle.
+    ControllerCycle { dids: BTreeSet<DID> },
+}
+
+impl Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |dids: &BTreeSet<DID>| {
+            dids.iter()
+                .map(|did| did.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        match self {
+            Self::MissingController { id, controller } => write!(
+                f,
+                "DID {} names controller {}, which is not in the registry",
+                id, controller
+            ),
+            Self::OneWayAlsoKnownAs { id, also_known_as } => write!(
+                f,
+                "DID {} is also known as {}, which does not name it in return",
+                id, also_known_as
+            ),
+            Self::DanglingVerificationMethod {
+                id,
+                relationship,
+                url,
+            } => write!(
+                f,
+                "DID {} refers to verification method {} in {}, which does not exist",
+                id, url, relationship
+            ),
+            Self::UnreadableKeyMaterial { id, method, reason } => write!(
+                f,
+                "DID {} has verification method {}, whose key material cannot be determined: {}",
+                id, method, reason
+            ),
+            Self::SharedKeyMaterial { dids, .. } => {
+                write!(f, "Key material is shared by DIDs {}", list(dids))
+            }
+            Self::ControllerCycle { dids } => {
+                write!(f, "DIDs {} form a controller cycle", list(dids))
+            }
+        }
+    }
+}
+
+pub(crate) fn audit(reg: &Registry, index: &ReverseIndex) -> Vec<Finding> {
+    let mut findings = Vec::new();
+
+    for (_, doc) in reg.iter() {
+        findings.extend(document_findings(reg, doc));
+    }
+
+    for (key, dids) in index.shared_keys() {
+        findings.push(Finding::SharedKeyMaterial { key, dids });
+    }
+
+    for dids in controller_cycles(reg) {
+        findings.push(Finding::ControllerCycle { dids });
+    }
+
+    findings
+}
+
+fn document_findings(reg: &Registry, doc: &Document) -> Vec<Finding> {
+    let mut findings = Vec::new();
+    let id = &doc.id;
+
+    for controller in doc.controller.iter().flat_map(|c| c.dids()) {
+        if reg.get_arc(&controller).is_none() {
+            findings.push(Finding::MissingController {
+                id: id.clone(),
+                controller,
+            });
+        }
+    }
+
+    for item in doc.also_known_as.iter().flat_map(|aka| aka.0.iter()) {
+        if let Either::Left(other) = &item.0 {
+            if other == id {
+                continue;
+            }
+
+            let reciprocated = reg.get_arc(other).is_some_and(|other| {
+                other
+                    .also_known_as
+                    .as_ref()
+                    .is_some_and(|aka| aka.0.contains(&AlsoKnownAsEither(Either::Left(id.clone()))))
+            });
+
+            if !reciprocated {
+                findings.push(Finding::OneWayAlsoKnownAs {
+                    id: id.clone(),
+                    also_known_as: other.clone(),
+                });
+            }
+        }
+    }
+
+    for (relationship, vms) in doc.verification_relationships() {
+        for url in vms.0.iter().filter_map(|vm| vm.0.as_ref().right()) {
+            let exists = reg.get_arc(&url.to_did()).is_some_and(|target| {
+                target
+                    .verification_method
+                    .iter()
+                    .flatten()
+                    .any(|vm| &vm.id == url)
+            });
+
+            if !exists {
+                findings.push(Finding::DanglingVerificationMethod {
+                    id: id.clone(),
+                    relationship,
+                    url: url.clone(),
+                });
+            }
+        }
+    }
+
+    for vm in doc.verification_methods() {
+        if let Err(e) = KeyMaterial::from_verification_method(vm) {
+            findings.push(Finding::UnreadableKeyMaterial {
+                id: id.clone(),
+                method: vm.id.clone(),
+                reason: e.to_string(),
+            });
+        }
+    }
+
+    findings
+}
+
+// the strongly connected components of the controller graph with more than one member, found with
+// an iterative form of Tarjan's algorithm so that long chains of controllers do not exhaust the
+// stack.
+fn controller_cycles(reg: &Registry) -> Vec<BTreeSet<DID>> {
+    let mut tarjan = Tarjan {
+        reg,
+        visited: BTreeMap::new(),
+        stack: Vec::new(),
+        on_stack: BTreeSet::new(),
+        work: Vec::new(),
+        cycles: Vec::new(),
+    };
+
+    for (root, _) in reg.iter() {
+        if !tarjan.visited.contains_key(root) {
+            tarjan.run(root.clone());
+        }
+    }
+
+    tarjan.cycles.sort();
+    tarjan.cycles
+}
+
+struct Tarjan<'a> {
+    reg: &'a Registry,
+    // index and lowest reachable index of each visited DID
+    visited: BTreeMap<DID, (usize, usize)>,
+    stack: Vec<DID>,
+    on_stack: BTreeSet<DID>,
+    // the DIDs being visited, with their controllers and the next one to follow
+    work: Vec<(DID, Vec<DID>, usize)>,
+    cycles: Vec<BTreeSet<DID>>,
+}
+
+impl Tarjan<'_> {
+    fn controllers(&self, did: &DID) -> Vec<DID> {
+        self.reg[did]
+            .controller
+            .iter()
+            .flat_map(|c| c.dids())
+            .filter(|controller| controller != did && self.reg.position(controller).is_some())
+            .collect()
+    }
+
+    fn visit(&mut self, did: DID) {
+        let next = self.visited.len();
+        self.visited.insert(did.clone(), (next, next));
+        self.stack.push(did.clone());
+        self.on_stack.insert(did.clone());
+        let controllers = self.controllers(&did);
+        self.work.push((did, controllers, 0));
+    }
+
+    fn lower(&mut self, did: &DID, low: usize) {
+        if let Some(entry) = self.visited.get_mut(did) {
+            entry.1 = entry.1.min(low);
+        }
+    }
+
+    fn run(&mut self, root: DID) {
+        self.visit(root);
+
+        while let Some((did, controllers, pos)) = self.work.last_mut() {
+            if let Some(controller) = controllers.get(*pos).cloned() {
+                *pos += 1;
+                let did = did.clone();
+
+                match self.visited.get(&controller) {
+                    None => self.visit(controller),
+                    Some((index, _)) if self.on_stack.contains(&controller) => {
+                        let index = *index;
+                        self.lower(&did, index)
+                    }
+                    Some(_) => {}
+                }
+
+                continue;
+            }
+
+            let (did, _, _) = self.work.pop().unwrap();
+            let (index, low) = self.visited[&did];
+
+            if let Some((parent, _, _)) = self.work.last() {
+                let parent = parent.clone();
+                self.lower(&parent, low);
+            }
+
+            if index == low {
+                let mut component = BTreeSet::new();
+                while let Some(member) = self.stack.pop() {
+                    self.on_stack.remove(&member);
+                    let done = member == did;
+                    component.insert(member);
+                    if done {
+                        break;
+                    }
+                }
+
+                if component.len() > 1 {
+                    self.cycles.push(component);
+                }
+            }
+        }
+    }
+}
+
+mod tests {
+    #[cfg(test)]
+    fn did(name: &str) -> crate::did::DID {
+        use crate::did::DID;
+
+        DID::parse(&format!("did:testing:u:{}", name)).unwrap()
+    }
+
+    #[test]
+    fn test_document_findings() {
+        use super::Finding;
+        use crate::{
+            document::{
+                AlsoKnownAs, AlsoKnownAsEither, Controller, Document, VerificationMethod,
+                VerificationMethodEither, VerificationMethods, VerificationRelationship,
+            },
+            registry::Registry,
+            url::URL,
+        };
+        use either::Either;
+
+        let aka = |names: &[&str]| {
+            Some(AlsoKnownAs(
+                names
+                    .iter()
+                    .map(|name| AlsoKnownAsEither(Either::Left(did(name))))
+                    .collect(),
+            ))
+        };
+
+        let mut reg = Registry::default();
+        reg.insert(Document {
+            id: did("alice"),
+            also_known_as: aka(&["alice", "bob", "charlie"]),
+            ..Default::default()
+        })
+        .unwrap();
+        reg.insert(Document {
+            id: did("bob"),
+            also_known_as: aka(&["alice"]),
+            verification_method: Some(
+                [VerificationMethod {
+                    id: URL::parse("did:testing:u:bob#key-1").unwrap(),
+                    controller: did("bob"),
+                    ..Default::default()
+                }]
+                .into(),
+            ),
+            ..Default::default()
+        })
+        .unwrap();
+        assert!(reg.audit().iter().all(|finding| !matches!(
+            finding,
+            Finding::OneWayAlsoKnownAs { also_known_as, .. } if also_known_as == &did("bob")
+        )));
+
+        // charlie does not name alice in return, and refers to a missing method of bob's
+        reg.insert(Document {
+            id: did("charlie"),
+            controller: Some(Controller(Either::Left(did("dave")))),
+            authentication: Some(VerificationMethods(
+                [
+                    VerificationMethodEither(Either::Right(
+                        URL::parse("did:testing:u:bob#key-1").unwrap(),
+                    )),
+                    VerificationMethodEither(Either::Right(
+                        URL::parse("did:testing:u:bob#key-2").unwrap(),
+                    )),
+                ]
+                .into(),
+            )),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(
+            reg.audit(),
+            vec![
+                Finding::OneWayAlsoKnownAs {
+                    id: did("alice"),
+                    also_known_as: did("charlie")
+                },
+                Finding::MissingController {
+                    id: did("charlie"),
+                    controller: did("dave")
+                },
+                Finding::DanglingVerificationMethod {
+                    id: did("charlie"),
+                    relationship: VerificationRelationship::Authentication,
+                    url: URL::parse("did:testing:u:bob#key-2").unwrap(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shared_key_material() {
+        use super::Finding;
+        use crate::{
+            document::{Document, VerificationMethod},
+            index::KeyMaterial,
+            jwk::JWK,
+            multibase::MultiBase,
+            registry::Registry,
+            url::URL,
+        };
+
+        let jwk = JWK::new().unwrap();
+        let doc = |name: &str, jwk: &JWK| Document {
+            id: did(name),
+            verification_method: Some(
+                [VerificationMethod {
+                    id: URL::parse(&format!("did:testing:u:{}#key-1", name)).unwrap(),
+                    controller: did(name),
+                    public_key_jwk: Some(jwk.clone()),
+                    ..Default::default()
+                }]
+                .into(),
+            ),
+            ..Default::default()
+        };
+
+        let mut reg = Registry::default();
+        reg.insert(doc("alice", &jwk)).unwrap();
+        reg.insert(doc("bob", &jwk)).unwrap();
+        reg.insert(doc("charlie", &JWK::new().unwrap())).unwrap();
+
+        let findings = reg.audit();
+        assert_eq!(findings.len(), 1);
+        assert!(matches!(
+            &findings[0],
+            Finding::SharedKeyMaterial { dids, .. } if dids == &[did("alice"), did("bob")].into()
+        ));
+        assert!(findings[0].to_string().contains("shared by DIDs"));
+
+        // a method's multibase key is checked even when its JWK cannot be thumbprinted, which is
+        // reported on its own
+        let key = MultiBase::new(vec![1, 2, 3]);
+        let broken = JWK::new_from_bytes(br#"{"kty":"EC","crv":"P-256","x":"AQID"}"#).unwrap();
+        for name in ["dave", "erin"] {
+            let mut doc = doc(name, &broken);
+            if let Some(vms) = &mut doc.verification_method {
+                let mut vm = vms.pop_first().unwrap();
+                vm.public_key_multibase = Some(key.clone());
+                vms.insert(vm);
+            }
+            reg.insert(doc).unwrap();
+        }
+
+        let findings = reg.audit();
+        assert_eq!(findings.len(), 4);
+        for (finding, name) in findings.iter().zip(["dave", "erin"]) {
+            assert!(matches!(
+                finding,
+                Finding::UnreadableKeyMaterial { id, reason, .. }
+                    if id == &did(name) && reason.contains("missing required member y")
+            ));
+        }
+        assert!(findings.contains(&Finding::SharedKeyMaterial {
+            key: KeyMaterial::from_multibase(&key),
+            dids: [did("dave"), did("erin")].into(),
+        }));
+    }
+
+    #[test]
+    fn test_controller_cycles() {
+        use super::Finding;
+        use crate::{
+            document::{Controller, Document},
+            registry::Registry,
+        };
+        use either::Either;
+
+        let doc = |name: &str, controllers: &[&str]| Document {
+            id: did(name),
+            controller: Some(Controller(Either::Right(
+                controllers.iter().map(|name| did(name)).collect(),
+            ))),
+            ..Default::default()
+        };
+
+        // naming yourself as controller is not a cycle
+        let mut reg = Registry::default();
+        reg.insert(doc("alice", &["alice", "bob"])).unwrap();
+        reg.insert(doc("bob", &["alice"])).unwrap();
+        reg.insert(doc("charlie", &["charlie"])).unwrap();
+
+        let findings = reg.audit();
+        assert_eq!(
+            findings,
+            vec![Finding::ControllerCycle {
+                dids: [did("alice"), did("bob")].into()
+            }]
+        );
+        assert!(findings[0].to_string().contains("controller cycle"));
+
+        // a longer cycle, through charlie
+        reg.update(doc("bob", &["charlie"])).unwrap();
+        reg.update(doc("charlie", &["alice"])).unwrap();
+        assert_eq!(
+            reg.audit(),
+            vec![Finding::ControllerCycle {
+                dids: [did("alice"), did("bob"), did("charlie")].into()
+            }]
+        );
+    }
+}
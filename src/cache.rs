@@ -0,0 +1,412 @@
+use crate::{
+    did::DID,
+    document::Document,
+    fetch::{FetchRequest, FetchResponse},
+};
+use imbl::OrdMap;
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::{Duration, SystemTime},
+};
+use url::Url;
+
+/// CachePolicy controls how long remotely fetched documents are kept by a [RemoteCache], and how
+/// many of them. Lifetimes are taken from the `Cache-Control` header of the response when
+/// present, bounded by `max_ttl`; otherwise `default_ttl` is used.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CachePolicy {
+    pub default_ttl: Duration,
+    pub max_ttl: Duration,
+    pub capacity: usize,
+}
+
+impl Default for CachePolicy {
+    fn default() -> Self {
+        Self {
+            default_ttl: Duration::from_secs(60 * 60),
+            max_ttl: Duration::from_secs(24 * 60 * 60),
+            capacity: 1000,
+        }
+    }
+}
+
+/// Freshness describes how a response may be cached, as determined from its headers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Freshness {
+    /// The response must not be stored (`Cache-Control: no-store`).
+    NoStore,
+    /// The response may be used until the provided time, after which it must be revalidated.
+    Until(SystemTime),
+}
+
+impl CachePolicy {
+    /// Determine the [Freshness] of a [FetchResponse] received at `now`. `no-cache` is treated as
+    /// an immediately stale response, `max-age` and `s-maxage` are honored, and the `Age` header
+    /// is subtracted from them.
+    pub fn freshness(&self, resp: &FetchResponse, now: SystemTime) -> Freshness {
+        let mut ttl = None;
+
+        if let Some(cc) = resp.headers.get("cache-control") {
+            for directive in cc.split(',').map(|d| d.trim().to_ascii_lowercase()) {
+                let (name, value) = match directive.split_once('=') {
+                    Some((name, value)) => (
+                        name.trim().to_string(),
+                        Some(value.trim().trim_matches('"').to_string()),
+                    ),
+                    None => (directive.clone(), None),
+                };
+
+                match (name.as_str(), value) {
+                    ("no-store", _) => return Freshness::NoStore,
+                    ("no-cache", _) => return Freshness::Until(now),
+                    ("s-maxage", Some(value)) => {
+                        if let Ok(secs) = value.parse::<u64>() {
+                            ttl = Some(Duration::from_secs(secs));
+                        }
+                    }
+                    ("max-age", Some(value)) if ttl.is_none() => {
+                        if let Ok(secs) = value.parse::<u64>() {
+                            ttl = Some(Duration::from_secs(secs));
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        let mut ttl = ttl.unwrap_or(self.default_ttl).min(self.max_ttl);
+
+        if let Some(age) = resp
+            .headers
+            .get("age")
+            .and_then(|age| age.trim().parse::<u64>().ok())
+        {
+            ttl = ttl.saturating_sub(Duration::from_secs(age));
+        }
+
+        Freshness::Until(now + ttl)
+    }
+}
+
+/// A [Document] held by the [RemoteCache], along with what is needed to revalidate it.
+#[derive(Debug)]
+pub struct CacheEntry {
+    pub document: Arc<Document>,
+    pub url: Url,
+    pub fetched: SystemTime,
+    pub expires: SystemTime,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    used: AtomicU64,
+}
+
+impl CacheEntry {
+    /// Create a [CacheEntry] from a [FetchResponse] and the [Document] parsed from it.
+    pub fn new(
+        url: Url,
+        document: Document,
+        resp: &FetchResponse,
+        now: SystemTime,
+        expires: SystemTime,
+    ) -> Self {
+        Self {
+            document: Arc::new(document),
+            url,
+            fetched: now,
+            expires,
+            etag: resp.headers.get("etag").cloned(),
+            last_modified: resp.headers.get("last-modified").cloned(),
+            used: AtomicU64::new(0),
+        }
+    }
+
+    /// Determine if the entry may be used without revalidation.
+    pub fn is_fresh(&self, now: SystemTime) -> bool {
+        now < self.expires
+    }
+
+    /// Build a conditional request which revalidates the entry, using `If-None-Match` and
+    /// `If-Modified-Since` when the original response carried validators.
+    pub fn revalidation_request(&self) -> FetchRequest {
+        let mut req = FetchRequest::new(self.url.clone());
+
+        if let Some(etag) = &self.etag {
+            req.headers
+                .insert("if-none-match".to_string(), etag.clone());
+        }
+
+        if let Some(last_modified) = &self.last_modified {
+            req.headers
+                .insert("if-modified-since".to_string(), last_modified.clone());
+        }
+
+        req
+    }
+
+    /// Apply a `304 Not Modified` response: the entry is fresh again, and validators provided by
+    /// the response replace the stored ones.
+    pub fn revalidated(&mut self, resp: &FetchResponse, now: SystemTime, expires: SystemTime) {
+        self.fetched = now;
+        self.expires = expires;
+
+        if let Some(etag) = resp.headers.get("etag") {
+            self.etag = Some(etag.clone());
+        }
+
+        if let Some(last_modified) = resp.headers.get("last-modified") {
+            self.last_modified = Some(last_modified.clone());
+        }
+    }
+}
+
+impl Clone for CacheEntry {
+    fn clone(&self) -> Self {
+        Self {
+            document: self.document.clone(),
+            url: self.url.clone(),
+            fetched: self.fetched,
+            expires: self.expires,
+            etag: self.etag.clone(),
+            last_modified: self.last_modified.clone(),
+            used: AtomicU64::new(self.used.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+/// RemoteCache holds documents fetched from remote sources by the
+/// [crate::registry::Registry], separately from the documents inserted into it directly. Entries
+/// expire according to the [CachePolicy], and the least recently used entries are evicted when
+/// the cache grows past its capacity.
+///
+/// Recency is tracked with atomic counters so that reads through a shared reference still count
+/// as use. Finding the eviction candidate is a linear scan; eviction only happens after a remote
+/// fetch, which dwarfs it.
+#[derive(Debug, Default)]
+pub struct RemoteCache {
+    policy: CachePolicy,
+    entries: OrdMap<DID, CacheEntry>,
+    clock: AtomicU64,
+}
+
+impl Clone for RemoteCache {
+    fn clone(&self) -> Self {
+        Self {
+            policy: self.policy.clone(),
+            entries: self.entries.clone(),
+            clock: AtomicU64::new(self.clock.load(Ordering::Relaxed)),
+        }
+    }
+}
+
+impl RemoteCache {
+    /// Create an empty cache with the provided [CachePolicy].
+    pub fn new(policy: CachePolicy) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Retrieve the [CachePolicy] in use.
+    pub fn policy(&self) -> &CachePolicy {
+        &self.policy
+    }
+
+    /// Change the [CachePolicy]. Returns the [DID]s evicted if the capacity shrank.
+    pub fn set_policy(&mut self, policy: CachePolicy) -> Vec<DID> {
+        self.policy = policy;
+        self.evict()
+    }
+
+    /// Retrieve a fresh entry, marking it as used.
+    pub fn get(&self, did: &DID, now: SystemTime) -> Option<&CacheEntry> {
+        let entry = self.entries.get(did)?;
+
+        if entry.is_fresh(now) {
+            self.touch(entry);
+            Some(entry)
+        } else {
+            None
+        }
+    }
+
+    /// Retrieve an entry whether or not it is fresh, without marking it as used.
+    pub fn entry(&self, did: &DID) -> Option<&CacheEntry> {
+        self.entries.get(did)
+    }
+
+    /// Retrieve a mutable entry whether or not it is fresh, marking it as used.
+    pub fn entry_mut(&mut self, did: &DID) -> Option<&mut CacheEntry> {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        let entry = self.entries.get_mut(did)?;
+        entry.used.store(tick, Ordering::Relaxed);
+        Some(entry)
+    }
+
+    /// Store an entry, replacing any previous entry for the same [DID]. Returns the [DID]s evicted
+    /// to stay within capacity.
+    pub fn insert(&mut self, entry: CacheEntry) -> Vec<DID> {
+        self.touch(&entry);
+        self.entries.insert(entry.document.id.clone(), entry);
+        self.evict()
+    }
+
+    /// Remove an entry.
+    pub fn remove(&mut self, did: &DID) -> Option<CacheEntry> {
+        self.entries.remove(did)
+    }
+
+    /// Remove all entries which are no longer fresh at `now`, returning their [DID]s.
+    pub fn expire(&mut self, now: SystemTime) -> Vec<DID> {
+        let expired: Vec<DID> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| !entry.is_fresh(now))
+            .map(|(did, _)| did.clone())
+            .collect();
+
+        for did in &expired {
+            self.entries.remove(did);
+        }
+
+        expired
+    }
+
+    /// Iterate over all entries, fresh or not, ordered by [DID].
+    pub fn iter(&self) -> impl Iterator<Item = (&DID, &CacheEntry)> {
+        self.entries.iter()
+    }
+
+    /// The number of entries, fresh or not.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Determine if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    fn touch(&self, entry: &CacheEntry) {
+        let tick = self.clock.fetch_add(1, Ordering::Relaxed) + 1;
+        entry.used.store(tick, Ordering::Relaxed);
+    }
+
+    fn evict(&mut self) -> Vec<DID> {
+        let mut evicted = Vec::new();
+
+        while self.entries.len() > self.policy.capacity {
+            let lru = self
+                .entries
+                .iter()
+                .min_by_key(|(_, entry)| entry.used.load(Ordering::Relaxed))
+                .map(|(did, _)| did.clone());
+
+            match lru {
+                Some(did) => {
+                    self.entries.remove(&did);
+                    evicted.push(did);
+                }
+                None => break,
+            }
+        }
+
+        evicted
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_freshness() {
+        use super::{CachePolicy, Freshness};
+        use crate::fetch::FetchResponse;
+        use std::time::{Duration, SystemTime};
+
+        let policy = CachePolicy {
+            default_ttl: Duration::from_secs(60),
+            max_ttl: Duration::from_secs(600),
+            capacity: 10,
+        };
+        let now = SystemTime::now();
+        let resp = |cc: &str| FetchResponse {
+            status: 200,
+            headers: [("cache-control".to_string(), cc.to_string())].into(),
+            body: Vec::new(),
+        };
+
+        assert_eq!(
+            policy.freshness(&FetchResponse::default(), now),
+            Freshness::Until(now + Duration::from_secs(60))
+        );
+        assert_eq!(
+            policy.freshness(&resp("public, max-age=120"), now),
+            Freshness::Until(now + Duration::from_secs(120))
+        );
+        assert_eq!(
+            policy.freshness(&resp("max-age=120, s-maxage=30"), now),
+            Freshness::Until(now + Duration::from_secs(30))
+        );
+        assert_eq!(
+            policy.freshness(&resp("max-age=86400"), now),
+            Freshness::Until(now + Duration::from_secs(600))
+        );
+        assert_eq!(
+            policy.freshness(&resp("no-cache"), now),
+            Freshness::Until(now)
+        );
+        assert_eq!(policy.freshness(&resp("no-store"), now), Freshness::NoStore);
+
+        let mut aged = resp("max-age=120");
+        aged.headers.insert("age".to_string(), "100".to_string());
+        assert_eq!(
+            policy.freshness(&aged, now),
+            Freshness::Until(now + Duration::from_secs(20))
+        );
+    }
+
+    #[test]
+    fn test_lru() {
+        use super::{CacheEntry, CachePolicy, RemoteCache};
+        use crate::{did::DID, document::Document, fetch::FetchResponse};
+        use std::time::{Duration, SystemTime};
+        use url::Url;
+
+        let now = SystemTime::now();
+        let later = now + Duration::from_secs(60);
+        let entry = |name: &str| {
+            CacheEntry::new(
+                Url::parse(&format!("https://example.org/{}", name)).unwrap(),
+                Document {
+                    id: DID::parse(&format!("did:testing:u:{}", name)).unwrap(),
+                    ..Default::default()
+                },
+                &FetchResponse::default(),
+                now,
+                later,
+            )
+        };
+
+        let alice = DID::parse("did:testing:u:alice").unwrap();
+        let bob = DID::parse("did:testing:u:bob").unwrap();
+        let charlie = DID::parse("did:testing:u:charlie").unwrap();
+
+        let mut cache = RemoteCache::new(CachePolicy {
+            capacity: 2,
+            ..Default::default()
+        });
+
+        assert!(cache.insert(entry("alice")).is_empty());
+        assert!(cache.insert(entry("bob")).is_empty());
+        assert!(cache.get(&alice, now).is_some());
+        assert_eq!(cache.insert(entry("charlie")), vec![bob.clone()]);
+        assert!(cache.get(&bob, now).is_none());
+        assert!(cache.get(&charlie, now).is_some());
+        assert!(cache.get(&alice, later).is_none());
+        assert!(cache.entry(&alice).is_some());
+        assert_eq!(cache.expire(later).len(), 2);
+        assert!(cache.is_empty());
+    }
+}
@@ -149,7 +149,6 @@ mod tests {
         let did = DID {
             name: "abcdef".into(),
             id: "123456".into(),
-            ..Default::default()
         };
 
         assert_eq!(did.to_string(), "did:abcdef:123456");
@@ -157,7 +156,6 @@ mod tests {
         let did = DID {
             name: "abcdef".into(),
             id: "123456:u:alice".into(),
-            ..Default::default()
         };
 
         assert_eq!(did.to_string(), "did:abcdef:123456:u:alice");
@@ -182,7 +180,6 @@ mod tests {
             DID {
                 name: "abcdef".into(),
                 id: "123456".into(),
-                ..Default::default()
             }
         );
 
@@ -192,7 +189,6 @@ mod tests {
             DID {
                 name: "abcdef".into(),
                 id: "123456:u:alice".into(),
-                ..Default::default()
             }
         );
     }
@@ -207,7 +203,6 @@ mod tests {
             DID {
                 name: "123456".into(),
                 id: "123".into(),
-                ..Default::default()
             }
         );
 
@@ -0,0 +1,264 @@
+use crate::{did::DID, document::Document, metadata::Identifiers, registry::Version};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeSet;
+
+/// Diff describes how one [crate::registry::Registry] (the right) differs from another (the
+/// left), as computed by [crate::registry::Registry::diff]. It can be serialized as a change
+/// report. Tombstones and the remote cache are not compared.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Diff {
+    /// [DID]s which are only in the right registry.
+    pub added: BTreeSet<DID>,
+    /// [DID]s which are only in the left registry.
+    pub removed: BTreeSet<DID>,
+    /// Documents which are in both registries, but differ, or have different [Identifiers]
+    /// asserted for them. Ordered by [DID].
+    pub changed: Vec<DocumentChange>,
+}
+
+impl Diff {
+    /// Determine if the registries hold the same documents.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// DocumentChange describes how a document differs between two registries.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentChange {
+    pub id: DID,
+    /// The [Version] of the document in the left registry.
+    pub left_version: Option<Version>,
+    /// The [Version] of the document in the right registry.
+    pub right_version: Option<Version>,
+    /// The properties which differ, ordered by their JSON names. Differences in the [Identifiers]
+    /// asserted for the document are given as the `equivalentId` and `canonicalId` properties.
+    pub properties: Vec<PropertyChange>,
+}
+
+/// PropertyChange describes a single property of a document which differs between two
+/// registries. Values are given as JSON; a missing value means the property is not set.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PropertyChange {
+    /// The JSON name of the property, e.g. `verificationMethod`.
+    pub property: String,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub left: Option<Value>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub right: Option<Value>,
+}
+
+impl DocumentChange {
+    pub(crate) fn new(
+        left: &Document,
+        left_version: Option<Version>,
+        right: &Document,
+        right_version: Option<Version>,
+    ) -> Self {
+        let properties = |doc: &Document| match serde_json::to_value(doc) {
+            Ok(Value::Object(map)) => map,
+            _ => Default::default(),
+        };
+
+        let (mut a, mut b) = (properties(left), properties(right));
+        let properties = left
+            .changed_properties(right)
+            .into_iter()
+            .map(|property| PropertyChange {
+                left: a.remove(&property),
+                right: b.remove(&property),
+                property,
+            })
+            .collect();
+
+        Self {
+            id: left.id.clone(),
+            left_version,
+            right_version,
+            properties,
+        }
+    }
+
+    // add the differences between the identifiers asserted in either registry
+    pub(crate) fn add_identifiers(&mut self, left: &Identifiers, right: &Identifiers) {
+        let properties = |identifiers: &Identifiers| match serde_json::to_value(identifiers) {
+            Ok(Value::Object(map)) => map,
+            _ => Default::default(),
+        };
+
+        let (mut a, mut b) = (properties(left), properties(right));
+        for property in ["canonicalId", "equivalentId"] {
+            let (left, right) = (a.remove(property), b.remove(property));
+            if left != right {
+                self.properties.push(PropertyChange {
+                    property: property.to_string(),
+                    left,
+                    right,
+                });
+            }
+        }
+
+        self.properties.sort_by(|a, b| a.property.cmp(&b.property));
+    }
+}
+
+/// Conflict is a document which differs between two registries being merged, passed to a
+/// [ConflictResolver] to decide which document to keep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Conflict<'a> {
+    /// The document in the registry being merged into.
+    pub left: &'a Document,
+    pub left_version: Option<Version>,
+    /// The document in the registry being merged from.
+    pub right: &'a Document,
+    pub right_version: Option<Version>,
+}
+
+/// ConflictResolver decides the outcome of a [Conflict] during
+/// [crate::registry::Registry::merge]. It returns the document to keep, which must have the same
+/// `id`; returning an error fails the merge. [MergePolicy] provides the common choices, and
+/// closures taking a [Conflict] may be used for anything else.
+pub trait ConflictResolver {
+    fn resolve(&self, conflict: &Conflict<'_>) -> Result<Document, anyhow::Error>;
+}
+
+impl<F> ConflictResolver for F
+where
+    F: Fn(&Conflict<'_>) -> Result<Document, anyhow::Error>,
+{
+    fn resolve(&self, conflict: &Conflict<'_>) -> Result<Document, anyhow::Error> {
+        self(conflict)
+    }
+}
+
+/// MergePolicy is a [ConflictResolver] for the common ways of settling a [Conflict].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum MergePolicy {
+    /// Keep the document in the registry being merged into.
+    PreferLeft,
+    /// Take the document from the registry being merged from.
+    PreferRight,
+    /// Keep whichever document has the greater [Version], preferring the left if they are equal.
+    /// Versions are only comparable between registries which share a history, such as one
+    /// imported from the other.
+    Newest,
+    /// Fail the merge.
+    #[default]
+    Fail,
+}
+
+impl ConflictResolver for MergePolicy {
+    fn resolve(&self, conflict: &Conflict<'_>) -> Result<Document, anyhow::Error> {
+        match self {
+            MergePolicy::PreferLeft => Ok(conflict.left.clone()),
+            MergePolicy::PreferRight => Ok(conflict.right.clone()),
+            MergePolicy::Newest => {
+                if conflict.right_version > conflict.left_version {
+                    Ok(conflict.right.clone())
+                } else {
+                    Ok(conflict.left.clone())
+                }
+            }
+            MergePolicy::Fail => Err(anyhow!(
+                "DID {} differs between registries",
+                conflict.left.id
+            )),
+        }
+    }
+}
+
+mod tests {
+    #[cfg(test)]
+    fn doc(name: &str, controller: Option<&str>) -> crate::document::Document {
+        use crate::{
+            did::DID,
+            document::{Controller, Document},
+        };
+        use either::Either;
+
+        let did = |name: &str| DID::parse(&format!("did:testing:u:{}", name)).unwrap();
+        Document {
+            id: did(name),
+            controller: controller.map(|c| Controller(Either::Left(did(c)))),
+            ..Default::default()
+        }
+    }
+
+    // two registries holding alice and bob, where on the right alice is removed, charlie added
+    // and bob made controlled by charlie
+    #[cfg(test)]
+    fn fixture() -> (crate::registry::Registry, crate::registry::Registry) {
+        use crate::registry::Registry;
+
+        let mut left = Registry::default();
+        left.insert(doc("alice", None)).unwrap();
+        left.insert(doc("bob", None)).unwrap();
+
+        let mut right = left.clone();
+        right.remove(&doc("alice", None).id);
+        right.update(doc("bob", Some("charlie"))).unwrap();
+        right.insert(doc("charlie", None)).unwrap();
+
+        (left, right)
+    }
+
+    #[test]
+    fn test_diff() {
+        let did = |name: &str| doc(name, None).id;
+        let (left, right) = fixture();
+        assert!(left.diff(&left.clone()).is_empty());
+
+        let diff = left.diff(&right);
+        assert_eq!(diff.added, [did("charlie")].into());
+        assert_eq!(diff.removed, [did("alice")].into());
+        assert_eq!(diff.changed.len(), 1);
+        let change = &diff.changed[0];
+        assert_eq!(change.id, did("bob"));
+        assert!(change.right_version > change.left_version);
+        assert_eq!(change.properties.len(), 1);
+        assert_eq!(change.properties[0].property, "controller");
+        assert!(change.properties[0].left.is_none());
+        assert_eq!(
+            change.properties[0].right,
+            Some(serde_json::json!("did:testing:u:charlie"))
+        );
+
+        // the report survives a round trip
+        let report = serde_json::to_string(&diff).unwrap();
+        assert_eq!(serde_json::from_str::<super::Diff>(&report).unwrap(), diff);
+    }
+
+    #[test]
+    fn test_merge() {
+        use super::{Conflict, MergePolicy};
+        let did = |name: &str| doc(name, None).id;
+        let (left, right) = fixture();
+
+        let mut merged = left.clone();
+        assert!(merged.merge(&right, &MergePolicy::Fail).is_err());
+        assert!(merged.diff(&left).is_empty());
+
+        let changes = merged.merge(&right, &MergePolicy::PreferLeft).unwrap();
+        assert_eq!(changes.added, [did("charlie")].into());
+        assert!(changes.removed.is_empty() && changes.changed.is_empty());
+        assert_eq!(merged[&did("bob")], doc("bob", None));
+
+        for policy in [MergePolicy::PreferRight, MergePolicy::Newest] {
+            let mut merged = left.clone();
+            let changes = merged.merge(&right, &policy).unwrap();
+            assert_eq!(changes.changed.len(), 1);
+            assert_eq!(merged[&did("bob")], doc("bob", Some("charlie")));
+            assert!(merged.get(&did("alice")).is_some());
+        }
+
+        // custom resolvers must keep the DID
+        let mut merged = left.clone();
+        let resolver = |_: &Conflict<'_>| Ok(doc("dave", None));
+        assert!(merged.merge(&right, &resolver).is_err());
+        assert!(merged.diff(&left).is_empty());
+    }
+}
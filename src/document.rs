@@ -2,11 +2,13 @@ use crate::{did::DID, jwk::JWK, multibase::MultiBase, registry::Registry, url::U
 use anyhow::anyhow;
 use either::Either;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{collections::BTreeSet, fmt::Display, hash::Hash, str::FromStr};
 use url::Url;
 
-#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Default)]
 pub enum VerificationMethodType {
+    #[default]
     JWK2020,
     ECDSASECP256K12019,
     Ed255192018,
@@ -17,12 +19,6 @@ pub enum VerificationMethodType {
     VerifiableCondition2021,
 }
 
-impl Default for VerificationMethodType {
-    fn default() -> Self {
-        VerificationMethodType::JWK2020
-    }
-}
-
 impl FromStr for VerificationMethodType {
     type Err = anyhow::Error;
 
@@ -148,9 +144,38 @@ pub struct ServiceEndpointProperties {
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ServiceTypes(pub Either<ServiceType, BTreeSet<ServiceType>>);
 
+impl ServiceTypes {
+    /// Retrieve the set of [ServiceType]s regardless of how they were specified.
+    pub fn types(&self) -> BTreeSet<ServiceType> {
+        match &self.0 {
+            Either::Left(typ) => [typ.clone()].into(),
+            Either::Right(set) => set.clone(),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ServiceEndpoints(pub Either<Url, ServiceEndpointProperties>);
 
+impl ServiceEndpoints {
+    /// Retrieve every [Url] named by the endpoint, including `origins` and `registries`.
+    pub fn urls(&self) -> BTreeSet<Url> {
+        match &self.0 {
+            Either::Left(url) => [url.clone()].into(),
+            Either::Right(properties) => {
+                let mut set = BTreeSet::new();
+                for urls in [&properties.origins, &properties.registries]
+                    .into_iter()
+                    .flatten()
+                {
+                    set.extend(urls.iter().cloned());
+                }
+                set
+            }
+        }
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
 pub struct ServiceEndpoint {
     pub id: Url,
@@ -175,13 +200,14 @@ impl VerificationMethods {
                 Either::Left(vm) => vm.valid()?,
                 Either::Right(url) => {
                     if let Some(registry) = &registry {
-                        if let Some(doc) = registry.get(&url.to_did()) {
-                            if let Some(vms) = doc.verification_method {
-                                if vms.iter().any(|vm| &(*vm).id == url) {
-                                    return Ok(());
-                                } else {
-                                    return Err(anyhow!("Could not locate verification method prescribed by {} in registry", url));
-                                }
+                        if let Some(doc) = registry.get_arc(&url.to_did()) {
+                            if !doc
+                                .verification_method
+                                .iter()
+                                .flatten()
+                                .any(|vm| &vm.id == url)
+                            {
+                                return Err(anyhow!("Could not locate verification method prescribed by {} in registry", url));
                             }
                         } else {
                             return Err(anyhow!(
@@ -200,6 +226,55 @@ impl VerificationMethods {
     }
 }
 
+/// The verification relationships a [Document] may express. See
+/// <https://www.w3.org/TR/did-core/#verification-relationships>.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum VerificationRelationship {
+    Authentication,
+    AssertionMethod,
+    KeyAgreement,
+    CapabilityInvocation,
+    CapabilityDelegation,
+}
+
+impl VerificationRelationship {
+    /// All verification relationships, in the order they appear in a [Document].
+    pub const ALL: [VerificationRelationship; 5] = [
+        Self::Authentication,
+        Self::AssertionMethod,
+        Self::KeyAgreement,
+        Self::CapabilityInvocation,
+        Self::CapabilityDelegation,
+    ];
+}
+
+impl Display for VerificationRelationship {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            Self::Authentication => "authentication",
+            Self::AssertionMethod => "assertionMethod",
+            Self::KeyAgreement => "keyAgreement",
+            Self::CapabilityInvocation => "capabilityInvocation",
+            Self::CapabilityDelegation => "capabilityDelegation",
+        })
+    }
+}
+
+impl FromStr for VerificationRelationship {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "authentication" => Ok(Self::Authentication),
+            "assertionMethod" => Ok(Self::AssertionMethod),
+            "keyAgreement" => Ok(Self::KeyAgreement),
+            "capabilityInvocation" => Ok(Self::CapabilityInvocation),
+            "capabilityDelegation" => Ok(Self::CapabilityDelegation),
+            _ => Err(anyhow!("Property does not match")),
+        }
+    }
+}
+
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct AlsoKnownAsEither(pub Either<DID, Url>);
 
@@ -209,6 +284,16 @@ pub struct AlsoKnownAs(pub BTreeSet<AlsoKnownAsEither>);
 #[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Controller(pub Either<DID, BTreeSet<DID>>);
 
+impl Controller {
+    /// Retrieve the set of controlling [DID]s regardless of how they were specified.
+    pub fn dids(&self) -> BTreeSet<DID> {
+        match &self.0 {
+            Either::Left(did) => [did.clone()].into(),
+            Either::Right(set) => set.clone(),
+        }
+    }
+}
+
 impl Default for Controller {
     fn default() -> Self {
         Controller(Either::Right(BTreeSet::default()))
@@ -308,6 +393,78 @@ pub struct Document {
 }
 
 impl Document {
+    /// Retrieve every [VerificationMethod] embedded in the document: those in the
+    /// `verificationMethod` property as well as those defined inline in verification
+    /// relationships. Methods referred to by [URL] are not included.
+    pub fn verification_methods(&self) -> Vec<&VerificationMethod> {
+        let mut ret: Vec<&VerificationMethod> = self
+            .verification_method
+            .iter()
+            .flat_map(|set| set.iter())
+            .collect();
+
+        for (_, field) in self.verification_relationships() {
+            for vm in field.0.iter() {
+                if let Either::Left(vm) = &vm.0 {
+                    ret.push(vm);
+                }
+            }
+        }
+
+        ret
+    }
+
+    /// Retrieve the [VerificationMethods] for a [VerificationRelationship], if the document has
+    /// that property.
+    pub fn relationship(&self, rel: VerificationRelationship) -> Option<&VerificationMethods> {
+        match rel {
+            VerificationRelationship::Authentication => self.authentication.as_ref(),
+            VerificationRelationship::AssertionMethod => self.assertion_method.as_ref(),
+            VerificationRelationship::KeyAgreement => self.key_agreement.as_ref(),
+            VerificationRelationship::CapabilityInvocation => self.capability_invocation.as_ref(),
+            VerificationRelationship::CapabilityDelegation => self.capability_delegation.as_ref(),
+        }
+    }
+
+    /// Retrieve the verification relationships which are present in the document.
+    pub fn verification_relationships(
+        &self,
+    ) -> Vec<(VerificationRelationship, &VerificationMethods)> {
+        VerificationRelationship::ALL
+            .into_iter()
+            .filter_map(|rel| self.relationship(rel).map(|field| (rel, field)))
+            .collect()
+    }
+
+    /// Determine which properties differ between two documents, by their JSON names, e.g.
+    /// `verificationMethod`.
+    pub fn changed_properties(&self, other: &Document) -> BTreeSet<String> {
+        let properties = |doc: &Document| match serde_json::to_value(doc) {
+            Ok(serde_json::Value::Object(map)) => map,
+            _ => Default::default(),
+        };
+
+        let (a, b) = (properties(self), properties(other));
+        a.keys()
+            .chain(b.keys())
+            .filter(|key| a.get(*key) != b.get(*key))
+            .cloned()
+            .collect()
+    }
+
+    /// Serialize the document as canonical JSON: object members are sorted by name, and no
+    /// insignificant whitespace is written, following
+    /// [RFC 8785](https://www.rfc-editor.org/rfc/rfc8785) for the values a document can hold.
+    /// Equal documents always serialize to the same bytes.
+    pub fn canonical_json(&self) -> Result<Vec<u8>, anyhow::Error> {
+        canonical_json(self)
+    }
+
+    /// The SHA-256 digest of the document's [Document::canonical_json] form.
+    pub fn digest(&self) -> Result<[u8; 32], anyhow::Error> {
+        Ok(Sha256::digest(self.canonical_json()?).into())
+    }
+
     /// Determines if a document is valid. Takes an optional registry to resolve [URL]s
     pub fn valid(&self, registry: Option<&Registry>) -> Result<(), anyhow::Error> {
         if let Some(vm) = &self.verification_method {
@@ -324,16 +481,43 @@ impl Document {
             &self.key_agreement,
             &self.capability_invocation,
             &self.capability_delegation,
-        ] {
-            if let Some(field) = field {
-                field.valid(registry)?
-            }
+        ]
+        .into_iter()
+        .flatten()
+        {
+            field.valid(registry)?
         }
 
         Ok(())
     }
 }
 
+// serialize a value as JSON with object members sorted by name and no insignificant whitespace.
+pub(crate) fn canonical_json<T: Serialize>(value: &T) -> Result<Vec<u8>, anyhow::Error> {
+    fn canonicalize(value: serde_json::Value) -> serde_json::Value {
+        match value {
+            serde_json::Value::Object(map) => {
+                let mut members: Vec<(String, serde_json::Value)> = map.into_iter().collect();
+                members.sort_by(|a, b| a.0.cmp(&b.0));
+                serde_json::Value::Object(
+                    members
+                        .into_iter()
+                        .map(|(k, v)| (k, canonicalize(v)))
+                        .collect(),
+                )
+            }
+            serde_json::Value::Array(items) => {
+                serde_json::Value::Array(items.into_iter().map(canonicalize).collect())
+            }
+            value => value,
+        }
+    }
+
+    Ok(serde_json::to_vec(&canonicalize(serde_json::to_value(
+        value,
+    )?))?)
+}
+
 mod serde_support {
     use super::{
         AlsoKnownAsEither, Context, Controller, ServiceEndpointProperties, ServiceEndpoints,
@@ -652,7 +836,7 @@ mod serde_support {
             S: Serializer,
         {
             match &self.0 {
-                Either::Left(url) => serializer.serialize_str(&url.to_string()),
+                Either::Left(url) => serializer.serialize_str(url.as_ref()),
                 Either::Right(properties) => properties.serialize(serializer),
             }
         }
@@ -711,7 +895,7 @@ mod serde_support {
             S: Serializer,
         {
             match &self.0 {
-                Either::Left(url) => serializer.serialize_str(&url.to_string()),
+                Either::Left(url) => serializer.serialize_str(url.as_ref()),
                 Either::Right(set) => set.serialize(serializer),
             }
         }
@@ -0,0 +1,481 @@
+use crate::{
+    did::DID,
+    document::{AlsoKnownAsEither, Document},
+};
+use either::Either;
+use imbl::{HashMap, OrdMap};
+use std::collections::BTreeSet;
+use url::Url;
+
+/// Transitivity determines how far `alsoKnownAs` equivalence reaches. The did-core spec only
+/// describes equivalence between two documents that point at each other (see
+/// <https://www.w3.org/TR/did-core/#also-known-as>), which is [Transitivity::Direct]. Some
+/// deployments wish to treat chains of such pairs as a single subject, which is
+/// [Transitivity::Transitive].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Transitivity {
+    /// Only two documents which name each other in `alsoKnownAs` are equivalent.
+    #[default]
+    Direct,
+    /// Equivalence is closed over bidirectional links: if A and B are equivalent, and B and C are
+    /// equivalent, A and C are too.
+    Transitive,
+}
+
+/// EquivalenceIndex maintains `alsoKnownAs` equivalence classes for a set of [Document]s. It is
+/// kept by the [crate::registry::Registry] and updated incrementally as documents are inserted and
+/// removed, so answering "what is equivalent to this [DID]" is a single map lookup.
+///
+/// Only bidirectional links count; a document claiming to be another without the other agreeing
+/// is ignored. Hypertext [Url]s in `alsoKnownAs` are considered once they have been associated
+/// with a [DID] through [EquivalenceIndex::alias_url], which the registry does when it caches a
+/// remote document.
+///
+/// Classes are assigned union-by-size when links are added. Removing a link may split a class, in
+/// which case only the members of that class are re-walked.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+/// use either::Either;
+///
+/// let alice = DID::parse("did:mymethod:alice").unwrap();
+/// let bob = DID::parse("did:mymethod:bob").unwrap();
+///
+/// let aka = |did: &DID| Some(AlsoKnownAs([AlsoKnownAsEither(Either::Left(did.clone()))].into()));
+///
+/// let mut index = EquivalenceIndex::new(Transitivity::Transitive);
+/// index.insert(&Document{ id: alice.clone(), also_known_as: aka(&bob), ..Default::default() });
+/// index.insert(&Document{ id: bob.clone(), also_known_as: aka(&alice), ..Default::default() });
+///
+/// assert!(index.equivalent(&alice, &bob));
+/// assert_eq!(index.equivalents(&alice).unwrap().len(), 2);
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EquivalenceIndex {
+    policy: Transitivity,
+    // the alsoKnownAs property of each document, as declared
+    declared: OrdMap<DID, BTreeSet<AlsoKnownAsEither>>,
+    // hypertext URLs which have been resolved to a DID
+    aliases: OrdMap<Url, DID>,
+    // documents which name a hypertext URL in alsoKnownAs
+    url_refs: OrdMap<Url, BTreeSet<DID>>,
+    // resolved outgoing links per document
+    links: OrdMap<DID, BTreeSet<DID>>,
+    // links which are reciprocated
+    edges: OrdMap<DID, BTreeSet<DID>>,
+    class_of: HashMap<DID, usize>,
+    classes: HashMap<usize, BTreeSet<DID>>,
+    next_class: usize,
+}
+
+impl EquivalenceIndex {
+    /// Create an empty index with the provided [Transitivity] policy.
+    pub fn new(policy: Transitivity) -> Self {
+        Self {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    /// Retrieve the [Transitivity] policy in use.
+    pub fn policy(&self) -> Transitivity {
+        self.policy
+    }
+
+    /// Change the [Transitivity] policy. Classes are recomputed from the existing links.
+    pub fn set_policy(&mut self, policy: Transitivity) {
+        self.policy = policy;
+        self.class_of.clear();
+        self.classes.clear();
+
+        let dids: Vec<DID> = self.declared.keys().cloned().collect();
+        for did in &dids {
+            self.new_class(did);
+        }
+
+        if self.policy == Transitivity::Transitive {
+            for did in &dids {
+                for other in self.edges.get(did).cloned().unwrap_or_default() {
+                    self.union(did, &other);
+                }
+            }
+        }
+    }
+
+    /// Add a [Document]'s `alsoKnownAs` links to the index. If the document's [DID] was already
+    /// indexed, it is replaced.
+    pub fn insert(&mut self, doc: &Document) {
+        if self.declared.contains_key(&doc.id) {
+            self.remove(&doc.id);
+        }
+
+        let declared: BTreeSet<AlsoKnownAsEither> = doc
+            .also_known_as
+            .as_ref()
+            .map(|aka| aka.0.clone())
+            .unwrap_or_default();
+
+        for item in &declared {
+            if let Either::Right(url) = &item.0 {
+                self.url_refs
+                    .entry(url.clone())
+                    .or_default()
+                    .insert(doc.id.clone());
+            }
+        }
+
+        self.declared.insert(doc.id.clone(), declared);
+        self.new_class(&doc.id);
+        self.link(&doc.id);
+    }
+
+    /// Remove a [DID] from the index, splitting its class if necessary.
+    pub fn remove(&mut self, did: &DID) {
+        if let Some(declared) = self.declared.remove(did) {
+            for item in declared {
+                if let Either::Right(url) = item.0 {
+                    if let Some(refs) = self.url_refs.get_mut(&url) {
+                        refs.remove(did);
+                        if refs.is_empty() {
+                            self.url_refs.remove(&url);
+                        }
+                    }
+                }
+            }
+        } else {
+            return;
+        }
+
+        self.unlink(did);
+
+        if let Some(class) = self.class_of.remove(did) {
+            if let Some(members) = self.classes.remove(&class) {
+                self.split(members.into_iter().filter(|m| m != did).collect());
+            }
+        }
+    }
+
+    /// Associate a hypertext [Url] with the [DID] it resolves to. Documents which name the [Url]
+    /// in `alsoKnownAs` will then be linked to the [DID].
+    pub fn alias_url(&mut self, url: Url, did: DID) {
+        if self.aliases.get(&url) == Some(&did) {
+            return;
+        }
+
+        self.aliases.insert(url.clone(), did);
+
+        for referrer in self.url_refs.get(&url).cloned().unwrap_or_default() {
+            self.unlink(&referrer);
+
+            if let Some(class) = self.class_of.get(&referrer).cloned() {
+                if let Some(members) = self.classes.remove(&class) {
+                    for member in &members {
+                        self.class_of.remove(member);
+                    }
+                    self.split(members);
+                }
+            }
+
+            self.link(&referrer);
+        }
+    }
+
+    /// Determine if two [DID]s are equivalent under the index's [Transitivity] policy. A [DID] is
+    /// always equivalent to itself.
+    pub fn equivalent(&self, did: &DID, other: &DID) -> bool {
+        if did == other {
+            return true;
+        }
+
+        match self.policy {
+            Transitivity::Direct => self
+                .edges
+                .get(did)
+                .map(|edges| edges.contains(other))
+                .unwrap_or(false),
+            Transitivity::Transitive => match (self.class_of.get(did), self.class_of.get(other)) {
+                (Some(a), Some(b)) => a == b,
+                _ => false,
+            },
+        }
+    }
+
+    /// Retrieve all [DID]s equivalent to the provided one, including itself. Returns [None] if the
+    /// [DID] is not indexed.
+    pub fn equivalents(&self, did: &DID) -> Option<BTreeSet<DID>> {
+        if !self.declared.contains_key(did) {
+            return None;
+        }
+
+        match self.policy {
+            Transitivity::Direct => {
+                let mut set = self.edges.get(did).cloned().unwrap_or_default();
+                set.insert(did.clone());
+                Some(set)
+            }
+            Transitivity::Transitive => self
+                .class_of
+                .get(did)
+                .and_then(|class| self.classes.get(class))
+                .cloned(),
+        }
+    }
+
+    /// Iterate over all equivalence classes with more than one member. Under
+    /// [Transitivity::Direct], each reciprocated pair is yielded as its own class.
+    pub fn classes(&self) -> Vec<BTreeSet<DID>> {
+        match self.policy {
+            Transitivity::Direct => {
+                let mut ret = Vec::new();
+                for (did, edges) in &self.edges {
+                    for other in edges.iter().filter(|other| *other > did) {
+                        ret.push([did.clone(), other.clone()].into());
+                    }
+                }
+                ret
+            }
+            Transitivity::Transitive => {
+                let mut ret: Vec<BTreeSet<DID>> = self
+                    .classes
+                    .values()
+                    .filter(|members| members.len() > 1)
+                    .cloned()
+                    .collect();
+                ret.sort();
+                ret
+            }
+        }
+    }
+
+    fn resolve(&self, did: &DID) -> BTreeSet<DID> {
+        let mut set = BTreeSet::new();
+
+        if let Some(declared) = self.declared.get(did) {
+            for item in declared {
+                match &item.0 {
+                    Either::Left(other) => {
+                        set.insert(other.clone());
+                    }
+                    Either::Right(url) => {
+                        if let Some(other) = self.aliases.get(url) {
+                            set.insert(other.clone());
+                        }
+                    }
+                }
+            }
+        }
+
+        set.remove(did);
+        set
+    }
+
+    fn link(&mut self, did: &DID) {
+        let links = self.resolve(did);
+
+        for other in &links {
+            if self
+                .links
+                .get(other)
+                .map(|l| l.contains(did))
+                .unwrap_or(false)
+            {
+                self.edges
+                    .entry(did.clone())
+                    .or_default()
+                    .insert(other.clone());
+                self.edges
+                    .entry(other.clone())
+                    .or_default()
+                    .insert(did.clone());
+
+                if self.policy == Transitivity::Transitive {
+                    self.union(did, other);
+                }
+            }
+        }
+
+        self.links.insert(did.clone(), links);
+    }
+
+    fn unlink(&mut self, did: &DID) {
+        self.links.remove(did);
+
+        if let Some(edges) = self.edges.remove(did) {
+            for other in edges {
+                if let Some(other_edges) = self.edges.get_mut(&other) {
+                    other_edges.remove(did);
+                    if other_edges.is_empty() {
+                        self.edges.remove(&other);
+                    }
+                }
+            }
+        }
+    }
+
+    fn new_class(&mut self, did: &DID) -> usize {
+        let class = self.next_class;
+        self.next_class += 1;
+        self.class_of.insert(did.clone(), class);
+        self.classes.insert(class, [did.clone()].into());
+        class
+    }
+
+    fn union(&mut self, did: &DID, other: &DID) {
+        let (a, b) = match (self.class_of.get(did), self.class_of.get(other)) {
+            (Some(a), Some(b)) if a != b => (*a, *b),
+            _ => return,
+        };
+
+        // merge the smaller class into the larger one
+        let (keep, merge) = if self.classes[&a].len() >= self.classes[&b].len() {
+            (a, b)
+        } else {
+            (b, a)
+        };
+
+        let members = self.classes.remove(&merge).unwrap_or_default();
+        for member in &members {
+            self.class_of.insert(member.clone(), keep);
+        }
+
+        self.classes.entry(keep).or_default().extend(members);
+    }
+
+    // re-walk the edges of a set of formerly-equivalent DIDs and assign them new classes.
+    fn split(&mut self, mut members: BTreeSet<DID>) {
+        while let Some(start) = members.pop_first() {
+            let class = self.new_class(&start);
+
+            if self.policy == Transitivity::Direct {
+                continue;
+            }
+
+            let mut stack = vec![start];
+            while let Some(did) = stack.pop() {
+                for other in self.edges.get(&did).cloned().unwrap_or_default() {
+                    if members.remove(&other) {
+                        self.class_of.insert(other.clone(), class);
+                        self.classes.entry(class).or_default().insert(other.clone());
+                        stack.push(other);
+                    }
+                }
+            }
+        }
+    }
+}
+
+mod tests {
+    // a document naming the provided DIDs in alsoKnownAs
+    #[cfg(test)]
+    fn doc(id: &str, aka: &[&str]) -> crate::document::Document {
+        use crate::{
+            did::DID,
+            document::{AlsoKnownAs, AlsoKnownAsEither, Document},
+        };
+        use either::Either;
+
+        Document {
+            id: DID::parse(id).unwrap(),
+            also_known_as: Some(AlsoKnownAs(
+                aka.iter()
+                    .map(|s| AlsoKnownAsEither(Either::Left(DID::parse(s).unwrap())))
+                    .collect(),
+            )),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_direct() {
+        use super::{EquivalenceIndex, Transitivity};
+        use crate::did::DID;
+
+        let mut index = EquivalenceIndex::new(Transitivity::Direct);
+        index.insert(&doc("did:testing:u:alice", &["did:testing:u:bob"]));
+        index.insert(&doc(
+            "did:testing:u:bob",
+            &["did:testing:u:alice", "did:testing:u:charlie"],
+        ));
+        index.insert(&doc("did:testing:u:charlie", &["did:testing:u:bob"]));
+
+        let alice = DID::parse("did:testing:u:alice").unwrap();
+        let bob = DID::parse("did:testing:u:bob").unwrap();
+        let charlie = DID::parse("did:testing:u:charlie").unwrap();
+
+        assert!(index.equivalent(&alice, &bob));
+        assert!(index.equivalent(&bob, &charlie));
+        assert!(!index.equivalent(&alice, &charlie));
+        assert_eq!(index.equivalents(&bob).unwrap().len(), 3);
+        assert_eq!(index.equivalents(&alice).unwrap().len(), 2);
+        assert_eq!(index.classes().len(), 2);
+    }
+
+    #[test]
+    fn test_transitive() {
+        use super::{EquivalenceIndex, Transitivity};
+        use crate::did::DID;
+
+        let mut index = EquivalenceIndex::new(Transitivity::Transitive);
+        index.insert(&doc("did:testing:u:alice", &["did:testing:u:bob"]));
+        index.insert(&doc(
+            "did:testing:u:bob",
+            &["did:testing:u:alice", "did:testing:u:charlie"],
+        ));
+        index.insert(&doc("did:testing:u:charlie", &["did:testing:u:bob"]));
+        index.insert(&doc("did:testing:u:dave", &["did:testing:u:charlie"]));
+
+        let alice = DID::parse("did:testing:u:alice").unwrap();
+        let bob = DID::parse("did:testing:u:bob").unwrap();
+        let charlie = DID::parse("did:testing:u:charlie").unwrap();
+        let dave = DID::parse("did:testing:u:dave").unwrap();
+
+        assert!(index.equivalent(&alice, &charlie));
+        // dave's link is not reciprocated
+        assert!(!index.equivalent(&alice, &dave));
+        assert_eq!(index.equivalents(&charlie).unwrap().len(), 3);
+        assert_eq!(index.classes().len(), 1);
+
+        index.remove(&bob);
+        assert!(!index.equivalent(&alice, &charlie));
+        assert_eq!(index.equivalents(&alice).unwrap().len(), 1);
+        assert!(index.equivalents(&bob).is_none());
+        assert!(index.classes().is_empty());
+
+        index.insert(&doc(
+            "did:testing:u:bob",
+            &["did:testing:u:alice", "did:testing:u:charlie"],
+        ));
+        assert!(index.equivalent(&alice, &charlie));
+
+        index.set_policy(Transitivity::Direct);
+        assert!(!index.equivalent(&alice, &charlie));
+        index.set_policy(Transitivity::Transitive);
+        assert!(index.equivalent(&alice, &charlie));
+    }
+
+    #[test]
+    fn test_alias_url() {
+        use super::{EquivalenceIndex, Transitivity};
+        use crate::{
+            did::DID,
+            document::{AlsoKnownAs, AlsoKnownAsEither},
+        };
+        use either::Either;
+        use url::Url;
+
+        let url = Url::parse("https://example.org/bob.json").unwrap();
+        let mut alice = doc("did:testing:u:alice", &[]);
+        alice.also_known_as = Some(AlsoKnownAs(
+            [AlsoKnownAsEither(Either::Right(url.clone()))].into(),
+        ));
+
+        let mut index = EquivalenceIndex::new(Transitivity::Transitive);
+        index.insert(&alice);
+        index.insert(&doc("did:testing:u:bob", &["did:testing:u:alice"]));
+
+        let bob = DID::parse("did:testing:u:bob").unwrap();
+        assert!(!index.equivalent(&alice.id, &bob));
+        index.alias_url(url, bob.clone());
+        assert!(index.equivalent(&alice.id, &bob));
+    }
+}
@@ -0,0 +1,320 @@
+use crate::{did::DID, document::Document};
+use std::sync::Arc;
+
+/// EventKind identifies the change to a [crate::registry::Registry] described by an [Event].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EventKind {
+    /// A document was inserted. See [crate::registry::Registry::insert].
+    Inserted,
+    /// A document was replaced. See [crate::registry::Registry::update].
+    Updated,
+    /// A document was removed. See [crate::registry::Registry::remove].
+    Removed,
+    /// A [DID] was deactivated. See [crate::registry::Registry::deactivate].
+    Deactivated,
+    /// The [crate::metadata::Identifiers] asserted for a document were changed; its document
+    /// is unchanged. See [crate::registry::Registry::set_equivalent_ids] and
+    /// [crate::registry::Registry::set_canonical_id].
+    Identified,
+    /// A document was fetched into the remote cache. See
+    /// [crate::registry::Registry::cache_document].
+    RemoteCached,
+}
+
+/// Event describes a change to a [crate::registry::Registry], with the document as it was before
+/// the change and as it is after. `before` is [None] for insertions, and `after` is [None] for
+/// removals and deactivations. Documents are shared with the registry, so events are cheap to
+/// create and keep.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event {
+    pub kind: EventKind,
+    pub id: DID,
+    pub before: Option<Arc<Document>>,
+    pub after: Option<Arc<Document>>,
+}
+
+/// Subscriber receives an [Event] after each change to a [crate::registry::Registry]. Register
+/// one with [crate::registry::Registry::subscribe]. Closures taking an [Event] are subscribers.
+pub trait Subscriber: Send + Sync {
+    fn notify(&self, event: &Event);
+}
+
+impl<F> Subscriber for F
+where
+    F: Fn(&Event) + Send + Sync,
+{
+    fn notify(&self, event: &Event) {
+        self(event)
+    }
+}
+
+/// Hook is consulted with an [Event] before a change is made to a [crate::registry::Registry],
+/// and may veto it by returning an error, which the change then fails with. Register one with
+/// [crate::registry::Registry::add_hook]. Closures taking an [Event] are hooks.
+pub trait Hook: Send + Sync {
+    fn check(&self, event: &Event) -> Result<(), anyhow::Error>;
+}
+
+impl<F> Hook for F
+where
+    F: Fn(&Event) -> Result<(), anyhow::Error> + Send + Sync,
+{
+    fn check(&self, event: &Event) -> Result<(), anyhow::Error> {
+        self(event)
+    }
+}
+
+/// Subscription identifies a [Subscriber] or [Hook] registered with a
+/// [crate::registry::Registry], so that it can be removed again.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Subscription(u64);
+
+/// Observers holds the [Subscriber]s and [Hook]s of a [crate::registry::Registry]. Copies of a
+/// registry carry the ones registered before it was copied.
+#[derive(Clone, Default)]
+pub(crate) struct Observers {
+    next: u64,
+    subscribers: Vec<(Subscription, Arc<dyn Subscriber>)>,
+    hooks: Vec<(Subscription, Arc<dyn Hook>)>,
+    // events held back until a staged copy of the registry is published
+    pub(crate) pending: Option<Vec<Event>>,
+}
+
+impl Observers {
+    fn next(&mut self) -> Subscription {
+        self.next += 1;
+        Subscription(self.next)
+    }
+
+    pub(crate) fn subscribe(&mut self, subscriber: Arc<dyn Subscriber>) -> Subscription {
+        let id = self.next();
+        self.subscribers.push((id, subscriber));
+        id
+    }
+
+    pub(crate) fn add_hook(&mut self, hook: Arc<dyn Hook>) -> Subscription {
+        let id = self.next();
+        self.hooks.push((id, hook));
+        id
+    }
+
+    pub(crate) fn unsubscribe(&mut self, id: Subscription) -> bool {
+        let len = self.subscribers.len() + self.hooks.len();
+        self.subscribers.retain(|(sub, _)| *sub != id);
+        self.hooks.retain(|(sub, _)| *sub != id);
+        len != self.subscribers.len() + self.hooks.len()
+    }
+
+    // consult the hooks about a change, returning the event to emit once it has been made. The
+    // event is only built if anything is listening.
+    pub(crate) fn check(
+        &self,
+        event: impl FnOnce() -> Event,
+    ) -> Result<Option<Event>, anyhow::Error> {
+        if self.subscribers.is_empty() && self.hooks.is_empty() {
+            return Ok(None);
+        }
+
+        let event = event();
+        for (_, hook) in &self.hooks {
+            hook.check(&event)?;
+        }
+
+        Ok(Some(event))
+    }
+
+    pub(crate) fn emit(&mut self, event: Option<Event>) {
+        if let Some(event) = event {
+            match &mut self.pending {
+                Some(pending) => pending.push(event),
+                None => self.notify(&event),
+            }
+        }
+    }
+
+    pub(crate) fn notify(&self, event: &Event) {
+        for (_, subscriber) in &self.subscribers {
+            subscriber.notify(event);
+        }
+    }
+
+    // a copy whose events are held back until they are taken with Observers::take_pending
+    pub(crate) fn deferred(&self) -> Self {
+        Self {
+            pending: Some(Vec::new()),
+            ..self.clone()
+        }
+    }
+
+    pub(crate) fn take_pending(&mut self) -> Vec<Event> {
+        self.pending.take().unwrap_or_default()
+    }
+}
+
+mod tests {
+    // a registry which records its events and turns away mallory, with its subscription and a
+    // function taking the events recorded so far
+    #[cfg(test)]
+    fn fixture() -> (
+        crate::registry::Registry,
+        super::Subscription,
+        impl Fn() -> Vec<super::Event>,
+    ) {
+        use super::Event;
+        use crate::registry::Registry;
+        use std::sync::{Arc, Mutex};
+
+        let events: Arc<Mutex<Vec<Event>>> = Default::default();
+        let seen = events.clone();
+
+        let mut reg = Registry::default();
+        let subscription =
+            reg.subscribe(move |event: &Event| seen.lock().unwrap().push(event.clone()));
+        reg.add_hook(|event: &Event| {
+            if event.id.to_string().ends_with("mallory") {
+                Err(anyhow::anyhow!("mallory is not welcome"))
+            } else {
+                Ok(())
+            }
+        });
+
+        (reg, subscription, move || {
+            std::mem::take(&mut *events.lock().unwrap())
+        })
+    }
+
+    #[cfg(test)]
+    fn doc(name: &str) -> crate::document::Document {
+        use crate::{did::DID, document::Document};
+
+        Document {
+            id: DID::parse(&format!("did:testing:u:{}", name)).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_subscribe() {
+        use super::EventKind;
+
+        let did = |name: &str| doc(name).id;
+        let (mut reg, subscription, take) = fixture();
+
+        reg.insert(doc("alice")).unwrap();
+        let mut updated = doc("alice");
+        updated.also_known_as = Some(Default::default());
+        reg.update(updated.clone()).unwrap();
+        reg.deactivate(&did("alice")).unwrap();
+
+        let got = take();
+        assert_eq!(
+            got.iter().map(|e| e.kind).collect::<Vec<_>>(),
+            vec![
+                EventKind::Inserted,
+                EventKind::Updated,
+                EventKind::Deactivated
+            ]
+        );
+        assert_eq!(got[0].before, None);
+        assert_eq!(got[0].after.as_deref(), Some(&doc("alice")));
+        assert_eq!(got[1].before.as_deref(), Some(&doc("alice")));
+        assert_eq!(got[1].after.as_deref(), Some(&updated));
+        assert_eq!(got[2].before.as_deref(), Some(&updated));
+        assert_eq!(got[2].after, None);
+
+        assert!(reg.unsubscribe(subscription));
+        assert!(!reg.unsubscribe(subscription));
+        reg.insert(doc("bob")).unwrap();
+        assert!(take().is_empty());
+    }
+
+    #[test]
+    fn test_hooks() {
+        use super::{Event, EventKind};
+
+        let did = |name: &str| doc(name).id;
+        let (mut reg, _, take) = fixture();
+
+        // vetoed changes fail, and are neither made nor reported
+        let err = reg.insert(doc("mallory")).unwrap_err();
+        assert!(err.to_string().contains("not welcome"));
+        assert!(reg.get(&did("mallory")).is_none());
+        assert!(take().is_empty());
+
+        let veto = reg.add_hook(|event: &Event| match event.kind {
+            EventKind::Removed => Err(anyhow::anyhow!("erin stays")),
+            _ => Ok(()),
+        });
+        reg.insert(doc("erin")).unwrap();
+        let err = reg.try_remove(&did("erin")).unwrap_err();
+        assert!(err.to_string().contains("erin stays"));
+        assert!(reg.remove(&did("erin")).is_none());
+        assert!(reg.get(&did("erin")).is_some());
+        assert!(reg.unsubscribe(veto));
+        assert_eq!(reg.try_remove(&did("erin")).unwrap(), Some(doc("erin")));
+        assert_eq!(reg.try_remove(&did("erin")).unwrap(), None);
+        assert_eq!(take().len(), 2);
+    }
+
+    #[test]
+    fn test_transactions() {
+        use super::EventKind;
+
+        let did = |name: &str| doc(name).id;
+        let (mut reg, _, take) = fixture();
+
+        // transactions report their changes once committed, and not at all if discarded
+        let mut tx = reg.transaction();
+        tx.insert(doc("bob")).unwrap();
+        assert!(tx.insert(doc("mallory")).is_err());
+        assert!(take().is_empty());
+        tx.commit().unwrap();
+        assert_eq!(
+            take().iter().map(|e| e.kind).collect::<Vec<_>>(),
+            vec![EventKind::Inserted]
+        );
+
+        let mut tx = reg.transaction();
+        tx.remove(&did("bob")).unwrap();
+        tx.rollback();
+        assert!(take().is_empty());
+        assert!(reg.get(&did("bob")).is_some());
+    }
+
+    #[test]
+    fn test_shared() {
+        use super::EventKind;
+        use crate::shared::SharedRegistry;
+
+        let did = |name: &str| doc(name).id;
+        let (mut reg, _, take) = fixture();
+        reg.insert(doc("bob")).unwrap();
+        take();
+
+        // writes to a shared registry report their changes once made, including transactions
+        // within them
+        let shared = SharedRegistry::from(reg);
+        shared
+            .write(|reg| {
+                reg.insert(doc("charlie"))?;
+                reg.transact(|tx| tx.remove(&did("bob")))?;
+                assert!(take().is_empty());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(
+            take().iter().map(|e| e.kind).collect::<Vec<_>>(),
+            vec![EventKind::Inserted, EventKind::Removed]
+        );
+
+        // and not at all if the write fails
+        assert!(shared
+            .write(|reg| {
+                reg.insert(doc("dave"))?;
+                reg.insert(doc("mallory"))
+            })
+            .is_err());
+        assert!(take().is_empty());
+        assert!(shared.get(&did("dave")).is_none());
+    }
+}
@@ -0,0 +1,385 @@
+use crate::{
+    document::Document,
+    metadata::{Identifiers, StoredMetadata},
+    registry::{Registry, Version},
+    time::VersionTime,
+    tombstone::Tombstone,
+};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader, Read, Write};
+
+/// The name of the manifest entry in an [ExportFormat::Archive].
+pub const MANIFEST_PATH: &str = "manifest.json";
+
+const MANIFEST_FORMAT: &str = "did-toolkit-registry";
+const MANIFEST_VERSION: u32 = 1;
+
+/// ExportFormat selects how [Registry::export] writes, and [Registry::import] reads, a registry.
+/// Each format is a stream of [Record]s, one per document or [Tombstone], so registries of any
+/// size are written and read a record at a time.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ExportFormat {
+    /// [JSON Lines](https://jsonlines.org): one JSON-encoded [Record] per line.
+    JsonLines,
+    /// A CBOR sequence, as described in [RFC 8742](https://www.rfc-editor.org/rfc/rfc8742): CBOR
+    /// encoded [Record]s, concatenated.
+    CborSequence,
+    /// A tar archive. The first entry is the [Manifest], named [MANIFEST_PATH], followed by one
+    /// JSON-encoded [Record] per entry under `records/`.
+    Archive,
+}
+
+/// Record is a single entry in an export: a document with its [Version], the times it was
+/// created and last updated and the [Identifiers] asserted for it, or a [Tombstone]. They are tagged by a `type` property, e.g.
+/// `{"type":"document","version":1,"document":{...}}`.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum Record {
+    Document {
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        version: Option<Version>,
+        document: Box<Document>,
+        /// When the document was created. Documents without one are created when imported.
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        created: Option<VersionTime>,
+        #[serde(skip_serializing_if = "Option::is_none", default)]
+        updated: Option<VersionTime>,
+        #[serde(skip_serializing_if = "Identifiers::is_empty", default)]
+        identifiers: Identifiers,
+    },
+    Tombstone(Tombstone),
+}
+
+/// Manifest describes the contents of an [ExportFormat::Archive]. Importing an archive fails if
+/// its contents do not match the manifest.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Always `did-toolkit-registry`.
+    pub format: String,
+    /// The version of the archive layout; currently `1`.
+    pub version: u32,
+    /// The number of document records in the archive.
+    pub documents: usize,
+    /// The number of tombstone records in the archive.
+    pub tombstones: usize,
+}
+
+impl Manifest {
+    fn new(reg: &Registry) -> Self {
+        Self {
+            format: MANIFEST_FORMAT.to_string(),
+            version: MANIFEST_VERSION,
+            documents: reg.len(),
+            tombstones: reg.tombstones().count(),
+        }
+    }
+
+    fn check(&self) -> Result<(), anyhow::Error> {
+        if self.format != MANIFEST_FORMAT {
+            return Err(anyhow!("Archive has unknown format {}", self.format));
+        }
+
+        if self.version != MANIFEST_VERSION {
+            return Err(anyhow!("Archive has unsupported version {}", self.version));
+        }
+
+        Ok(())
+    }
+}
+
+// documents are written in order, followed by tombstones
+fn records(reg: &Registry) -> impl Iterator<Item = Record> + '_ {
+    reg.iter()
+        .map(|(did, doc)| {
+            let metadata = reg.document_metadata(did).unwrap_or_default();
+            Record::Document {
+                version: reg.version(did),
+                document: Box::new(doc.clone()),
+                created: metadata.created,
+                updated: metadata.updated,
+                identifiers: reg.identifiers(did).cloned().unwrap_or_default(),
+            }
+        })
+        .chain(reg.tombstones().cloned().map(Record::Tombstone))
+}
+
+fn restore(reg: &mut Registry, record: Record, keep_versions: bool) -> Result<(), anyhow::Error> {
+    match record {
+        Record::Document {
+            version,
+            document,
+            created,
+            updated,
+            identifiers,
+        } => reg.restore(
+            *document,
+            version.filter(|_| keep_versions),
+            StoredMetadata {
+                created: created.unwrap_or_else(VersionTime::now),
+                updated,
+                identifiers,
+            },
+        ),
+        Record::Tombstone(tombstone) => reg.insert_tombstone(tombstone),
+    }
+}
+
+pub(crate) fn export<W: Write>(
+    reg: &Registry,
+    format: ExportFormat,
+    mut w: W,
+) -> Result<(), anyhow::Error> {
+    match format {
+        ExportFormat::JsonLines => {
+            for record in records(reg) {
+                serde_json::to_writer(&mut w, &record)?;
+                w.write_all(b"\n")?;
+            }
+        }
+        ExportFormat::CborSequence => {
+            for record in records(reg) {
+                ciborium::ser::into_writer(&record, &mut w)?;
+            }
+        }
+        ExportFormat::Archive => {
+            let mut builder = tar::Builder::new(w);
+            append(&mut builder, MANIFEST_PATH, &Manifest::new(reg))?;
+            for (num, record) in records(reg).enumerate() {
+                append(&mut builder, &format!("records/{}.json", num), &record)?;
+            }
+
+            w = builder.into_inner()?;
+        }
+    }
+
+    w.flush()?;
+    Ok(())
+}
+
+fn append<W: Write, T: Serialize>(
+    builder: &mut tar::Builder<W>,
+    path: &str,
+    item: &T,
+) -> Result<(), anyhow::Error> {
+    let data = serde_json::to_vec(item)?;
+    let mut header = tar::Header::new_gnu();
+    header.set_size(data.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, path, data.as_slice())?;
+    Ok(())
+}
+
+pub(crate) fn import<R: Read>(
+    reg: &mut Registry,
+    format: ExportFormat,
+    r: R,
+    keep_versions: bool,
+) -> Result<usize, anyhow::Error> {
+    let mut count = 0;
+
+    match format {
+        ExportFormat::JsonLines => {
+            for (num, line) in BufReader::new(r).lines().enumerate() {
+                let line = line?;
+                if line.trim().is_empty() {
+                    continue;
+                }
+
+                let record = serde_json::from_str(&line)
+                    .map_err(|e| anyhow!("Invalid record on line {}: {}", num + 1, e))?;
+                restore(reg, record, keep_versions)?;
+                count += 1;
+            }
+        }
+        ExportFormat::CborSequence => {
+            let mut r = BufReader::new(r);
+            while !r.fill_buf()?.is_empty() {
+                let record = ciborium::de::from_reader(&mut r)
+                    .map_err(|e| anyhow!("Invalid record {}: {}", count, e))?;
+                restore(reg, record, keep_versions)?;
+                count += 1;
+            }
+        }
+        ExportFormat::Archive => {
+            let mut archive = tar::Archive::new(r);
+            let mut manifest: Option<Manifest> = None;
+            let (mut documents, mut tombstones) = (0, 0);
+
+            for entry in archive.entries()? {
+                let entry = entry?;
+                let path = entry.path()?.to_string_lossy().to_string();
+
+                if manifest.is_none() {
+                    if path != MANIFEST_PATH {
+                        return Err(anyhow!("Archive does not begin with {}", MANIFEST_PATH));
+                    }
+
+                    let m: Manifest = serde_json::from_reader(entry)?;
+                    m.check()?;
+                    manifest = Some(m);
+                    continue;
+                }
+
+                if !path.starts_with("records/") {
+                    return Err(anyhow!("Unexpected entry {} in archive", path));
+                }
+
+                let record: Record = serde_json::from_reader(entry)
+                    .map_err(|e| anyhow!("Invalid record {}: {}", path, e))?;
+                match record {
+                    Record::Document { .. } => documents += 1,
+                    Record::Tombstone(_) => tombstones += 1,
+                }
+
+                restore(reg, record, keep_versions)?;
+                count += 1;
+            }
+
+            let manifest = manifest.ok_or(anyhow!("Archive has no {}", MANIFEST_PATH))?;
+            if manifest.documents != documents || manifest.tombstones != tombstones {
+                return Err(anyhow!(
+                    "Archive holds {} documents and {} tombstones, but its manifest lists {} and {}",
+                    documents,
+                    tombstones,
+                    manifest.documents,
+                    manifest.tombstones
+                ));
+            }
+        }
+    }
+
+    Ok(count)
+}
+
+mod tests {
+    // a registry holding the documents of alice, bob and charlie, each controlling itself and
+    // carrying a verification method
+    #[cfg(test)]
+    fn fixture() -> crate::registry::Registry {
+        use crate::{
+            did::DID,
+            document::{Controller, Document, VerificationMethod},
+            registry::Registry,
+            url::URL,
+        };
+        use either::Either;
+
+        let mut reg = Registry::default();
+        for name in ["alice", "bob", "charlie"] {
+            let did = DID::parse(&format!("did:testing:u:{}", name)).unwrap();
+            reg.insert(Document {
+                id: did.clone(),
+                controller: Some(Controller(Either::Left(did.clone()))),
+                verification_method: Some(
+                    [VerificationMethod {
+                        id: URL::parse(&format!("{}#key-1", did)).unwrap(),
+                        controller: did.clone(),
+                        ..Default::default()
+                    }]
+                    .into(),
+                ),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        reg
+    }
+
+    #[test]
+    fn test_round_trip() {
+        use super::ExportFormat;
+        use crate::{
+            did::DID,
+            document::{Controller, Document},
+            registry::Registry,
+        };
+        use either::Either;
+
+        let mut reg = fixture();
+
+        let bob = DID::parse("did:testing:u:bob").unwrap();
+        reg.update(reg[&bob].clone()).unwrap();
+        let alice = DID::parse("did:testing:u:alice").unwrap();
+        reg.update(Document {
+            controller: Some(Controller(Either::Left(bob.clone()))),
+            ..reg[&alice].clone()
+        })
+        .unwrap();
+        let charlie = DID::parse("did:testing:u:charlie").unwrap();
+        reg.deactivate(&charlie).unwrap();
+
+        for format in [
+            ExportFormat::JsonLines,
+            ExportFormat::CborSequence,
+            ExportFormat::Archive,
+        ] {
+            let mut buf = Vec::new();
+            reg.export(format, &mut buf).unwrap();
+
+            let mut imported = Registry::default();
+            assert_eq!(imported.import(format, buf.as_slice()).unwrap(), 3);
+            assert_eq!(imported.len(), 2);
+            for (did, doc) in reg.iter() {
+                assert_eq!(&imported[did], doc);
+                assert_eq!(imported.version(did), reg.version(did));
+                assert_eq!(imported.document_metadata(did), reg.document_metadata(did));
+            }
+            assert_eq!(imported.tombstone(&charlie), reg.tombstone(&charlie));
+        }
+    }
+
+    #[test]
+    fn test_import_failures() {
+        use super::ExportFormat;
+        use crate::registry::Registry;
+
+        let reg = fixture();
+
+        for format in [
+            ExportFormat::JsonLines,
+            ExportFormat::CborSequence,
+            ExportFormat::Archive,
+        ] {
+            let mut buf = Vec::new();
+            reg.export(format, &mut buf).unwrap();
+
+            // importing again fails, as the documents already exist, and changes nothing
+            let mut imported = Registry::default();
+            imported.import(format, buf.as_slice()).unwrap();
+            assert!(imported.import(format, buf.as_slice()).is_err());
+            assert_eq!(imported.len(), 3);
+
+            // truncated input fails, and nothing is imported
+            let mut imported = Registry::default();
+            assert!(imported.import(format, &buf[..buf.len() / 2]).is_err());
+            assert!(imported.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_import_times() {
+        use super::ExportFormat;
+        use crate::{did::DID, registry::Registry};
+
+        // documents keep the times they were created and updated
+        let record = r#"{"type":"document","document":{"id":"did:testing:u:dave"},"created":"2020-01-01T00:00:00Z","updated":"2021-06-01T12:30:00.25Z"}"#;
+        let mut imported = Registry::default();
+        imported
+            .import(ExportFormat::JsonLines, record.as_bytes())
+            .unwrap();
+        let metadata = imported
+            .document_metadata(&DID::parse("did:testing:u:dave").unwrap())
+            .unwrap();
+        assert_eq!(
+            metadata.created.unwrap().to_string(),
+            "2020-01-01T00:00:00Z"
+        );
+        assert_eq!(
+            metadata.updated.unwrap().to_string(),
+            "2021-06-01T12:30:00.25Z"
+        );
+    }
+}
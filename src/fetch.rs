@@ -0,0 +1,567 @@
+use crate::{did::DID, document::Document};
+use anyhow::anyhow;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    io::Read,
+    net::{IpAddr, Ipv4Addr, SocketAddr, ToSocketAddrs},
+    time::Duration,
+};
+use url::{Host, Url};
+
+/// FetchPolicy constrains where and how remote documents may be fetched by a [Fetcher]. The
+/// default policy is conservative: only `https` is allowed, private and otherwise non-global
+/// addresses are refused, bodies are limited to 1MiB, and requests time out after 10 seconds.
+///
+/// Host lists match the host portion of the [Url] exactly, or any subdomain of an entry when the
+/// entry starts with a `.`, e.g. `.example.org`, ignoring case and any trailing `.`. The deny list
+/// takes precedence over the allow list. If the allow list is [None], all hosts not denied are
+/// allowed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FetchPolicy {
+    pub https_only: bool,
+    pub allow_hosts: Option<BTreeSet<String>>,
+    pub deny_hosts: BTreeSet<String>,
+    pub block_private: bool,
+    pub max_body_size: usize,
+    pub timeout: Duration,
+    pub connect_timeout: Duration,
+    pub max_redirects: usize,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        Self {
+            https_only: true,
+            allow_hosts: None,
+            deny_hosts: BTreeSet::new(),
+            block_private: true,
+            max_body_size: 1024 * 1024,
+            timeout: Duration::from_secs(10),
+            connect_timeout: Duration::from_secs(5),
+            max_redirects: 5,
+        }
+    }
+}
+
+// hosts are compared in lower case and without a trailing dot, which name the same host. The
+// host is expected to be normalized already.
+fn host_matches(list: &BTreeSet<String>, host: &str) -> bool {
+    list.iter()
+        .map(|entry| entry.trim_end_matches('.').to_ascii_lowercase())
+        .any(|entry| match entry.strip_prefix('.') {
+            Some(domain) => host == domain || host.ends_with(&entry),
+            None => host == entry,
+        })
+}
+
+impl FetchPolicy {
+    /// Check a [Url] against the policy without performing any network activity. Hosts which are
+    /// IP addresses are checked against the private address rules here; names are checked by the
+    /// [Transport] once resolved, see [FetchPolicy::check_addr].
+    pub fn check_url(&self, url: &Url) -> Result<(), anyhow::Error> {
+        match url.scheme() {
+            "https" => {}
+            "http" if !self.https_only => {}
+            scheme => return Err(anyhow!("URL scheme {} is not allowed by policy", scheme)),
+        }
+
+        let host = match url.host() {
+            Some(host) => host,
+            None => return Err(anyhow!("URL {} has no host", url)),
+        };
+
+        let name = match &host {
+            Host::Domain(domain) => domain.trim_end_matches('.').to_ascii_lowercase(),
+            Host::Ipv4(ip) => ip.to_string(),
+            Host::Ipv6(ip) => ip.to_string(),
+        };
+
+        if host_matches(&self.deny_hosts, &name) {
+            return Err(anyhow!("Host {} is denied by policy", name));
+        }
+
+        if let Some(allow) = &self.allow_hosts {
+            if !host_matches(allow, &name) {
+                return Err(anyhow!("Host {} is not allowed by policy", name));
+            }
+        }
+
+        match host {
+            Host::Ipv4(ip) => self.check_addr(&IpAddr::V4(ip)),
+            Host::Ipv6(ip) => self.check_addr(&IpAddr::V6(ip)),
+            Host::Domain(_) => Ok(()),
+        }
+    }
+
+    /// Check a resolved address against the policy.
+    pub fn check_addr(&self, addr: &IpAddr) -> Result<(), anyhow::Error> {
+        if self.block_private && !is_global(addr) {
+            return Err(anyhow!("Address {} is not allowed by policy", addr));
+        }
+
+        Ok(())
+    }
+}
+
+// std's Ipv4Addr::is_global and friends are not yet stable, so this approximates them.
+fn is_global(addr: &IpAddr) -> bool {
+    match addr {
+        IpAddr::V4(ip) => {
+            let octets = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // shared address space, RFC 6598
+                || (octets[0] == 100 && (octets[1] & 0xc0) == 64)
+                // IETF protocol assignments, RFC 6890
+                || (octets[0] == 192 && octets[1] == 0 && octets[2] == 0)
+                // benchmarking, RFC 2544
+                || (octets[0] == 198 && (octets[1] & 0xfe) == 18)
+                // reserved
+                || octets[0] >= 240
+                || octets[0] == 0)
+        }
+        IpAddr::V6(ip) => {
+            if let Some(v4) = ip.to_ipv4_mapped() {
+                return is_global(&IpAddr::V4(v4));
+            }
+
+            let segments = ip.segments();
+            let octets = ip.octets();
+            let embedded = |at: usize| {
+                Ipv4Addr::new(octets[at], octets[at + 1], octets[at + 2], octets[at + 3])
+            };
+
+            // transition addresses reach whatever IPv4 address they embed: 6to4, 2002::/16
+            if segments[0] == 0x2002 && !is_global(&IpAddr::V4(embedded(2))) {
+                return false;
+            }
+
+            // NAT64, 64:ff9b::/96
+            if segments[..6] == [0x64, 0xff9b, 0, 0, 0, 0] {
+                return is_global(&IpAddr::V4(embedded(12)));
+            }
+
+            // IPv4-compatible, ::a.b.c.d, which is deprecated; :: and ::1 are caught below
+            if segments[..6] == [0; 6] && !(ip.is_loopback() || ip.is_unspecified()) {
+                return is_global(&IpAddr::V4(embedded(12)));
+            }
+
+            !(ip.is_loopback()
+                || ip.is_unspecified()
+                || ip.is_multicast()
+                // unique local, fc00::/7
+                || (segments[0] & 0xfe00) == 0xfc00
+                // link local, fe80::/10
+                || (segments[0] & 0xffc0) == 0xfe80
+                // documentation, 2001:db8::/32
+                || (segments[0] == 0x2001 && segments[1] == 0xdb8)
+                // Teredo, 2001::/32, which tunnels to an obfuscated IPv4 address
+                || (segments[0] == 0x2001 && segments[1] == 0))
+        }
+    }
+}
+
+pub(crate) fn too_large(url: &Url, policy: &FetchPolicy) -> anyhow::Error {
+    anyhow!(
+        "Response from {} exceeds maximum size of {} bytes",
+        url,
+        policy.max_body_size
+    )
+}
+
+pub(crate) fn response_headers(map: &reqwest::header::HeaderMap) -> BTreeMap<String, String> {
+    let mut headers = BTreeMap::new();
+    for (key, value) in map {
+        if let Ok(value) = value.to_str() {
+            headers.insert(key.as_str().to_ascii_lowercase(), value.to_string());
+        }
+    }
+
+    headers
+}
+
+/// A request made through a [Transport]. Header names are lower-case.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FetchRequest {
+    pub url: Url,
+    pub headers: BTreeMap<String, String>,
+}
+
+impl FetchRequest {
+    /// Create a plain GET request for the [Url].
+    pub fn new(url: Url) -> Self {
+        Self {
+            url,
+            headers: BTreeMap::new(),
+        }
+    }
+}
+
+/// A response returned by a [Transport]. Header names are lower-case.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct FetchResponse {
+    pub status: u16,
+    pub headers: BTreeMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+/// Transport performs HTTP requests on behalf of a [Fetcher]. Implementations are responsible for
+/// enforcing the network-level portions of the [FetchPolicy]: resolved addresses, timeouts,
+/// redirects and the body size limit. [ReqwestTransport] is used by default; [StaticTransport] is
+/// provided as a local stand-in for tests.
+pub trait Transport: Send + Sync {
+    fn fetch(
+        &self,
+        request: &FetchRequest,
+        policy: &FetchPolicy,
+    ) -> Result<FetchResponse, anyhow::Error>;
+}
+
+/// The default [Transport], built on [reqwest]'s blocking client. Redirects are followed by hand
+/// so that each hop is checked against the [FetchPolicy], and connections are pinned to the
+/// addresses which were checked, so a second DNS lookup cannot point the request elsewhere.
+#[derive(Clone, Debug, Default)]
+pub struct ReqwestTransport;
+
+impl Transport for ReqwestTransport {
+    fn fetch(
+        &self,
+        request: &FetchRequest,
+        policy: &FetchPolicy,
+    ) -> Result<FetchResponse, anyhow::Error> {
+        let mut url = request.url.clone();
+
+        for _ in 0..=policy.max_redirects {
+            policy.check_url(&url)?;
+
+            // a proxy would resolve the host itself, defeating the pinning below
+            let mut builder = reqwest::blocking::Client::builder()
+                .no_proxy()
+                .redirect(reqwest::redirect::Policy::none())
+                .timeout(policy.timeout)
+                .connect_timeout(policy.connect_timeout);
+
+            if let Some(Host::Domain(domain)) = url.host() {
+                let port = url.port_or_known_default().unwrap_or(443);
+                let addrs: Vec<SocketAddr> = (domain, port).to_socket_addrs()?.collect();
+                if addrs.is_empty() {
+                    return Err(anyhow!("Host {} did not resolve", domain));
+                }
+
+                for addr in &addrs {
+                    policy.check_addr(&addr.ip())?;
+                }
+
+                builder = builder.resolve_to_addrs(domain, &addrs);
+            }
+
+            let mut req = builder.build()?.get(url.clone());
+            for (key, value) in &request.headers {
+                req = req.header(key, value);
+            }
+
+            let mut resp = req.send()?;
+
+            if resp.status().is_redirection() {
+                if let Some(location) = resp.headers().get(reqwest::header::LOCATION) {
+                    url = url.join(location.to_str()?)?;
+                    continue;
+                }
+            }
+
+            if let Some(len) = resp.content_length() {
+                if len as usize > policy.max_body_size {
+                    return Err(too_large(&url, policy));
+                }
+            }
+
+            let headers = response_headers(resp.headers());
+            let status = resp.status().as_u16();
+            let mut body = Vec::new();
+            resp.by_ref()
+                .take(policy.max_body_size as u64 + 1)
+                .read_to_end(&mut body)?;
+
+            if body.len() > policy.max_body_size {
+                return Err(too_large(&url, policy));
+            }
+
+            return Ok(FetchResponse {
+                status,
+                headers,
+                body,
+            });
+        }
+
+        Err(anyhow!(
+            "Too many redirects fetching {}; limit is {}",
+            request.url,
+            policy.max_redirects
+        ))
+    }
+}
+
+/// A [Transport] which serves canned responses from memory, keyed by [Url]. Requests for any
+/// other [Url] fail. The body size limit of the [FetchPolicy] is still enforced.
+#[derive(Clone, Debug, Default)]
+pub struct StaticTransport {
+    pub responses: BTreeMap<Url, FetchResponse>,
+}
+
+impl StaticTransport {
+    /// Serve a JSON-encoded [Document] at the [Url] with a `200 OK` status.
+    pub fn insert_document(&mut self, url: Url, doc: &Document) -> Result<(), anyhow::Error> {
+        self.responses.insert(
+            url,
+            FetchResponse {
+                status: 200,
+                headers: [(
+                    "content-type".to_string(),
+                    "application/did+json".to_string(),
+                )]
+                .into(),
+                body: serde_json::to_vec(doc)?,
+            },
+        );
+
+        Ok(())
+    }
+}
+
+impl Transport for StaticTransport {
+    fn fetch(
+        &self,
+        request: &FetchRequest,
+        policy: &FetchPolicy,
+    ) -> Result<FetchResponse, anyhow::Error> {
+        match self.responses.get(&request.url) {
+            Some(resp) => {
+                if resp.body.len() > policy.max_body_size {
+                    return Err(too_large(&request.url, policy));
+                }
+
+                Ok(resp.clone())
+            }
+            None => Err(anyhow!("No response for {}", request.url)),
+        }
+    }
+}
+
+/// Fetcher retrieves remote [Document]s on behalf of a [crate::registry::Registry], according to
+/// a [FetchPolicy] and using a [Transport].
+///
+/// ```
+/// use did_toolkit::prelude::*;
+/// use url::Url;
+///
+/// let did = DID::parse("did:mymethod:alice").unwrap();
+/// let url = Url::parse("https://example.org/alice.json").unwrap();
+///
+/// let mut transport = StaticTransport::default();
+/// transport.insert_document(url.clone(), &Document{ id: did.clone(), ..Default::default() }).unwrap();
+///
+/// let fetcher = Fetcher::with_transport(FetchPolicy::default(), Box::new(transport));
+/// assert!(fetcher.fetch_document(&url, Some(&did)).is_ok());
+/// assert!(fetcher.fetch_document(&url, Some(&DID::parse("did:mymethod:bob").unwrap())).is_err());
+///
+/// let url = Url::parse("http://example.org/alice.json").unwrap();
+/// assert!(fetcher.fetch_document(&url, None).is_err());
+/// ```
+pub struct Fetcher {
+    policy: FetchPolicy,
+    transport: Box<dyn Transport>,
+}
+
+impl Default for Fetcher {
+    fn default() -> Self {
+        Self::new(FetchPolicy::default())
+    }
+}
+
+impl std::fmt::Debug for Fetcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Fetcher")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Fetcher {
+    /// Create a [Fetcher] using the [ReqwestTransport].
+    pub fn new(policy: FetchPolicy) -> Self {
+        Self::with_transport(policy, Box::new(ReqwestTransport))
+    }
+
+    /// Create a [Fetcher] using the provided [Transport].
+    pub fn with_transport(policy: FetchPolicy, transport: Box<dyn Transport>) -> Self {
+        Self { policy, transport }
+    }
+
+    /// Retrieve the [FetchPolicy] in use.
+    pub fn policy(&self) -> &FetchPolicy {
+        &self.policy
+    }
+
+    /// Perform a request through the [Transport], after checking it against the [FetchPolicy].
+    pub fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse, anyhow::Error> {
+        self.policy.check_url(&request.url)?;
+        self.transport.fetch(request, &self.policy)
+    }
+
+    /// Fetch a JSON [Document] from the [Url]. If a [DID] is expected, the document's `id` must
+    /// match it.
+    pub fn fetch_document(
+        &self,
+        url: &Url,
+        expected: Option<&DID>,
+    ) -> Result<Document, anyhow::Error> {
+        let resp = self.fetch(&FetchRequest::new(url.clone()))?;
+        Self::parse_document(url, &resp, expected)
+    }
+
+    pub(crate) fn parse_document(
+        url: &Url,
+        resp: &FetchResponse,
+        expected: Option<&DID>,
+    ) -> Result<Document, anyhow::Error> {
+        if !(200..300).contains(&resp.status) {
+            return Err(anyhow!(
+                "Fetching {} returned HTTP status {}",
+                url,
+                resp.status
+            ));
+        }
+
+        let doc: Document = serde_json::from_slice(&resp.body)?;
+
+        if let Some(expected) = expected {
+            if &doc.id != expected {
+                return Err(anyhow!(
+                    "Document fetched from {} has id {}, expected {}",
+                    url,
+                    doc.id,
+                    expected
+                ));
+            }
+        }
+
+        Ok(doc)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_check_url() {
+        use super::FetchPolicy;
+        use url::Url;
+
+        let policy = FetchPolicy::default();
+        let check = |policy: &FetchPolicy, s: &str| policy.check_url(&Url::parse(s).unwrap());
+
+        assert!(check(&policy, "https://example.org/").is_ok());
+        assert!(check(&policy, "http://example.org/").is_err());
+        assert!(check(&policy, "file:///etc/passwd").is_err());
+        assert!(check(&policy, "https://127.0.0.1/").is_err());
+        assert!(check(&policy, "https://10.1.2.3/").is_err());
+        assert!(check(&policy, "https://169.254.169.254/").is_err());
+        assert!(check(&policy, "https://100.64.0.1/").is_err());
+        assert!(check(&policy, "https://[::1]/").is_err());
+        assert!(check(&policy, "https://[fd00::1]/").is_err());
+        assert!(check(&policy, "https://[::ffff:192.168.0.1]/").is_err());
+        assert!(check(&policy, "https://[2002:c0a8:1::1]/").is_err());
+        assert!(check(&policy, "https://[64:ff9b::a9fe:a9fe]/").is_err());
+        assert!(check(&policy, "https://[::7f00:1]/").is_err());
+        assert!(check(&policy, "https://[2001:0:4136:e378:8000:63bf:3fff:fdd2]/").is_err());
+        assert!(check(&policy, "https://8.8.8.8/").is_ok());
+        assert!(check(&policy, "https://[2002:808:808::1]/").is_ok());
+        assert!(check(&policy, "https://[64:ff9b::808:808]/").is_ok());
+
+        let policy = FetchPolicy {
+            https_only: false,
+            block_private: false,
+            allow_hosts: Some([".example.org".to_string(), "127.0.0.1".to_string()].into()),
+            deny_hosts: ["bad.example.org".to_string()].into(),
+            ..Default::default()
+        };
+
+        assert!(check(&policy, "http://example.org/").is_ok());
+        assert!(check(&policy, "http://www.example.org/").is_ok());
+        assert!(check(&policy, "http://bad.example.org/").is_err());
+        assert!(check(&policy, "http://bad.example.org./").is_err());
+        assert!(check(&policy, "http://www.example.org./").is_ok());
+        assert!(check(&policy, "http://example.com./").is_err());
+        assert!(check(&policy, "http://badexample.org/").is_err());
+        assert!(check(&policy, "http://example.com/").is_err());
+        assert!(check(&policy, "http://127.0.0.1/").is_ok());
+
+        // entries match whatever their case
+        let policy = FetchPolicy {
+            allow_hosts: Some([".Example.ORG.".to_string()].into()),
+            deny_hosts: ["Bad.Example.org".to_string()].into(),
+            ..Default::default()
+        };
+
+        assert!(check(&policy, "https://www.example.org/").is_ok());
+        assert!(check(&policy, "https://WWW.EXAMPLE.ORG/").is_ok());
+        assert!(check(&policy, "https://bad.example.org/").is_err());
+        assert!(check(&policy, "https://BAD.example.org./").is_err());
+    }
+
+    #[test]
+    fn test_fetch_document() {
+        use super::{FetchPolicy, FetchResponse, Fetcher, StaticTransport};
+        use crate::{did::DID, document::Document};
+        use url::Url;
+
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let url = Url::parse("https://example.org/alice.json").unwrap();
+        let missing = Url::parse("https://example.org/missing.json").unwrap();
+        let large = Url::parse("https://example.org/large.json").unwrap();
+
+        let mut transport = StaticTransport::default();
+        transport
+            .insert_document(
+                url.clone(),
+                &Document {
+                    id: did.clone(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+        transport.responses.insert(
+            missing.clone(),
+            FetchResponse {
+                status: 404,
+                ..Default::default()
+            },
+        );
+        transport.responses.insert(
+            large.clone(),
+            FetchResponse {
+                status: 200,
+                body: vec![b' '; 2048],
+                ..Default::default()
+            },
+        );
+
+        let fetcher = Fetcher::with_transport(
+            FetchPolicy {
+                max_body_size: 1024,
+                ..Default::default()
+            },
+            Box::new(transport),
+        );
+
+        assert_eq!(fetcher.fetch_document(&url, Some(&did)).unwrap().id, did);
+        assert!(fetcher
+            .fetch_document(&url, Some(&DID::parse("did:testing:u:bob").unwrap()))
+            .is_err());
+        assert!(fetcher.fetch_document(&missing, None).is_err());
+        assert!(fetcher.fetch_document(&large, None).is_err());
+    }
+}
@@ -0,0 +1,201 @@
+use anyhow::anyhow;
+use sha2::{Digest, Sha256, Sha512};
+use std::fmt::Display;
+
+/// HashAlgorithm is a hash function which a [HashLink] may use, identified by its
+/// [multihash](https://github.com/multiformats/multihash) code.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HashAlgorithm {
+    /// SHA-256, multihash code `0x12`.
+    #[default]
+    Sha256,
+    /// SHA-512, multihash code `0x13`.
+    Sha512,
+}
+
+impl HashAlgorithm {
+    /// The multihash code of the algorithm.
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Sha256 => 0x12,
+            Self::Sha512 => 0x13,
+        }
+    }
+
+    /// The length of the digests the algorithm produces, in bytes.
+    pub fn digest_len(&self) -> usize {
+        match self {
+            Self::Sha256 => 32,
+            Self::Sha512 => 64,
+        }
+    }
+
+    fn from_code(code: u8) -> Result<Self, anyhow::Error> {
+        match code {
+            0x12 => Ok(Self::Sha256),
+            0x13 => Ok(Self::Sha512),
+            code => Err(anyhow!("Multihash code {:#x} is not supported", code)),
+        }
+    }
+
+    fn digest(&self, content: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Sha256 => Sha256::digest(content).to_vec(),
+            Self::Sha512 => Sha512::digest(content).to_vec(),
+        }
+    }
+}
+
+/// HashLink is the resource hash of a [Hashlink](https://datatracker.ietf.org/doc/html/draft-sporny-hashlink),
+/// as carried by the `hl` parameter of a DID [crate::url::URL] to pin the content it
+/// dereferences to: a [multihash](https://github.com/multiformats/multihash) of the content,
+/// encoded with [multibase]. Encoding uses base58btc, as the specification recommends; any
+/// multibase encoding is accepted when decoding.
+///
+/// [crate::registry::Registry::dereference] checks the `hl` parameter of the [crate::url::URL]
+/// against the canonical form of what it dereferences to: [crate::document::Document::canonical_json]
+/// for documents, and the content of [crate::resource::Resource]s.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let hl = HashLink::new(b"Hello World!");
+/// assert_eq!(hl.to_string(), "zQmWvQxTqbG2Z9HPJgG57jjwR154cKhbtJenbyYTWkjgF3e");
+///
+/// let parsed: HashLink = "zQmWvQxTqbG2Z9HPJgG57jjwR154cKhbtJenbyYTWkjgF3e".parse().unwrap();
+/// assert!(parsed.verify(b"Hello World!").is_ok());
+/// assert!(parsed.verify(b"Goodbye World!").is_err());
+/// ```
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct HashLink {
+    pub algorithm: HashAlgorithm,
+    pub digest: Vec<u8>,
+}
+
+impl HashLink {
+    /// Compute the [HashLink] of the content with SHA-256.
+    pub fn new(content: &[u8]) -> Self {
+        Self::with_algorithm(HashAlgorithm::default(), content)
+    }
+
+    /// Compute the [HashLink] of the content with the provided [HashAlgorithm].
+    pub fn with_algorithm(algorithm: HashAlgorithm, content: &[u8]) -> Self {
+        Self {
+            algorithm,
+            digest: algorithm.digest(content),
+        }
+    }
+
+    /// The multihash of the content: the code of the algorithm, the length of the digest and the
+    /// digest.
+    pub fn multihash(&self) -> Vec<u8> {
+        let mut multihash = vec![self.algorithm.code(), self.digest.len() as u8];
+        multihash.extend_from_slice(&self.digest);
+        multihash
+    }
+
+    /// Encode the [HashLink] with the provided [multibase::Base].
+    pub fn encode(&self, base: multibase::Base) -> String {
+        multibase::encode(base, self.multihash())
+    }
+
+    /// Decode a [HashLink] from its multibase form.
+    pub fn decode(s: &str) -> Result<Self, anyhow::Error> {
+        let (_, multihash) =
+            multibase::decode(s).map_err(|e| anyhow!("Hashlink {} is not multibase: {}", s, e))?;
+
+        let (algorithm, digest) = match multihash.as_slice() {
+            [code, len, digest @ ..] => {
+                let algorithm = HashAlgorithm::from_code(*code)?;
+                if *len as usize != algorithm.digest_len() || digest.len() != algorithm.digest_len()
+                {
+                    return Err(anyhow!("Hashlink {} has a digest of the wrong length", s));
+                }
+
+                (algorithm, digest.to_vec())
+            }
+            _ => return Err(anyhow!("Hashlink {} is not a multihash", s)),
+        };
+
+        Ok(Self { algorithm, digest })
+    }
+
+    /// Check that the content hashes to the [HashLink].
+    pub fn verify(&self, content: &[u8]) -> Result<(), anyhow::Error> {
+        if self.algorithm.digest(content) != self.digest {
+            return Err(anyhow!("Content does not match hashlink {}", self));
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for HashLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.encode(multibase::Base::Base58Btc))
+    }
+}
+
+impl std::str::FromStr for HashLink {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::decode(s)
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_encode() {
+        use super::HashLink;
+
+        let hl = HashLink::new(b"Hello World!");
+        assert_eq!(
+            hl.to_string(),
+            "zQmWvQxTqbG2Z9HPJgG57jjwR154cKhbtJenbyYTWkjgF3e"
+        );
+        assert_eq!(hl.multihash()[..2], [0x12, 0x20]);
+
+        // any multibase decodes to the same hashlink
+        for base in [
+            multibase::Base::Base58Btc,
+            multibase::Base::Base16Lower,
+            multibase::Base::Base64Url,
+        ] {
+            assert_eq!(HashLink::decode(&hl.encode(base)).unwrap(), hl);
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        use super::{HashAlgorithm, HashLink};
+
+        assert!(HashLink::new(b"Hello World!")
+            .verify(b"Hello World!")
+            .is_ok());
+
+        let hl = HashLink::with_algorithm(HashAlgorithm::Sha512, b"Hello World!");
+        assert_eq!(hl.digest.len(), 64);
+        let parsed: HashLink = hl.to_string().parse().unwrap();
+        assert_eq!(parsed.algorithm, HashAlgorithm::Sha512);
+        assert!(parsed.verify(b"Hello World!").is_ok());
+        assert!(parsed.verify(b"Hello World").is_err());
+    }
+
+    #[test]
+    fn test_decode_invalid() {
+        use super::HashLink;
+
+        for bad in [
+            "",
+            "myhash",
+            // identity multihash
+            &multibase::encode(multibase::Base::Base58Btc, [0x00, 0x01, 0x00]),
+            // truncated digest
+            &multibase::encode(multibase::Base::Base58Btc, [0x12, 0x20, 0x00]),
+            &multibase::encode(multibase::Base::Base58Btc, [0x12]),
+        ] {
+            assert!(HashLink::decode(bad).is_err(), "{}", bad);
+        }
+    }
+}
@@ -0,0 +1,345 @@
+use crate::{
+    did::DID,
+    document::{Document, ServiceType, VerificationMethod},
+    jwk::JWK,
+    multibase::MultiBase,
+};
+use imbl::{OrdMap, OrdSet};
+use std::collections::BTreeSet;
+use url::Url;
+
+/// KeyMaterial identifies the public key of a [VerificationMethod] for the purposes of lookup.
+/// JWKs are identified by their RFC 7638 thumbprint (see [JWK::thumbprint]) and multibase keys by
+/// their decoded bytes.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum KeyMaterial {
+    JWKThumbprint(String),
+    MultiBase(Vec<u8>),
+}
+
+impl KeyMaterial {
+    /// Compute the [KeyMaterial] for a [JWK].
+    pub fn from_jwk(jwk: &JWK) -> Result<Self, anyhow::Error> {
+        Ok(KeyMaterial::JWKThumbprint(jwk.thumbprint()?))
+    }
+
+    /// Compute the [KeyMaterial] for a [MultiBase] key.
+    pub fn from_multibase(mb: &MultiBase) -> Self {
+        KeyMaterial::MultiBase(mb.as_bytes().to_vec())
+    }
+
+    /// Compute the [KeyMaterial] for a [VerificationMethod]: that of its JWK and of its multibase
+    /// key, for whichever of the two it carries. Fails if its JWK cannot be thumbprinted.
+    pub fn from_verification_method(vm: &VerificationMethod) -> Result<Vec<Self>, anyhow::Error> {
+        let mut keys = Vec::new();
+
+        if let Some(jwk) = &vm.public_key_jwk {
+            keys.push(Self::from_jwk(jwk)?);
+        }
+
+        keys.extend(vm.public_key_multibase.as_ref().map(Self::from_multibase));
+        Ok(keys)
+    }
+}
+
+/// ReverseIndex maintains secondary indexes from the contents of [Document]s back to their
+/// [DID]s: by key material, service type, service endpoint URL, controller, and the [DID]s whose
+/// verification methods are referenced by URL in verification relationships. It is kept by the
+/// [crate::registry::Registry] and updated as documents are inserted, updated and removed.
+///
+/// Service endpoint URLs include the `origins` and `registries` of endpoints which are described
+/// with properties, so a `LinkedDomains` origin can be looked up directly.
+#[derive(Clone, Debug, Default)]
+pub struct ReverseIndex {
+    keys: OrdMap<KeyMaterial, OrdSet<DID>>,
+    service_types: OrdMap<ServiceType, OrdSet<DID>>,
+    service_endpoints: OrdMap<Url, OrdSet<DID>>,
+    controllers: OrdMap<DID, OrdSet<DID>>,
+    references: OrdMap<DID, OrdSet<DID>>,
+}
+
+fn add<K: Ord + Clone>(map: &mut OrdMap<K, OrdSet<DID>>, key: K, did: &DID) {
+    map.entry(key).or_default().insert(did.clone());
+}
+
+fn del<K: Ord + Clone>(map: &mut OrdMap<K, OrdSet<DID>>, key: K, did: &DID) {
+    if let Some(set) = map.get_mut(&key) {
+        set.remove(did);
+        if set.is_empty() {
+            map.remove(&key);
+        }
+    }
+}
+
+fn get<K: Ord + Clone>(map: &OrdMap<K, OrdSet<DID>>, key: &K) -> BTreeSet<DID> {
+    map.get(key)
+        .map(|set| set.iter().cloned().collect())
+        .unwrap_or_default()
+}
+
+impl ReverseIndex {
+    /// Add a [Document]'s contents to the index.
+    pub fn insert(&mut self, doc: &Document) {
+        let did = &doc.id;
+
+        for key in Self::keys_for(doc) {
+            add(&mut self.keys, key, did);
+        }
+
+        let (types, urls) = Self::services_for(doc);
+        for typ in types {
+            add(&mut self.service_types, typ, did);
+        }
+
+        for url in urls {
+            add(&mut self.service_endpoints, url, did);
+        }
+
+        for controller in Self::controllers_for(doc) {
+            add(&mut self.controllers, controller, did);
+        }
+
+        for reference in Self::references_for(doc) {
+            add(&mut self.references, reference, did);
+        }
+    }
+
+    /// Remove a [Document]'s contents from the index. The document must be the one that was
+    /// indexed, otherwise stale entries may remain.
+    pub fn remove(&mut self, doc: &Document) {
+        let did = &doc.id;
+
+        for key in Self::keys_for(doc) {
+            del(&mut self.keys, key, did);
+        }
+
+        let (types, urls) = Self::services_for(doc);
+        for typ in types {
+            del(&mut self.service_types, typ, did);
+        }
+
+        for url in urls {
+            del(&mut self.service_endpoints, url, did);
+        }
+
+        for controller in Self::controllers_for(doc) {
+            del(&mut self.controllers, controller, did);
+        }
+
+        for reference in Self::references_for(doc) {
+            del(&mut self.references, reference, did);
+        }
+    }
+
+    /// Retrieve the [DID]s whose documents carry the provided [KeyMaterial].
+    pub fn by_key(&self, key: &KeyMaterial) -> BTreeSet<DID> {
+        get(&self.keys, key)
+    }
+
+    /// Retrieve the [DID]s whose documents have a service of the provided [ServiceType].
+    pub fn by_service_type(&self, typ: &ServiceType) -> BTreeSet<DID> {
+        get(&self.service_types, typ)
+    }
+
+    /// Retrieve the [DID]s whose documents have a service endpoint naming the provided [Url].
+    pub fn by_service_endpoint(&self, url: &Url) -> BTreeSet<DID> {
+        get(&self.service_endpoints, url)
+    }
+
+    /// Retrieve the [DID]s whose documents name the provided [DID] as a controller.
+    pub fn by_controller(&self, controller: &DID) -> BTreeSet<DID> {
+        get(&self.controllers, controller)
+    }
+
+    /// Retrieve the [DID]s whose documents refer to a verification method of the provided [DID]
+    /// by URL, in any verification relationship.
+    pub fn by_reference(&self, did: &DID) -> BTreeSet<DID> {
+        get(&self.references, did)
+    }
+
+    /// Retrieve the [KeyMaterial] which is carried by the documents of more than one [DID],
+    /// ordered by [KeyMaterial].
+    pub fn shared_keys(&self) -> Vec<(KeyMaterial, BTreeSet<DID>)> {
+        self.keys
+            .iter()
+            .filter(|(_, dids)| dids.len() > 1)
+            .map(|(key, dids)| (key.clone(), dids.iter().cloned().collect()))
+            .collect()
+    }
+
+    // JWKs which cannot be thumbprinted are left out, and reported by Registry::audit instead
+    fn keys_for(doc: &Document) -> BTreeSet<KeyMaterial> {
+        let mut keys = BTreeSet::new();
+
+        for vm in doc.verification_methods() {
+            keys.extend(
+                vm.public_key_jwk
+                    .as_ref()
+                    .and_then(|jwk| KeyMaterial::from_jwk(jwk).ok()),
+            );
+            keys.extend(
+                vm.public_key_multibase
+                    .as_ref()
+                    .map(KeyMaterial::from_multibase),
+            );
+        }
+
+        keys
+    }
+
+    fn services_for(doc: &Document) -> (BTreeSet<ServiceType>, BTreeSet<Url>) {
+        let mut types = BTreeSet::new();
+        let mut urls = BTreeSet::new();
+
+        for service in doc.service.iter().flatten() {
+            types.extend(service.typ.types());
+            urls.insert(service.id.clone());
+            urls.extend(service.endpoint.urls());
+        }
+
+        (types, urls)
+    }
+
+    fn references_for(doc: &Document) -> BTreeSet<DID> {
+        doc.verification_relationships()
+            .into_iter()
+            .flat_map(|(_, vms)| vms.0.iter())
+            .filter_map(|vm| vm.0.as_ref().right().map(|url| url.to_did()))
+            .collect()
+    }
+
+    fn controllers_for(doc: &Document) -> BTreeSet<DID> {
+        doc.controller
+            .as_ref()
+            .map(|controller| controller.dids())
+            .unwrap_or_default()
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_reverse_index() {
+        use super::{KeyMaterial, ReverseIndex};
+        use crate::{
+            did::DID,
+            document::{
+                Controller, Document, ServiceEndpoint, ServiceEndpointProperties, ServiceEndpoints,
+                ServiceType, ServiceTypes, VerificationMethod, VerificationMethodEither,
+                VerificationMethods,
+            },
+            jwk::JWK,
+            multibase::MultiBase,
+            url::URL,
+        };
+        use either::Either;
+        use url::Url;
+
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let did2 = DID::parse("did:testing:u:bob").unwrap();
+        let jwk = JWK::new().unwrap();
+        let origin = Url::parse("https://example.org").unwrap();
+
+        let doc = Document {
+            id: did.clone(),
+            controller: Some(Controller(Either::Left(did2.clone()))),
+            authentication: Some(VerificationMethods(
+                [VerificationMethodEither(Either::Right(
+                    URL::parse("did:testing:u:bob#key-1").unwrap(),
+                ))]
+                .into(),
+            )),
+            verification_method: Some(
+                [VerificationMethod {
+                    id: URL::parse("did:testing:u:alice#key-1").unwrap(),
+                    controller: did.clone(),
+                    public_key_jwk: Some(jwk.clone()),
+                    ..Default::default()
+                }]
+                .into(),
+            ),
+            service: Some(
+                [ServiceEndpoint {
+                    id: Url::parse("https://example.org/service").unwrap(),
+                    typ: ServiceTypes(Either::Left(ServiceType::LinkedDomains)),
+                    endpoint: ServiceEndpoints(Either::Right(ServiceEndpointProperties {
+                        origins: Some([origin.clone()].into()),
+                        registries: None,
+                    })),
+                }]
+                .into(),
+            ),
+            ..Default::default()
+        };
+
+        let mut index = ReverseIndex::default();
+        index.insert(&doc);
+
+        let key = KeyMaterial::from_jwk(&jwk).unwrap();
+        assert_eq!(index.by_key(&key), [did.clone()].into());
+        assert_eq!(
+            index.by_service_type(&ServiceType::LinkedDomains),
+            [did.clone()].into()
+        );
+        assert!(index
+            .by_service_type(&ServiceType::CredentialRegistry)
+            .is_empty());
+        assert_eq!(index.by_service_endpoint(&origin), [did.clone()].into());
+        assert_eq!(index.by_controller(&did2), [did.clone()].into());
+        assert!(index.by_controller(&did).is_empty());
+        assert_eq!(index.by_reference(&did2), [did.clone()].into());
+
+        index.remove(&doc);
+        assert!(index.by_key(&key).is_empty());
+        assert!(index.by_service_endpoint(&origin).is_empty());
+        assert!(index.by_controller(&did2).is_empty());
+        assert!(index.by_reference(&did2).is_empty());
+
+        // methods carrying both a JWK and a multibase key are found by either
+        let mb = MultiBase::new(vec![1, 2, 3]);
+        let vm = VerificationMethod {
+            id: URL::parse("did:testing:u:alice#key-2").unwrap(),
+            controller: did.clone(),
+            public_key_jwk: Some(jwk.clone()),
+            public_key_multibase: Some(mb.clone()),
+            ..Default::default()
+        };
+        assert_eq!(
+            KeyMaterial::from_verification_method(&vm).unwrap(),
+            vec![key.clone(), KeyMaterial::from_multibase(&mb)]
+        );
+        let doc = Document {
+            verification_method: Some([vm].into()),
+            ..doc
+        };
+        index.insert(&doc);
+        assert_eq!(index.by_key(&key), [did.clone()].into());
+        assert_eq!(
+            index.by_key(&KeyMaterial::from_multibase(&mb)),
+            [did.clone()].into()
+        );
+
+        // and by their multibase key when their JWK cannot be thumbprinted
+        let vm = VerificationMethod {
+            public_key_jwk: Some(JWK::new_from_bytes(br#"{"kty":"EC","crv":"P-256"}"#).unwrap()),
+            ..doc
+                .verification_method
+                .iter()
+                .flatten()
+                .next()
+                .unwrap()
+                .clone()
+        };
+        assert!(KeyMaterial::from_verification_method(&vm).is_err());
+        index.remove(&doc);
+        let doc = Document {
+            verification_method: Some([vm].into()),
+            ..doc
+        };
+        index.insert(&doc);
+        assert!(index.by_key(&key).is_empty());
+        assert_eq!(
+            index.by_key(&KeyMaterial::from_multibase(&mb)),
+            [did.clone()].into()
+        );
+    }
+}
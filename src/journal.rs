@@ -0,0 +1,431 @@
+use crate::{
+    did::DID,
+    document::{canonical_json, Document},
+    metadata::Identifiers,
+    registry::{Registry, Version},
+    time::VersionTime,
+    tombstone::Resurrection,
+};
+use anyhow::anyhow;
+use imbl::Vector;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// The `previous` hash of the first entry in a [Journal].
+pub const GENESIS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// Operation is the kind of change recorded by a [JournalEntry].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Operation {
+    /// See [Registry::insert].
+    Insert,
+    /// See [Registry::update].
+    Update,
+    /// See [Registry::remove].
+    Remove,
+    /// See [Registry::deactivate].
+    Deactivate,
+    /// See [Registry::set_equivalent_ids] and [Registry::set_canonical_id].
+    Identify,
+}
+
+/// JournalEntry records a single change to a [Registry] in its [Journal]. Hashes and digests are
+/// SHA-256, hex-encoded.
+///
+/// An entry's `hash` commits to its other properties, with the document given by its `digest`
+/// over [Document::canonical_json]; its `previous` property is the `hash` of the entry before it,
+/// so that no entry can be changed, removed or reordered without breaking the chain.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    /// The position of the entry in the journal, starting at zero.
+    pub sequence: u64,
+    pub operation: Operation,
+    pub id: DID,
+    /// The [Version] of the document after the change, or of the
+    /// [crate::tombstone::Tombstone] of a deactivation. Removals have no version.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version: Option<Version>,
+    pub time: VersionTime,
+    /// The document after the change, for inserts and updates.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub document: Option<Document>,
+    /// The digest of the document's canonical JSON.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub digest: Option<String>,
+    /// The [Identifiers] of the document after the change, for changes to them and for inserts
+    /// of documents which carry them.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub identifiers: Option<Identifiers>,
+    /// The hash of the previous entry, or [GENESIS] for the first.
+    pub previous: String,
+    pub hash: String,
+}
+
+fn hex(digest: impl AsRef<[u8]>) -> String {
+    multibase::Base::Base16Lower.encode(digest)
+}
+
+impl JournalEntry {
+    /// Compute the hash of the entry from its other properties.
+    pub fn compute_hash(&self) -> Result<String, anyhow::Error> {
+        let mut committed = serde_json::json!({
+            "sequence": self.sequence,
+            "operation": self.operation,
+            "id": self.id,
+            "version": self.version,
+            "time": self.time,
+            "digest": self.digest,
+            "previous": self.previous,
+        });
+
+        // committed only when present, so the hashes of other entries are unchanged
+        if let Some(identifiers) = &self.identifiers {
+            committed["identifiers"] = serde_json::to_value(identifiers)?;
+        }
+
+        Ok(hex(Sha256::digest(canonical_json(&committed)?)))
+    }
+
+    /// Check that the entry's document matches its digest, and that its hash is correct.
+    pub fn verify(&self) -> Result<(), anyhow::Error> {
+        let digest = match &self.document {
+            Some(doc) => Some(hex(doc.digest()?)),
+            None => None,
+        };
+
+        if digest != self.digest {
+            return Err(anyhow!(
+                "Journal entry {} has a document which does not match its digest",
+                self.sequence
+            ));
+        }
+
+        if self.compute_hash()? != self.hash {
+            return Err(anyhow!(
+                "Journal entry {} does not match its hash",
+                self.sequence
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Journal is an append-only, hash-chained log of the changes made to a [Registry]: every
+/// insert, update, removal and deactivation, with the document as it was after the change, and
+/// every change to the [Identifiers] asserted for a document.
+/// Enable it with [Registry::enable_journal]. Changes staged in a
+/// [crate::transaction::Transaction] are recorded once they are committed. The remote cache is
+/// not recorded.
+///
+/// [Journal::replay] checks the chain and rebuilds the registry it describes, and
+/// [Journal::document_at] recovers a document as it was at any time.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let mut reg = Registry::default();
+/// reg.enable_journal();
+///
+/// let did = DID::parse("did:mymethod:alice").unwrap();
+/// reg.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+/// reg.deactivate(&did).unwrap();
+///
+/// let journal = reg.journal().unwrap();
+/// assert_eq!(journal.len(), 2);
+/// assert!(reg.verify_journal().is_ok());
+///
+/// // rewriting history breaks the chain
+/// let mut entries: Vec<JournalEntry> = journal.entries().cloned().collect();
+/// entries[0].document.as_mut().unwrap().also_known_as = Some(Default::default());
+/// assert!(Journal::from_entries(entries).replay().is_err());
+/// ```
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Journal {
+    entries: Vector<JournalEntry>,
+}
+
+impl Journal {
+    /// Create a journal from entries, such as ones previously written out from
+    /// [Journal::entries]. The entries are not checked; see [Journal::replay].
+    pub fn from_entries(entries: impl IntoIterator<Item = JournalEntry>) -> Self {
+        Self {
+            entries: entries.into_iter().collect(),
+        }
+    }
+
+    /// Iterate over the entries, oldest first.
+    pub fn entries(&self) -> impl Iterator<Item = &JournalEntry> {
+        self.entries.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Iterate over at most `limit` entries, starting from the one with the provided sequence
+    /// number.
+    pub fn since(&self, sequence: u64, limit: usize) -> impl Iterator<Item = &JournalEntry> {
+        let start = (sequence as usize).min(self.entries.len());
+        let end = start.saturating_add(limit).min(self.entries.len());
+        (start..end).map(|index| &self.entries[index])
+    }
+
+    /// The hash of the latest entry, which commits to the whole journal; [GENESIS] if it is
+    /// empty.
+    pub fn head(&self) -> &str {
+        self.entries
+            .last()
+            .map(|entry| entry.hash.as_str())
+            .unwrap_or(GENESIS)
+    }
+
+    /// Retrieve the entries for a [DID], oldest first.
+    pub fn history(&self, did: &DID) -> Vec<&JournalEntry> {
+        self.entries
+            .iter()
+            .filter(|entry| &entry.id == did)
+            .collect()
+    }
+
+    /// Retrieve a document as it was at the provided time, after the last change made at or
+    /// before it. Returns [None] if it did not exist, or had been removed or deactivated. Changes
+    /// are timed to the nanosecond, so one made within the second of a time given to whole
+    /// seconds, as in a [crate::url::URL], comes after it; see [VersionTime::truncated].
+    pub fn document_at(&self, did: &DID, time: &VersionTime) -> Option<Document> {
+        self.entries
+            .iter()
+            .filter(|entry| &entry.id == did && &entry.time <= time)
+            .last()
+            .and_then(|entry| entry.document.clone())
+    }
+
+    /// Check every entry and the chain between them, and rebuild the registry the journal
+    /// describes by applying each change in turn. Fails at the first entry which does not verify,
+    /// or whose change cannot be applied.
+    pub fn replay(&self) -> Result<Registry, anyhow::Error> {
+        let mut reg = Registry::default();
+        // resurrections are only recorded if they were allowed at the time
+        reg.set_resurrection(Resurrection::Allow);
+
+        let mut previous = GENESIS;
+        for (sequence, entry) in self.entries.iter().enumerate() {
+            if entry.sequence != sequence as u64 {
+                return Err(anyhow!(
+                    "Journal entry {} is out of sequence at position {}",
+                    entry.sequence,
+                    sequence
+                ));
+            }
+
+            if entry.previous != previous {
+                return Err(anyhow!(
+                    "Journal entry {} does not follow the entry before it",
+                    entry.sequence
+                ));
+            }
+
+            entry.verify()?;
+            reg.replay(entry).map_err(|e| {
+                anyhow!("Journal entry {} cannot be applied: {}", entry.sequence, e)
+            })?;
+            previous = &entry.hash;
+        }
+
+        reg.set_resurrection(Resurrection::default());
+        Ok(reg)
+    }
+
+    pub(crate) fn entry(
+        &self,
+        operation: Operation,
+        id: &DID,
+        document: Option<&Document>,
+        identifiers: Option<&Identifiers>,
+        version: Option<Version>,
+        time: VersionTime,
+    ) -> Result<JournalEntry, anyhow::Error> {
+        let digest = match document {
+            Some(doc) => Some(hex(doc.digest()?)),
+            None => None,
+        };
+
+        let mut entry = JournalEntry {
+            sequence: self.entries.len() as u64,
+            operation,
+            id: id.clone(),
+            version,
+            time,
+            document: document.cloned(),
+            digest,
+            identifiers: identifiers.cloned(),
+            previous: self.head().to_string(),
+            hash: String::new(),
+        };
+
+        entry.hash = entry.compute_hash()?;
+        Ok(entry)
+    }
+
+    pub(crate) fn append(&mut self, entry: JournalEntry) {
+        self.entries.push_back(entry)
+    }
+}
+
+mod tests {
+    #[cfg(test)]
+    fn doc(name: &str) -> crate::document::Document {
+        use crate::{did::DID, document::Document};
+
+        Document {
+            id: DID::parse(&format!("did:testing:u:{}", name)).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    // a journaled registry in which alice was inserted and updated, and bob inserted and removed,
+    // with alice's updated document
+    #[cfg(test)]
+    fn fixture() -> (crate::registry::Registry, crate::document::Document) {
+        use crate::registry::Registry;
+
+        let mut reg = Registry::default();
+        reg.enable_journal();
+        reg.insert(doc("alice")).unwrap();
+        let mut updated = doc("alice");
+        updated.also_known_as = Some(Default::default());
+        reg.update(updated.clone()).unwrap();
+        reg.insert(doc("bob")).unwrap();
+        reg.remove(&doc("bob").id);
+
+        (reg, updated)
+    }
+
+    #[test]
+    fn test_record() {
+        use super::{Operation, GENESIS};
+        use crate::{registry::Registry, tombstone::Resurrection};
+
+        let did = |name: &str| doc(name).id;
+
+        let mut reg = Registry::default();
+        reg.enable_journal();
+        assert_eq!(reg.journal().unwrap().head(), GENESIS);
+
+        reg.insert(doc("alice")).unwrap();
+        reg.insert(doc("bob")).unwrap();
+        let mut updated = doc("alice");
+        updated.also_known_as = Some(Default::default());
+        reg.update(updated.clone()).unwrap();
+        reg.remove(&did("bob"));
+        reg.deactivate(&did("alice")).unwrap();
+        reg.set_resurrection(Resurrection::Allow);
+        reg.insert(doc("alice")).unwrap();
+
+        // changes staged in a transaction are only recorded if it is committed
+        let mut tx = reg.transaction();
+        tx.insert(doc("charlie")).unwrap();
+        tx.rollback();
+        reg.transact(|tx| tx.insert(doc("dave"))).unwrap();
+
+        let journal = reg.journal().unwrap();
+        assert_eq!(
+            journal.entries().map(|e| e.operation).collect::<Vec<_>>(),
+            vec![
+                Operation::Insert,
+                Operation::Insert,
+                Operation::Update,
+                Operation::Remove,
+                Operation::Deactivate,
+                Operation::Insert,
+                Operation::Insert,
+            ]
+        );
+        assert_eq!(journal.history(&did("alice")).len(), 4);
+        assert!(journal.history(&did("charlie")).is_empty());
+        assert_ne!(journal.head(), GENESIS);
+        reg.verify_journal().unwrap();
+    }
+
+    #[test]
+    fn test_document_at() {
+        use crate::time::VersionTime;
+
+        let did = |name: &str| doc(name).id;
+        let (reg, updated) = fixture();
+
+        let journal = reg.journal().unwrap();
+        let first = &journal.entries().next().unwrap().time;
+        let before = VersionTime(first.0 - time::Duration::seconds(1));
+        assert_eq!(journal.document_at(&did("alice"), &before), None);
+        assert_eq!(
+            journal.document_at(&did("alice"), &VersionTime::now()),
+            Some(updated)
+        );
+        assert_eq!(journal.document_at(&did("bob"), &VersionTime::now()), None);
+    }
+
+    #[test]
+    fn test_replay() {
+        use super::{Journal, JournalEntry};
+
+        let did = |name: &str| doc(name).id;
+        let (mut reg, _) = fixture();
+        reg.deactivate(&did("alice")).unwrap();
+
+        // entries survive a round trip through serialization
+        let json: Vec<String> = reg
+            .journal()
+            .unwrap()
+            .entries()
+            .map(|e| serde_json::to_string(e).unwrap())
+            .collect();
+        let entries: Vec<JournalEntry> = json
+            .iter()
+            .map(|e| serde_json::from_str(e).unwrap())
+            .collect();
+        let replayed = Journal::from_entries(entries.clone()).replay().unwrap();
+        assert!(replayed.diff(&reg).is_empty());
+        assert!(replayed.is_deactivated(&did("alice")));
+        assert_eq!(
+            replayed.tombstone(&did("alice")),
+            reg.tombstone(&did("alice"))
+        );
+
+        // tampering with an entry, or the order of entries, is detected
+        let mut tampered = entries.clone();
+        tampered[2].document = Some(doc("alice"));
+        assert!(Journal::from_entries(tampered).replay().is_err());
+
+        let mut tampered = entries.clone();
+        tampered[2].version = tampered[1].version;
+        assert!(Journal::from_entries(tampered).replay().is_err());
+
+        let mut tampered = entries;
+        tampered.remove(3);
+        assert!(Journal::from_entries(tampered).replay().is_err());
+    }
+
+    #[test]
+    fn test_verify() {
+        use crate::registry::Registry;
+
+        let mut reg = Registry::default();
+        reg.enable_journal();
+        reg.insert(doc("alice")).unwrap();
+        reg.verify_journal().unwrap();
+
+        // a journal enabled after documents were added does not describe them
+        let mut late = Registry::default();
+        late.insert(doc("alice")).unwrap();
+        late.enable_journal();
+        late.insert(doc("bob")).unwrap();
+        assert!(late.verify_journal().is_err());
+        assert!(Registry::default().verify_journal().is_err());
+    }
+}
@@ -1,5 +1,7 @@
+use anyhow::anyhow;
 use josekit::jwk::{alg::ec::EcCurve, Jwk};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::hash::{Hash, Hasher};
 
 /// Encapsulation of JSON Web Keys, provided by the [josekit] crate underneath. Serialization
@@ -26,6 +28,33 @@ impl JWK {
     pub fn to_public_only(&self) -> Result<Self, anyhow::Error> {
         Ok(JWK(self.0.to_public_key()?))
     }
+
+    /// Computes the JWK thumbprint according to <https://www.rfc-editor.org/rfc/rfc7638>, using
+    /// SHA-256. The result is base64url-encoded without padding. Only the public members of the
+    /// key are considered, so a key and its public counterpart share a thumbprint.
+    pub fn thumbprint(&self) -> Result<String, anyhow::Error> {
+        let members: &[&str] = match self.0.key_type() {
+            "EC" => &["crv", "kty", "x", "y"],
+            "RSA" => &["e", "kty", "n"],
+            "OKP" => &["crv", "kty", "x"],
+            "oct" => &["k", "kty"],
+            typ => return Err(anyhow!("Unsupported key type {} for thumbprint", typ)),
+        };
+
+        let mut map = serde_json::Map::new();
+        for member in members {
+            match self.0.parameter(member) {
+                Some(value) => {
+                    map.insert(member.to_string(), value.clone());
+                }
+                None => return Err(anyhow!("JWK is missing required member {}", member)),
+            }
+        }
+
+        // serde_json's map is ordered by key, which is what the RFC requires.
+        let digest = Sha256::digest(serde_json::to_vec(&map)?);
+        Ok(multibase::Base::Base64Url.encode(digest))
+    }
 }
 
 impl Serialize for JWK {
@@ -48,17 +77,41 @@ impl Hash for JWK {
 
 impl PartialOrd for JWK {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for JWK {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         let mut state = std::collections::hash_map::DefaultHasher::default();
         let mut other_state = std::collections::hash_map::DefaultHasher::default();
         self.hash(&mut state);
         other.hash(&mut other_state);
 
-        state.finish().partial_cmp(&other_state.finish())
+        state.finish().cmp(&other_state.finish())
     }
 }
 
-impl Ord for JWK {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap()
+mod tests {
+    #[test]
+    fn test_thumbprint() {
+        use super::JWK;
+
+        // RFC 7638 section 3.1
+        let jwk = JWK::new_from_bytes(
+            br#"{"kty":"RSA","n":"0vx7agoebGcQSuuPiLJXZptN9nndrQmbXEps2aiAFbWhM78LhWx4cbbfAAtVT86zwu1RK7aPFFxuhDR1L6tSoc_BJECPebWKRXjBZCiFV4n3oknjhMstn64tZ_2W-5JsGY4Hc5n9yBXArwl93lqt7_RN5w6Cf0h4QyQ5v-65YGjQR0_FDW2QvzqY368QQMicAtaSqzs8KJZgnYb9c7d0zgdAZHzu6qMQvRL5hajrn1n91CbOpbISD08qNLyrdkt-bFTWhAI4vMQFh6WeZu0fM4lFd2NcRwr3XPksINHaQ-G_xBniIqbw0Ls1jF44-csFCur-kEgU8awapJzKnqDKgw","e":"AQAB","alg":"RS256","kid":"2011-04-29"}"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            jwk.thumbprint().unwrap(),
+            "NzbLsXh8uDCcd-6MNwXF4W_7noWXFZAfHkxZsRGC9Xs"
+        );
+
+        let jwk = JWK::new().unwrap();
+        assert_eq!(
+            jwk.thumbprint().unwrap(),
+            jwk.to_public_only().unwrap().thumbprint().unwrap()
+        );
     }
 }
@@ -1,22 +1,73 @@
+/// Registry-wide consistency checks for a [crate::registry::Registry]
+pub mod audit;
+/// Expiry and revalidation of remotely fetched documents
+pub mod cache;
 /// Decentralized Identifier syntax parsing and generation
 pub mod did;
+/// Comparing and merging [crate::registry::Registry] instances
+pub mod diff;
 /// Decentralized Identity Document typing and (de)-serialization
 pub mod document;
+/// alsoKnownAs equivalence classes, as maintained by [crate::registry::Registry]
+pub mod equivalence;
+/// Change notifications and hooks for a [crate::registry::Registry]
+pub mod events;
+/// Bulk export and import of a [crate::registry::Registry]
+pub mod export;
+/// Policy-constrained fetching of remote documents
+pub mod fetch;
+/// Hashlinks, for pinning DID URLs to the content they dereference to
+pub mod hashlink;
+/// Secondary indexes over document contents, as maintained by [crate::registry::Registry]
+pub mod index;
+/// Append-only, hash-chained log of changes to a [crate::registry::Registry]
+pub mod journal;
 /// JSON Web Key management
 pub mod jwk;
+/// Merkle tree commitments to the contents of a [crate::registry::Registry], with inclusion proofs
+pub mod merkle;
+/// DID document metadata, as maintained by [crate::registry::Registry]
+pub mod metadata;
+/// Per-method policies for the documents kept by a [crate::registry::Registry]
+pub mod method;
 /// Multibase public key management
 pub mod multibase;
+/// Composable filters for querying a [crate::registry::Registry]
+pub mod query;
 /// In-Memory Registry for Decentralized Identity Documents, with some database-like features.
 pub mod registry;
+/// Replicating a [crate::registry::Registry] from the change feed of another
+pub mod replication;
+/// Async registry and resolver, available with the `async` feature
+#[cfg(feature = "async")]
+pub mod resolver;
+/// Resources linked to [crate::did::DID]s, dereferenced by DID URL path
+pub mod resource;
+/// DID resolution over HTTP, serving a [crate::registry::Registry]
+pub mod server;
+/// Thread-safe, copy-on-write sharing of a [crate::registry::Registry]
+pub mod shared;
 /// String handling routines; not included in prelude, should avoid using publicly.
 pub mod string;
 /// VersionTime [crate::url::URL] parameter handling
 pub mod time;
+/// Deactivated DIDs, as kept by [crate::registry::Registry]
+pub mod tombstone;
+/// Atomic batches of changes to a [crate::registry::Registry]
+pub mod transaction;
 /// DID URLs, a way to inter-link to [crate::did::DID]s.
 pub mod url;
 
 /// Convenience module for exporting all public types
 pub mod prelude {
     // NOTE we did not include the string methods as they will pollute global namespace poorly
-    pub use crate::{did::*, document::*, jwk::*, multibase::*, registry::*, time::*, url::*};
+    pub use crate::{
+        audit::*, did::*, diff::*, document::*, equivalence::*, events::*, export::*, fetch::*,
+        hashlink::*, index::*, journal::*, jwk::*, merkle::*, metadata::*, method::*, multibase::*,
+        query::*, registry::*, replication::*, resource::*, server::*, shared::*, time::*,
+        tombstone::*, transaction::*, url::*,
+    };
+
+    #[cfg(feature = "async")]
+    pub use crate::resolver::*;
 }
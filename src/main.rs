@@ -1,16 +1,55 @@
 use anyhow::anyhow;
-use clap::Parser;
-use std::path::PathBuf;
-use util::{create_files, create_identities};
+use clap::{Parser, Subcommand, ValueEnum};
+use did_toolkit::{export::ExportFormat, registry::Registry, server::ResolutionServer};
+use std::{net::TcpListener, path::PathBuf, sync::Arc};
+use util::{create_export, create_files, create_identities};
+
+#[derive(ValueEnum, Clone, Copy, Debug)]
+enum Export {
+    Jsonl,
+    CborSeq,
+    Tar,
+}
+
+impl Export {
+    fn format(self) -> ExportFormat {
+        match self {
+            Export::Jsonl => ExportFormat::JsonLines,
+            Export::CborSeq => ExportFormat::CborSequence,
+            Export::Tar => ExportFormat::Archive,
+        }
+    }
+
+    fn from_filename(path: &std::path::Path) -> Option<Self> {
+        match path.extension()?.to_str()? {
+            "jsonl" => Some(Export::Jsonl),
+            "cborseq" => Some(Export::CborSeq),
+            "tar" => Some(Export::Tar),
+            _ => None,
+        }
+    }
+
+    fn filename(self) -> &'static str {
+        match self {
+            Export::Jsonl => "registry.jsonl",
+            Export::CborSeq => "registry.cborseq",
+            Export::Tar => "registry.tar",
+        }
+    }
+}
 
 #[derive(Parser, Debug)]
 #[command(
     author = "Erik Hollensbe <erik+github@hollensbe.org",
-    about = "Generate a tree of documents for testing DID parser compliance"
+    about = "Generate a tree of documents for testing DID parser compliance",
+    args_conflicts_with_subcommands = true,
+    subcommand_negates_reqs = true
 )]
 struct Args {
-    #[arg(help = "Path to generate files to")]
-    path: PathBuf,
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[arg(help = "Path to generate files to", required = true)]
+    path: Option<PathBuf>,
     #[arg(
         help = "Number of identities to create",
         short = 'c',
@@ -34,6 +73,32 @@ struct Args {
     max_did_len: usize,
     #[arg(help = "Output CBOR instead of JSON", long = "cbor")]
     cbor: bool,
+    #[arg(
+        help = "Write a single bulk export in this format instead of one file per document",
+        long = "export",
+        value_enum
+    )]
+    export: Option<Export>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    #[command(
+        about = "Serve a registry over HTTP, with the DID resolution endpoints of the Universal Resolver"
+    )]
+    Serve {
+        #[arg(
+            help = "Generated documents to serve: a directory of JSON or CBOR files, or a bulk export"
+        )]
+        path: PathBuf,
+        #[arg(
+            help = "Address to listen on",
+            short = 'l',
+            long = "listen",
+            default_value = "127.0.0.1:8080"
+        )]
+        listen: String,
+    },
 }
 
 const MAX_DID_LEN: usize = 1000;
@@ -41,13 +106,77 @@ const MAX_DID_LEN: usize = 1000;
 fn main() -> Result<(), anyhow::Error> {
     let args = Args::parse();
 
+    if let Some(Command::Serve { path, listen }) = args.command {
+        return serve(path, &listen);
+    }
+
     if args.max_did_len > MAX_DID_LEN {
         return Err(anyhow!("DID lengths cannot be longer than {}", MAX_DID_LEN));
     }
 
-    std::fs::create_dir_all(args.path.clone())?;
+    // clap requires the path unless a command is given
+    let path = args.path.unwrap();
+    std::fs::create_dir_all(path.clone())?;
     let reg = create_identities(args.count, args.complexity_factor, args.max_did_len)?;
-    create_files(args.path, args.cbor, &reg)?;
+    match args.export {
+        Some(export) => create_export(path.join(export.filename()), export.format(), &reg)?,
+        None => create_files(path, args.cbor, &reg)?,
+    }
+    Ok(())
+}
+
+fn serve(path: PathBuf, listen: &str) -> Result<(), anyhow::Error> {
+    let mut reg = Registry::default();
+
+    if path.is_dir() {
+        load_dir(&mut reg, &path)?;
+    } else {
+        let export = Export::from_filename(&path)
+            .ok_or(anyhow!("Cannot tell the format of {}", path.display()))?;
+        let io = std::io::BufReader::new(std::fs::File::open(&path)?);
+        reg.import(export.format(), io)
+            .map_err(|e| anyhow!("Cannot import {}: {}", path.display(), e))?;
+    }
+
+    let listener = TcpListener::bind(listen)?;
+    eprintln!(
+        "Serving {} documents on http://{}/1.0/identifiers/",
+        reg.len(),
+        listener.local_addr()?
+    );
+    ResolutionServer::new(Arc::new(reg.into())).serve(listener)
+}
+
+// load a directory of documents in filename order. Every file which cannot be loaded is
+// reported, and the registry is only usable if there were none.
+fn load_dir(reg: &mut Registry, path: &std::path::Path) -> Result<(), anyhow::Error> {
+    let mut filenames = Vec::new();
+    for entry in std::fs::read_dir(path)? {
+        filenames.push(entry?.path());
+    }
+    filenames.sort();
+
+    let mut errors = Vec::new();
+    for filename in filenames {
+        let res = match filename.extension().and_then(|ext| ext.to_str()) {
+            Some("json") => reg.load_document(filename.clone()),
+            Some("cbor") => reg.load_document_cbor(filename.clone()),
+            _ => continue,
+        };
+
+        if let Err(e) = res {
+            errors.push(format!("{}: {}", filename.display(), e));
+        }
+    }
+
+    if !errors.is_empty() {
+        return Err(anyhow!(
+            "Cannot load {} files:\n{}",
+            errors.len(),
+            errors.join("\n")
+        ));
+    }
+
     Ok(())
 }
 //
@@ -60,7 +189,7 @@ mod util {
     use std::{collections::BTreeSet, path::PathBuf};
     use url::Url;
 
-    pub fn create_identities<'a>(
+    pub fn create_identities(
         count: usize,
         complexity: usize,
         max_did_len: usize,
@@ -85,33 +214,42 @@ mod util {
             }
         }
 
-        link_documents_aka(&mut reg, complexity);
-        link_documents_controller(&mut reg, complexity);
+        link_documents_aka(&mut reg, complexity)?;
+        link_documents_controller(&mut reg, complexity)?;
 
         Ok(reg)
     }
 
     pub fn create_files(dir: PathBuf, cbor: bool, reg: &Registry) -> Result<(), anyhow::Error> {
-        let mut num = 0;
-
-        for (_, doc) in reg.iter() {
+        for (num, (_, doc)) in reg.iter().enumerate() {
             if cbor {
-                let filename = dir.join(&format!("{}.cbor", num));
+                let filename = dir.join(format!("{}.cbor", num));
                 let mut opts = std::fs::OpenOptions::new();
                 opts.create_new(true);
                 opts.write(true);
                 let io = opts.open(filename)?;
                 ciborium::ser::into_writer(doc, io)?;
             } else {
-                let filename = dir.join(&format!("{}.json", num));
-                std::fs::write(filename, &json!(doc).to_string())?;
+                let filename = dir.join(format!("{}.json", num));
+                std::fs::write(filename, json!(doc).to_string())?;
             }
-            num += 1;
         }
 
         Ok(())
     }
 
+    pub fn create_export(
+        filename: PathBuf,
+        format: ExportFormat,
+        reg: &Registry,
+    ) -> Result<(), anyhow::Error> {
+        let mut opts = std::fs::OpenOptions::new();
+        opts.create_new(true);
+        opts.write(true);
+        let io = std::io::BufWriter::new(opts.open(filename)?);
+        reg.export(format, io)
+    }
+
     pub fn generate_random_url() -> Result<Url, anyhow::Error> {
         let domains = &["example.net", "example.org", "example.com"];
         let mut chars: [u8; 100] = [0; 100];
@@ -173,7 +311,7 @@ mod util {
 
         let mut rng = rand::rng();
 
-        for x in 0..attrs.len() {
+        for attr in attrs.iter_mut() {
             let mut set = BTreeSet::new();
             let path = &mut [0; 10];
             path.fill(&mut rng);
@@ -191,72 +329,70 @@ mod util {
                 }
             }
 
-            *attrs[x] = Some(VerificationMethods(set));
+            **attr = Some(VerificationMethods(set));
         }
 
         Ok(())
     }
 
-    pub fn link_documents_controller(reg: &mut Registry, iterations: usize) {
+    pub fn link_documents_controller(
+        reg: &mut Registry,
+        iterations: usize,
+    ) -> Result<(), anyhow::Error> {
         let mut rng = rand::rng();
         for _ in 0..iterations {
-            let one = &mut reg[rng.random_range(0..reg.len())].clone();
+            let mut one = reg[rng.random_range(0..reg.len())].clone();
             let two = reg[rng.random_range(0..reg.len())].clone();
 
-            if let None = one.controller {
-                reg[&one.id].controller = Some(Controller(Either::Left(two.id)));
-            } else {
-                match one.controller.clone().unwrap().0 {
+            match one.controller.clone() {
+                None => one.controller = Some(Controller(Either::Left(two.id))),
+                Some(controller) => match controller.0 {
                     Either::Left(did) => {
                         if did != two.id {
                             let mut set = BTreeSet::new();
                             set.insert(did);
                             set.insert(two.id);
-                            reg[&one.id].controller = Some(Controller(Either::Right(set)));
+                            one.controller = Some(Controller(Either::Right(set)));
                         }
                     }
                     Either::Right(mut set) => {
                         set.insert(two.id);
-                        reg[&one.id].controller = Some(Controller(Either::Right(set)));
+                        one.controller = Some(Controller(Either::Right(set)));
                     }
-                }
+                },
             }
+
+            reg.update(one)?;
         }
+
+        Ok(())
     }
 
-    pub fn link_documents_aka(reg: &mut Registry, iterations: usize) {
+    pub fn link_documents_aka(reg: &mut Registry, iterations: usize) -> Result<(), anyhow::Error> {
         let mut rng = rand::rng();
         for _ in 0..iterations {
-            let one = reg[rng.random_range(0..reg.len())].clone();
-            let two = reg[rng.random_range(0..reg.len())].clone();
-
-            let one_id = one.id.clone();
-            let two_id = two.id.clone();
+            let mut one = reg[rng.random_range(0..reg.len())].clone();
+            let mut two = reg[rng.random_range(0..reg.len())].clone();
 
             if one == two {
                 continue;
             }
 
-            if let None = one.also_known_as {
-                let one = &mut reg[&one_id];
-                one.also_known_as = Some(AlsoKnownAs::default());
-            }
-
-            if let None = two.also_known_as {
-                let two = &mut reg[&two_id];
-                two.also_known_as = Some(AlsoKnownAs::default());
-            }
-
-            let aka = &mut reg[&one_id].also_known_as.clone().unwrap();
+            let mut aka = one.also_known_as.unwrap_or_default();
             aka.0
-                .insert(AlsoKnownAsEither(Either::Left(two_id.clone())));
-            reg[&one_id].also_known_as = Some(aka.clone());
+                .insert(AlsoKnownAsEither(Either::Left(two.id.clone())));
+            one.also_known_as = Some(aka);
 
-            let aka = &mut reg[&two_id].also_known_as.clone().unwrap();
+            let mut aka = two.also_known_as.unwrap_or_default();
             aka.0
-                .insert(AlsoKnownAsEither(Either::Left(one_id.clone())));
-            reg[&two_id].also_known_as = Some(aka.clone());
+                .insert(AlsoKnownAsEither(Either::Left(one.id.clone())));
+            two.also_known_as = Some(aka);
+
+            reg.update(one)?;
+            reg.update(two)?;
         }
+
+        Ok(())
     }
 
     pub fn generate_verification_method(
@@ -301,16 +437,15 @@ mod util {
             None => {
                 // this complies with the character limitations in the spec. Create an array of all
                 // the valid characters, then select them randomly. Probably could be done better.
-                let mut bytes: Vec<u8> = Vec::from((0x61..=0x7a).collect::<Vec<u8>>());
-                bytes.append(&mut Vec::from(
-                    ('0'..'9').map(|a| a as u8).collect::<Vec<u8>>(),
-                ));
+                let mut bytes: Vec<u8> = (0x61..=0x7a).collect::<Vec<u8>>();
+                bytes.append(&mut ('0'..='9').map(|a| a as u8).collect::<Vec<u8>>());
 
                 let mut v = Vec::new();
 
-                for _ in 0..rng.random_range(0..max_len) {
+                // method names and ids may not be empty
+                for _ in 0..rng.random_range(1..=max_len.max(1)) {
                     let idx = rng.random_range(0..bytes.len());
-                    v.push(bytes.get(idx).unwrap().clone());
+                    v.push(*bytes.get(idx).unwrap());
                 }
 
                 v
@@ -321,8 +456,8 @@ mod util {
         chars.fill(&mut rng);
 
         let mut method_id = Vec::new();
-        for x in 0..rng.random_range(0..max_len) {
-            method_id.push(chars[x]);
+        for c in chars.iter().take(rng.random_range(1..=max_len.max(1))) {
+            method_id.push(*c);
         }
 
         Ok(DID {
@@ -0,0 +1,604 @@
+use crate::{did::DID, document::Document};
+use anyhow::anyhow;
+use serde::{de::Visitor, Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{cmp::Ordering, fmt::Display, sync::Arc};
+
+/// MerkleHash is a SHA-256 hash within a [MerkleTree]. It displays and serializes as lowercase
+/// hex.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MerkleHash(pub [u8; 32]);
+
+/// The root of an empty [MerkleTree], and the hash of an empty subtree within one.
+pub const EMPTY: MerkleHash = MerkleHash([0; 32]);
+
+impl Display for MerkleHash {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&multibase::Base::Base16Lower.encode(self.0))
+    }
+}
+
+impl std::str::FromStr for MerkleHash {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let bytes = multibase::Base::Base16Lower
+            .decode(s)
+            .map_err(|e| anyhow!("Merkle hash {} is not hex: {}", s, e))?;
+        Ok(MerkleHash(bytes.try_into().map_err(|_| {
+            anyhow!("Merkle hash {} is not 32 bytes long", s)
+        })?))
+    }
+}
+
+impl Serialize for MerkleHash {
+    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: serde::Serializer,
+    {
+        serializer.serialize_str(&self.to_string())
+    }
+}
+
+impl Visitor<'_> for MerkleHash {
+    type Value = MerkleHash;
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("Expecting a hex-encoded SHA-256 hash")
+    }
+
+    fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
+    where
+        E: serde::de::Error,
+    {
+        v.parse().map_err(E::custom)
+    }
+}
+
+impl<'de> Deserialize<'de> for MerkleHash {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: serde::Deserializer<'de>,
+    {
+        deserializer.deserialize_str::<MerkleHash>(Default::default())
+    }
+}
+
+// the hash committing to a document: its DID, and the digest of its canonical JSON. Leaves and
+// nodes are prefixed differently so that one cannot be passed off as the other.
+fn leaf(did: &DID, digest: &MerkleHash) -> MerkleHash {
+    let did = did.to_string();
+    let mut hasher = Sha256::new();
+    hasher.update([0]);
+    hasher.update((did.len() as u64).to_be_bytes());
+    hasher.update(did.as_bytes());
+    hasher.update(digest.0);
+    MerkleHash(hasher.finalize().into())
+}
+
+fn node(left: &MerkleHash, leaf: &MerkleHash, right: &MerkleHash) -> MerkleHash {
+    let mut hasher = Sha256::new();
+    hasher.update([1]);
+    hasher.update(left.0);
+    hasher.update(leaf.0);
+    hasher.update(right.0);
+    MerkleHash(hasher.finalize().into())
+}
+
+#[derive(Clone, Debug)]
+struct Node {
+    did: DID,
+    digest: MerkleHash,
+    // orders the nodes as a heap, so the shape of the tree depends only on its contents
+    priority: [u8; 32],
+    hash: MerkleHash,
+    left: Option<Arc<Node>>,
+    right: Option<Arc<Node>>,
+}
+
+fn hash_of(node: &Option<Arc<Node>>) -> MerkleHash {
+    node.as_ref().map(|node| node.hash).unwrap_or(EMPTY)
+}
+
+impl Node {
+    fn new(did: DID, digest: MerkleHash) -> Self {
+        let priority = Sha256::digest(did.to_string().as_bytes()).into();
+        let mut node = Node {
+            did,
+            digest,
+            priority,
+            hash: EMPTY,
+            left: None,
+            right: None,
+        };
+        node.rehash();
+        node
+    }
+
+    fn rehash(&mut self) {
+        self.hash = node(
+            &hash_of(&self.left),
+            &leaf(&self.did, &self.digest),
+            &hash_of(&self.right),
+        );
+    }
+
+    fn with_children(&self, left: Option<Arc<Node>>, right: Option<Arc<Node>>) -> Arc<Node> {
+        let mut node = Node {
+            left,
+            right,
+            ..self.clone()
+        };
+        node.rehash();
+        Arc::new(node)
+    }
+
+    fn above(&self, other: &Node) -> bool {
+        (self.priority, &self.did) > (other.priority, &other.did)
+    }
+}
+
+// split a subtree into the nodes ordered before and after a DID, which it must not contain
+fn split(tree: &Option<Arc<Node>>, did: &DID) -> (Option<Arc<Node>>, Option<Arc<Node>>) {
+    match tree {
+        None => (None, None),
+        Some(node) => {
+            if did < &node.did {
+                let (left, right) = split(&node.left, did);
+                (left, Some(node.with_children(right, node.right.clone())))
+            } else {
+                let (left, right) = split(&node.right, did);
+                (Some(node.with_children(node.left.clone(), left)), right)
+            }
+        }
+    }
+}
+
+// join two subtrees, all of whose DIDs in the first are ordered before those in the second
+fn join(left: &Option<Arc<Node>>, right: &Option<Arc<Node>>) -> Option<Arc<Node>> {
+    match (left, right) {
+        (None, tree) | (tree, None) => tree.clone(),
+        (Some(l), Some(r)) => {
+            if l.above(r) {
+                Some(l.with_children(l.left.clone(), join(&l.right, right)))
+            } else {
+                Some(r.with_children(join(left, &r.left), r.right.clone()))
+            }
+        }
+    }
+}
+
+fn insert(tree: &Option<Arc<Node>>, new: Node) -> Arc<Node> {
+    let node = match tree {
+        None => return Arc::new(new),
+        Some(node) => node,
+    };
+
+    match new.did.cmp(&node.did) {
+        Ordering::Equal => {
+            let mut node = Node {
+                digest: new.digest,
+                ..node.as_ref().clone()
+            };
+            node.rehash();
+            Arc::new(node)
+        }
+        _ if new.above(node) => {
+            let (left, right) = split(tree, &new.did);
+            new.with_children(left, right)
+        }
+        Ordering::Less => node.with_children(Some(insert(&node.left, new)), node.right.clone()),
+        Ordering::Greater => node.with_children(node.left.clone(), Some(insert(&node.right, new))),
+    }
+}
+
+fn remove(tree: &Option<Arc<Node>>, did: &DID) -> Option<Option<Arc<Node>>> {
+    let node = tree.as_ref()?;
+
+    Some(match did.cmp(&node.did) {
+        Ordering::Equal => join(&node.left, &node.right),
+        Ordering::Less => Some(node.with_children(remove(&node.left, did)?, node.right.clone())),
+        Ordering::Greater => Some(node.with_children(node.left.clone(), remove(&node.right, did)?)),
+    })
+}
+
+/// MerkleTree commits to the documents of a [crate::registry::Registry] with a single
+/// [MerkleHash], its root, which can be published so that third parties can later check with a
+/// [MerkleProof] that a document was, or was not, in the registry at that time. Enable it with
+/// [crate::registry::Registry::enable_merkle_tree]; it is updated as documents are inserted,
+/// updated and removed, in time logarithmic in the size of the registry. Tombstones and the remote
+/// cache are not included.
+///
+/// Each document is committed to by its [DID] and the SHA-256 digest of its
+/// [Document::canonical_json]. The tree is a binary search tree ordered by [DID], so that an
+/// in-order walk visits the documents as [crate::registry::Registry::iter] does. Its shape is
+/// balanced by a priority derived from each [DID], which makes it depend only on the documents it
+/// holds, and not on the order they were inserted in. The hash of each node is the SHA-256 hash of
+/// a `1` byte followed by the hash of its left subtree, the hash of its document and the hash of
+/// its right subtree, with [EMPTY] for a missing subtree. The hash of a document is the SHA-256
+/// hash of a `0` byte, the length of its [DID] as a big-endian 64-bit integer, the [DID] and the
+/// digest of the document.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let mut reg = Registry::default();
+/// reg.enable_merkle_tree().unwrap();
+///
+/// let alice = DID::parse("did:mymethod:alice").unwrap();
+/// let bob = DID::parse("did:mymethod:bob").unwrap();
+/// reg.insert(Document{ id: alice.clone(), ..Default::default() }).unwrap();
+///
+/// let tree = reg.merkle_tree().unwrap();
+/// let root = tree.root();
+///
+/// let proof = tree.prove(&alice);
+/// assert!(proof.verify_inclusion(&root, &reg[&alice]).is_ok());
+///
+/// let proof = tree.prove(&bob);
+/// assert!(proof.verify_exclusion(&root).is_ok());
+/// assert!(proof.verify_inclusion(&root, &Document{ id: bob, ..Default::default() }).is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MerkleTree {
+    root: Option<Arc<Node>>,
+    len: usize,
+}
+
+impl MerkleTree {
+    /// Build a tree over the provided documents. Fails if a document cannot be digested.
+    pub fn from_documents<'a>(
+        documents: impl IntoIterator<Item = &'a Document>,
+    ) -> Result<Self, anyhow::Error> {
+        let mut tree = Self::default();
+        for doc in documents {
+            tree.insert(doc.id.clone(), doc.digest()?);
+        }
+
+        Ok(tree)
+    }
+
+    /// The root hash, committing to every document in the tree. [EMPTY] if there are none.
+    pub fn root(&self) -> MerkleHash {
+        hash_of(&self.root)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    /// Determine if the tree holds a document for a [DID].
+    pub fn contains(&self, did: &DID) -> bool {
+        self.digest(did).is_some()
+    }
+
+    /// The digest of the document held for a [DID], as given by [Document::digest].
+    pub fn digest(&self, did: &DID) -> Option<MerkleHash> {
+        let mut next = &self.root;
+        while let Some(node) = next {
+            match did.cmp(&node.did) {
+                Ordering::Equal => return Some(node.digest),
+                Ordering::Less => next = &node.left,
+                Ordering::Greater => next = &node.right,
+            }
+        }
+
+        None
+    }
+
+    /// Prove that the tree holds a document for a [DID], or that it does not. The proof is the
+    /// path searched for the [DID] from the root; see [MerkleProof].
+    pub fn prove(&self, did: &DID) -> MerkleProof {
+        let mut path = Vec::new();
+        let mut children = None;
+        let mut next = &self.root;
+
+        while let Some(node) = next {
+            match did.cmp(&node.did) {
+                Ordering::Equal => {
+                    children = Some((hash_of(&node.left), hash_of(&node.right)));
+                    break;
+                }
+                Ordering::Less => {
+                    path.push(MerkleStep {
+                        id: node.did.clone(),
+                        digest: node.digest,
+                        sibling: hash_of(&node.right),
+                    });
+                    next = &node.left;
+                }
+                Ordering::Greater => {
+                    path.push(MerkleStep {
+                        id: node.did.clone(),
+                        digest: node.digest,
+                        sibling: hash_of(&node.left),
+                    });
+                    next = &node.right;
+                }
+            }
+        }
+
+        path.reverse();
+        MerkleProof {
+            id: did.clone(),
+            children,
+            path,
+        }
+    }
+
+    pub(crate) fn insert(&mut self, did: DID, digest: [u8; 32]) {
+        if !self.contains(&did) {
+            self.len += 1;
+        }
+
+        self.root = Some(insert(&self.root, Node::new(did, MerkleHash(digest))));
+    }
+
+    pub(crate) fn remove(&mut self, did: &DID) {
+        if let Some(root) = remove(&self.root, did) {
+            self.root = root;
+            self.len -= 1;
+        }
+    }
+}
+
+/// MerkleStep is a node on the path searched for a [DID] in a [MerkleTree], as part of a
+/// [MerkleProof].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleStep {
+    /// The [DID] of the node's document, which decides the direction of the search.
+    pub id: DID,
+    /// The digest of the node's document.
+    pub digest: MerkleHash,
+    /// The hash of the node's subtree which the search did not enter.
+    pub sibling: MerkleHash,
+}
+
+/// MerkleProof shows that a [MerkleTree] with a given root holds a document for a [DID], or does
+/// not, as produced by [MerkleTree::prove]. It can be serialized to be handed to third parties,
+/// who check it with [MerkleProof::verify_inclusion] or [MerkleProof::verify_exclusion].
+///
+/// A proof is the path searched for the [DID], with the node at each step. The path ends at the
+/// node holding the document, whose subtree hashes are given in `children`, or at an empty
+/// subtree if there is none. Verifying it recomputes the root from the bottom of the path up,
+/// following the [DID] left or right at each step by comparing it to the [DID] of the step, so a
+/// path can only arrive at the root if it is the one a search for the [DID] would take.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MerkleProof {
+    pub id: DID,
+    /// The hashes of the left and right subtrees of the node holding the document. [None] for a
+    /// proof that the tree does not hold one.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub children: Option<(MerkleHash, MerkleHash)>,
+    /// The nodes searched, from the bottom of the path to the root.
+    pub path: Vec<MerkleStep>,
+}
+
+impl MerkleProof {
+    /// Determine if the proof is one of inclusion.
+    pub fn is_inclusion(&self) -> bool {
+        self.children.is_some()
+    }
+
+    /// Check that a tree with the provided root holds the document.
+    pub fn verify_inclusion(&self, root: &MerkleHash, doc: &Document) -> Result<(), anyhow::Error> {
+        if doc.id != self.id {
+            return Err(anyhow!(
+                "Merkle proof is for DID {}, not {}",
+                self.id,
+                doc.id
+            ));
+        }
+
+        let (left, right) = match &self.children {
+            Some(children) => children,
+            None => {
+                return Err(anyhow!(
+                    "Merkle proof shows DID {} is not in the tree",
+                    self.id
+                ))
+            }
+        };
+
+        let hash = node(left, &leaf(&doc.id, &MerkleHash(doc.digest()?)), right);
+        if &self.fold(hash)? != root {
+            return Err(anyhow!(
+                "Merkle proof does not show the document of DID {} is in the tree",
+                self.id
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check that a tree with the provided root does not hold a document for the [DID].
+    pub fn verify_exclusion(&self, root: &MerkleHash) -> Result<(), anyhow::Error> {
+        if self.children.is_some() {
+            return Err(anyhow!("Merkle proof shows DID {} is in the tree", self.id));
+        }
+
+        if &self.fold(EMPTY)? != root {
+            return Err(anyhow!(
+                "Merkle proof does not show DID {} is absent from the tree",
+                self.id
+            ));
+        }
+
+        Ok(())
+    }
+
+    // compute the root from the hash at the bottom of the path
+    fn fold(&self, mut hash: MerkleHash) -> Result<MerkleHash, anyhow::Error> {
+        for step in &self.path {
+            let leaf = leaf(&step.id, &step.digest);
+            hash = match self.id.cmp(&step.id) {
+                Ordering::Less => node(&hash, &leaf, &step.sibling),
+                Ordering::Greater => node(&step.sibling, &leaf, &hash),
+                Ordering::Equal => {
+                    return Err(anyhow!(
+                        "Merkle proof for DID {} passes through its own node",
+                        self.id
+                    ))
+                }
+            };
+        }
+
+        Ok(hash)
+    }
+}
+
+mod tests {
+    #[cfg(test)]
+    fn doc(n: usize) -> crate::document::Document {
+        use crate::{did::DID, document::Document};
+
+        Document {
+            id: DID::parse(&format!("did:testing:u:{}", n)).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    // a registry with a Merkle tree, holding the documents numbered below 100 which are not
+    // multiples of 3
+    #[cfg(test)]
+    fn fixture() -> crate::registry::Registry {
+        use crate::registry::Registry;
+
+        let mut reg = Registry::default();
+        reg.enable_merkle_tree().unwrap();
+        for n in (0..100).filter(|n| n % 3 != 0) {
+            reg.insert(doc(n)).unwrap();
+        }
+
+        reg
+    }
+
+    #[test]
+    fn test_build() {
+        use super::{MerkleTree, EMPTY};
+        use crate::{document::Document, registry::Registry};
+
+        let mut reg = Registry::default();
+        reg.enable_merkle_tree().unwrap();
+        assert_eq!(reg.merkle_tree().unwrap().root(), EMPTY);
+        assert!(reg.merkle_tree().unwrap().is_empty());
+
+        for n in 0..100 {
+            reg.insert(doc(n)).unwrap();
+        }
+
+        let tree = reg.merkle_tree().unwrap();
+        assert_eq!(tree.len(), reg.len());
+        assert_ne!(tree.root(), EMPTY);
+
+        // the tree depends only on its contents
+        let mut docs: Vec<&Document> = reg.iter().map(|(_, doc)| doc).collect();
+        docs.reverse();
+        assert_eq!(
+            MerkleTree::from_documents(docs).unwrap().root(),
+            tree.root()
+        );
+
+        // and is built from the documents already there when it is enabled
+        let mut late = Registry::default();
+        for n in 0..100 {
+            late.insert(doc(n)).unwrap();
+        }
+        late.enable_merkle_tree().unwrap();
+        assert_eq!(late.merkle_tree().unwrap().root(), tree.root());
+    }
+
+    #[test]
+    fn test_proofs() {
+        use super::{MerkleProof, EMPTY};
+        use crate::document::{Controller, Document};
+        use either::Either;
+
+        let did = |n: usize| doc(n).id;
+        let reg = fixture();
+
+        let tree = reg.merkle_tree().unwrap();
+        let root = tree.root();
+
+        for n in 0..100 {
+            let proof = tree.prove(&did(n));
+            if n % 3 != 0 {
+                assert!(proof.is_inclusion());
+                proof.verify_inclusion(&root, &doc(n)).unwrap();
+                assert!(proof.verify_exclusion(&root).is_err());
+            } else {
+                proof.verify_exclusion(&root).unwrap();
+                assert!(proof.verify_inclusion(&root, &doc(n)).is_err());
+            }
+        }
+
+        // proofs survive a round trip, and fail for other documents or roots
+        let proof = tree.prove(&did(1));
+        let json = serde_json::to_string(&proof).unwrap();
+        let proof: MerkleProof = serde_json::from_str(&json).unwrap();
+        proof.verify_inclusion(&root, &doc(1)).unwrap();
+        let changed = Document {
+            controller: Some(Controller(Either::Left(did(2)))),
+            ..doc(1)
+        };
+        assert!(proof.verify_inclusion(&root, &changed).is_err());
+        assert!(proof.verify_inclusion(&EMPTY, &doc(1)).is_err());
+        assert!(proof.verify_inclusion(&root, &doc(2)).is_err());
+
+        // a proof of inclusion cannot be turned into one of exclusion
+        let mut forged = tree.prove(&did(1));
+        forged.children = None;
+        assert!(forged.verify_exclusion(&root).is_err());
+    }
+
+    #[test]
+    fn test_updates() {
+        use super::{MerkleTree, EMPTY};
+        use crate::document::{Controller, Document};
+        use either::Either;
+
+        let did = |n: usize| doc(n).id;
+        let mut reg = fixture();
+        let root = reg.merkle_tree().unwrap().root();
+
+        // changes update the root, and proofs against the old root no longer hold
+        let changed = Document {
+            controller: Some(Controller(Either::Left(did(2)))),
+            ..doc(1)
+        };
+        reg.update(changed.clone()).unwrap();
+        let updated = reg.merkle_tree().unwrap().root();
+        assert_ne!(updated, root);
+        let proof = reg.merkle_tree().unwrap().prove(&did(1));
+        assert!(proof.verify_inclusion(&root, &changed).is_err());
+        proof.verify_inclusion(&updated, &changed).unwrap();
+
+        reg.remove(&did(2));
+        reg.deactivate(&did(4)).unwrap();
+        let tree = reg.merkle_tree().unwrap();
+        assert_eq!(tree.len(), reg.len());
+        tree.prove(&did(2)).verify_exclusion(&tree.root()).unwrap();
+        tree.prove(&did(4)).verify_exclusion(&tree.root()).unwrap();
+
+        // a transaction which is rolled back leaves the tree as it was
+        let before = tree.root();
+        let mut tx = reg.transaction();
+        tx.insert(doc(3)).unwrap();
+        tx.rollback();
+        assert_eq!(reg.merkle_tree().unwrap().root(), before);
+
+        // and the incrementally updated tree matches one built from scratch
+        let rebuilt = MerkleTree::from_documents(reg.iter().map(|(_, doc)| doc)).unwrap();
+        assert_eq!(rebuilt.root(), reg.merkle_tree().unwrap().root());
+
+        for n in 0..100 {
+            reg.remove(&did(n));
+        }
+        assert_eq!(reg.merkle_tree().unwrap().root(), EMPTY);
+        assert!(reg.merkle_tree().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,501 @@
+use crate::{did::DID, registry::Version, time::VersionTime, tombstone::Tombstone};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+
+/// DocumentMetadata describes a document rather than the [DID] subject it represents, as in
+/// <https://www.w3.org/TR/did-core/#did-document-metadata>. It serializes with the camelCase
+/// names of the specification, omitting properties which are not set, in JSON and CBOR alike.
+///
+/// [crate::registry::Registry] keeps the creation and update times and the version of each
+/// document it stores, and reports them with [crate::registry::Registry::document_metadata] and
+/// [crate::registry::Registry::resolve]. `equivalentId` and `canonicalId` are asserted with
+/// [crate::registry::Registry::set_equivalent_ids] and
+/// [crate::registry::Registry::set_canonical_id].
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let mut reg = Registry::default();
+/// let did = DID::parse("did:mymethod:alice").unwrap();
+/// reg.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+///
+/// let metadata = reg.document_metadata(&did).unwrap();
+/// assert!(metadata.created.is_some());
+/// assert!(metadata.updated.is_none());
+/// assert_eq!(metadata.version_id, reg.version(&did).map(|v| v.to_string()));
+///
+/// let json = serde_json::to_value(&metadata).unwrap();
+/// assert!(json.get("versionId").is_some());
+/// assert!(json.get("deactivated").is_none());
+/// ```
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DocumentMetadata {
+    /// When the document was created.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub created: Option<VersionTime>,
+    /// When the document was last updated, if it has been.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub updated: Option<VersionTime>,
+    /// Whether the [DID] has been deactivated.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub deactivated: Option<bool>,
+    /// When the next update to the document is scheduled, if it is known.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub next_update: Option<VersionTime>,
+    /// The version of the document, as given in the `versionId` parameter of a DID
+    /// [crate::url::URL].
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub version_id: Option<String>,
+    /// The version of the next update to the document, if it is known.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub next_version_id: Option<String>,
+    /// [DID]s which the DID method asserts are equivalent to this one.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub equivalent_id: Option<BTreeSet<DID>>,
+    /// The [DID] which the DID method asserts is the canonical one for this document.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub canonical_id: Option<DID>,
+}
+
+impl DocumentMetadata {
+    /// Determine if the [DID] has been deactivated.
+    pub fn is_deactivated(&self) -> bool {
+        self.deactivated.unwrap_or_default()
+    }
+}
+
+impl From<&Tombstone> for DocumentMetadata {
+    fn from(tombstone: &Tombstone) -> Self {
+        Self {
+            updated: Some(tombstone.deactivated.clone()),
+            deactivated: Some(true),
+            version_id: Some(tombstone.version.to_string()),
+            ..Default::default()
+        }
+    }
+}
+
+/// Canonicalization determines whether [crate::registry::Registry::get] follows the
+/// `canonicalId` of a document. See [crate::registry::Registry::set_canonicalization].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Canonicalization {
+    /// Documents are retrieved by the [DID] they are stored under only.
+    #[default]
+    Ignore,
+    /// Retrieving a [DID] returns the document of its canonical [DID] instead, if the remote
+    /// cache holds it, and [DID]s named only in the `equivalentId` or `canonicalId` of a stored
+    /// document retrieve that document.
+    Follow,
+}
+
+/// Identifiers are the `equivalentId` and `canonicalId` asserted for a document in a
+/// [crate::registry::Registry], with [crate::registry::Registry::set_equivalent_ids] and
+/// [crate::registry::Registry::set_canonical_id]. They are recorded in the
+/// [crate::journal::Journal] and exported with the document.
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Identifiers {
+    #[serde(skip_serializing_if = "BTreeSet::is_empty", default)]
+    pub equivalent_id: BTreeSet<DID>,
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub canonical_id: Option<DID>,
+}
+
+impl Identifiers {
+    /// Determine if no identifiers are asserted.
+    pub fn is_empty(&self) -> bool {
+        self.equivalent_id.is_empty() && self.canonical_id.is_none()
+    }
+
+    // the DIDs other than its own which the document is known by
+    pub(crate) fn aliases(&self) -> impl Iterator<Item = &DID> {
+        self.equivalent_id.iter().chain(self.canonical_id.iter())
+    }
+}
+
+// the metadata a registry keeps for each document it stores
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct StoredMetadata {
+    pub(crate) created: VersionTime,
+    pub(crate) updated: Option<VersionTime>,
+    pub(crate) identifiers: Identifiers,
+}
+
+impl StoredMetadata {
+    pub(crate) fn new(created: VersionTime) -> Self {
+        Self {
+            created,
+            updated: None,
+            identifiers: Identifiers::default(),
+        }
+    }
+
+    pub(crate) fn metadata(&self, version: Option<Version>) -> DocumentMetadata {
+        DocumentMetadata {
+            created: Some(self.created.clone()),
+            updated: self.updated.clone(),
+            version_id: version.map(|v| v.to_string()),
+            equivalent_id: Some(self.identifiers.equivalent_id.clone())
+                .filter(|ids| !ids.is_empty()),
+            canonical_id: self.identifiers.canonical_id.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+mod tests {
+    #[cfg(test)]
+    fn doc(did: &str) -> crate::document::Document {
+        use crate::{did::DID, document::Document};
+
+        Document {
+            id: DID::parse(did).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    // a journaled registry holding the documents of did:testing:u:alice:long and
+    // did:testing:u:bob, for asserting identifiers of
+    #[cfg(test)]
+    fn fixture() -> crate::registry::Registry {
+        use crate::registry::Registry;
+
+        let mut reg = Registry::default();
+        reg.enable_journal();
+        reg.insert(doc("did:testing:u:alice:long")).unwrap();
+        reg.insert(doc("did:testing:u:bob")).unwrap();
+
+        reg
+    }
+
+    #[test]
+    fn test_document_metadata() {
+        use crate::{
+            document::Controller, registry::Registry, time::VersionTime, tombstone::Resurrection,
+        };
+
+        let doc = doc("did:testing:u:alice");
+        let did = doc.id.clone();
+
+        let mut reg = Registry::default();
+        assert!(reg.document_metadata(&did).is_none());
+        let before = VersionTime::now();
+        reg.insert(doc.clone()).unwrap();
+
+        let inserted = reg.document_metadata(&did).unwrap();
+        assert!(inserted.created.as_ref().unwrap() >= &before);
+        assert!(inserted.updated.is_none());
+        assert!(!inserted.is_deactivated());
+        assert_eq!(
+            inserted.version_id,
+            Some(reg.version(&did).unwrap().to_string())
+        );
+
+        // unchanged documents are not updated
+        reg.update(doc.clone()).unwrap();
+        assert_eq!(reg.document_metadata(&did).unwrap(), inserted);
+
+        let mut changed = doc.clone();
+        changed.controller = Some(Controller(either::Either::Left(did.clone())));
+        reg.update(changed).unwrap();
+        let updated = reg.resolve(&did).unwrap().metadata;
+        assert_eq!(updated.created, inserted.created);
+        assert!(updated.updated.is_some());
+        assert_ne!(updated.version_id, inserted.version_id);
+
+        // deactivation is reported in place of the document's own metadata
+        reg.deactivate(&did).unwrap();
+        let deactivated = reg.document_metadata(&did).unwrap();
+        assert!(deactivated.is_deactivated());
+        assert!(deactivated.created.is_none());
+        assert_eq!(
+            deactivated.version_id,
+            Some(reg.tombstone(&did).unwrap().version.to_string())
+        );
+
+        // and a resurrected DID starts afresh
+        reg.set_resurrection(Resurrection::Allow);
+        reg.insert(doc).unwrap();
+        let resurrected = reg.document_metadata(&did).unwrap();
+        assert!(resurrected.updated.is_none());
+        assert!(resurrected.deactivated.is_none());
+    }
+
+    #[test]
+    fn test_document_metadata_serde() {
+        use super::DocumentMetadata;
+        use crate::{did::DID, time::VersionTime};
+        use std::collections::BTreeSet;
+
+        // the names of the specification are used, and what is not set is omitted
+        let metadata = DocumentMetadata {
+            created: Some(VersionTime::parse("2023-01-01T00:00:00Z").unwrap()),
+            next_update: Some(VersionTime::parse("2023-02-01T00:00:00.5Z").unwrap()),
+            deactivated: Some(false),
+            version_id: Some("1".to_string()),
+            next_version_id: Some("2".to_string()),
+            equivalent_id: Some(BTreeSet::from([DID::parse("did:testing:u:bob").unwrap()])),
+            canonical_id: Some(DID::parse("did:testing:u:carol").unwrap()),
+            ..Default::default()
+        };
+        assert_eq!(
+            serde_json::to_value(&metadata).unwrap(),
+            serde_json::json!({
+                "created": "2023-01-01T00:00:00Z",
+                "deactivated": false,
+                "nextUpdate": "2023-02-01T00:00:00.5Z",
+                "versionId": "1",
+                "nextVersionId": "2",
+                "equivalentId": ["did:testing:u:bob"],
+                "canonicalId": "did:testing:u:carol",
+            })
+        );
+        assert_eq!(
+            serde_json::from_str::<DocumentMetadata>(&serde_json::to_string(&metadata).unwrap())
+                .unwrap(),
+            metadata
+        );
+        assert_eq!(
+            serde_json::from_str::<DocumentMetadata>("{}").unwrap(),
+            DocumentMetadata::default()
+        );
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&metadata, &mut cbor).unwrap();
+        assert_eq!(
+            ciborium::from_reader::<DocumentMetadata, _>(cbor.as_slice()).unwrap(),
+            metadata
+        );
+    }
+
+    #[test]
+    fn test_canonical_ids() {
+        let did = |s: &str| doc(s).id;
+        let long = did("did:testing:u:alice:long");
+        let short = did("did:testing:u:alice");
+        let other = did("did:testing:u:alice:other");
+
+        // identifiers can only be asserted for documents in the registry
+        let mut reg = fixture();
+        assert!(reg
+            .set_canonical_id(&did("did:testing:u:carol"), Some(short.clone()))
+            .is_err());
+
+        // identifiers must be of the same method, and asserted for one document only
+        assert!(reg
+            .set_equivalent_ids(&long, [did("did:elsewhere:alice")])
+            .is_err());
+        reg.set_canonical_id(&long, Some(short.clone())).unwrap();
+        reg.set_equivalent_ids(&long, [other.clone(), long.clone()])
+            .unwrap();
+        assert!(reg
+            .set_canonical_id(&did("did:testing:u:bob"), Some(short.clone()))
+            .is_err());
+
+        // aliases and stored documents may not share a DID, whichever comes first
+        assert!(reg
+            .set_equivalent_ids(&long, [did("did:testing:u:bob")])
+            .is_err());
+        assert!(reg.insert(doc("did:testing:u:alice")).is_err());
+        assert!(reg.insert(doc("did:testing:u:alice:other")).is_err());
+        assert!(reg.get_arc(&short).is_none());
+
+        let metadata = reg.document_metadata(&long).unwrap();
+        assert_eq!(metadata.canonical_id, Some(short.clone()));
+        assert_eq!(
+            metadata
+                .equivalent_id
+                .unwrap()
+                .into_iter()
+                .collect::<Vec<_>>(),
+            vec![other.clone()]
+        );
+
+        for id in [&long, &short, &other] {
+            assert_eq!(reg.canonical_did(id), Some(short.clone()));
+        }
+        assert_eq!(
+            reg.canonical_did(&did("did:testing:u:bob")),
+            Some(did("did:testing:u:bob"))
+        );
+        assert!(reg.canonical_did(&did("did:testing:u:carol")).is_none());
+
+        assert!(reg.same_subject(&long, &other));
+        assert!(reg.same_subject(&other, &short));
+        assert!(!reg.same_subject(&long, &did("did:testing:u:bob")));
+        assert!(!reg.same_subject(&did("did:testing:u:carol"), &did("did:testing:u:dave")));
+    }
+
+    #[test]
+    fn test_canonicalization() {
+        use super::Canonicalization;
+        use crate::{
+            document::{
+                Document, VerificationMethod, VerificationMethodEither, VerificationMethods,
+            },
+            jwk::JWK,
+            url::URL,
+        };
+        use either::Either;
+
+        let did = |s: &str| doc(s).id;
+        let long = did("did:testing:u:alice:long");
+        let short = did("did:testing:u:alice");
+        let other = did("did:testing:u:alice:other");
+
+        let mut reg = fixture();
+        let key = URL::parse("did:testing:u:alice:long#key-1").unwrap();
+        reg.update(Document {
+            verification_method: Some(
+                [VerificationMethod {
+                    id: key.clone(),
+                    controller: long.clone(),
+                    public_key_jwk: Some(JWK::new().unwrap()),
+                    ..Default::default()
+                }]
+                .into(),
+            ),
+            authentication: Some(VerificationMethods(
+                [VerificationMethodEither(Either::Right(key))].into(),
+            )),
+            ..doc("did:testing:u:alice:long")
+        })
+        .unwrap();
+        reg.set_canonical_id(&long, Some(short.clone())).unwrap();
+        reg.set_equivalent_ids(&long, [other.clone()]).unwrap();
+
+        // redirection is only followed when asked for
+        assert!(reg.get(&short).is_none());
+        reg.set_canonicalization(Canonicalization::Follow);
+        assert_eq!(reg.get(&short).unwrap().id, long);
+        assert_eq!(reg.get(&other).unwrap().id, long);
+        assert_eq!(
+            reg.resolve(&short).unwrap().metadata.canonical_id,
+            Some(short.clone())
+        );
+
+        // but documents are only ever found under their own DID by the registry itself
+        assert!(reg.get_arc(&short).is_none());
+        assert_eq!(reg.get_arc(&long).unwrap().id, long);
+
+        assert!(reg.audit().is_empty());
+
+        // identifiers are updated in place, and forgotten with the document
+        reg.set_canonical_id(&long, None).unwrap();
+        assert_eq!(reg.get(&long).unwrap().id, long);
+        assert!(!reg.same_subject(&long, &short));
+        reg.remove(&long);
+        assert!(reg.canonical_did(&other).is_none());
+        reg.set_canonical_id(&did("did:testing:u:bob"), Some(other.clone()))
+            .unwrap();
+        assert_eq!(reg.get(&other).unwrap().id, did("did:testing:u:bob"));
+    }
+
+    #[test]
+    fn test_set_identifiers() {
+        use super::Identifiers;
+        use crate::{
+            events::{Event, EventKind},
+            journal::Operation,
+        };
+        use std::sync::{Arc, Mutex};
+
+        let long = doc("did:testing:u:alice:long").id;
+        let short = doc("did:testing:u:alice").id;
+
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let seen = events.clone();
+
+        let mut reg = fixture();
+        reg.subscribe(move |event: &Event| seen.lock().unwrap().push(event.kind));
+        let version = reg.version(&long).unwrap();
+
+        // identifiers are changed like documents: checked, versioned, journaled and sent out
+        let veto = reg.add_hook(|event: &Event| match event.kind {
+            EventKind::Identified => Err(anyhow::anyhow!("no")),
+            _ => Ok(()),
+        });
+        assert!(reg.set_canonical_id(&long, Some(short.clone())).is_err());
+        assert!(reg.identifiers(&long).unwrap().is_empty());
+        reg.unsubscribe(veto);
+
+        reg.set_canonical_id(&long, Some(short.clone())).unwrap();
+        assert!(reg.version(&long).unwrap() > version);
+        assert!(reg.document_metadata(&long).unwrap().updated.is_some());
+        assert_eq!(*events.lock().unwrap(), vec![EventKind::Identified]);
+
+        // unchanged identifiers are not a change
+        let version = reg.version(&long).unwrap();
+        reg.set_canonical_id(&long, Some(short.clone())).unwrap();
+        assert_eq!(reg.version(&long), Some(version));
+
+        let journal = reg.journal().unwrap();
+        let entry = journal.entries().last().unwrap();
+        assert_eq!(entry.operation, Operation::Identify);
+        assert_eq!(
+            entry.identifiers,
+            Some(Identifiers {
+                canonical_id: Some(short.clone()),
+                ..Default::default()
+            })
+        );
+        assert!(reg.verify_journal().is_ok());
+        assert_eq!(
+            journal.replay().unwrap().identifiers(&long),
+            reg.identifiers(&long)
+        );
+    }
+
+    #[test]
+    fn test_identifiers_carried() {
+        use crate::{
+            export::ExportFormat,
+            registry::Registry,
+            replication::{ChannelFeed, Replicator},
+            shared::SharedRegistry,
+        };
+        use std::sync::Arc;
+
+        let did = |s: &str| doc(s).id;
+        let long = did("did:testing:u:alice:long");
+        let short = did("did:testing:u:alice");
+
+        let mut reg = fixture();
+        let before = reg.clone();
+        reg.set_canonical_id(&long, Some(short.clone())).unwrap();
+
+        // they are compared, exported and replicated with the document
+        let diff = before.diff(&reg);
+        assert_eq!(diff.changed.len(), 1);
+        assert_eq!(diff.changed[0].properties[0].property, "canonicalId");
+        assert_eq!(
+            diff.changed[0].properties[0].right,
+            Some(serde_json::json!("did:testing:u:alice"))
+        );
+
+        let mut buf = Vec::new();
+        reg.export(ExportFormat::JsonLines, &mut buf).unwrap();
+        let mut imported = Registry::default();
+        imported
+            .import(ExportFormat::JsonLines, buf.as_slice())
+            .unwrap();
+        assert_eq!(imported.identifiers(&long), reg.identifiers(&long));
+        assert_eq!(imported.canonical_did(&short), Some(short.clone()));
+
+        let source = Arc::new(SharedRegistry::from(reg));
+        let mut replica = Registry::default();
+        let mut replicator = Replicator::new(ChannelFeed::serve(source.clone()));
+        replicator.pull(&mut replica).unwrap();
+        assert_eq!(
+            replica.identifiers(&long),
+            source.snapshot().identifiers(&long)
+        );
+
+        source
+            .write(|reg| reg.set_equivalent_ids(&long, [did("did:testing:u:alice:other")]))
+            .unwrap();
+        assert_eq!(replicator.pull(&mut replica).unwrap(), 1);
+        assert!(replica.same_subject(&long, &did("did:testing:u:alice:other")));
+    }
+}
@@ -0,0 +1,305 @@
+use crate::{
+    did::DID,
+    events::{Event, Hook},
+};
+use std::{collections::BTreeMap, sync::Arc};
+
+/// Retention determines which documents a [crate::registry::Registry] keeps for the [DID]s of a
+/// method. See [MethodPolicy].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Retention {
+    /// Documents may be inserted, and fetched into the remote cache.
+    #[default]
+    Store,
+    /// Documents may only be fetched into the remote cache; inserting them fails.
+    Cache,
+    /// Documents are never kept. Inserting them fails, and fetched documents are returned without
+    /// being cached, so they are resolved afresh each time.
+    Resolve,
+    /// Documents are refused altogether: inserting or fetching them fails.
+    Refuse,
+}
+
+impl Retention {
+    /// Determine if documents may be inserted into the registry.
+    pub fn stores(&self) -> bool {
+        *self == Retention::Store
+    }
+
+    /// Determine if fetched documents may be kept in the remote cache.
+    pub fn caches(&self) -> bool {
+        matches!(self, Retention::Store | Retention::Cache)
+    }
+}
+
+/// MethodPolicy governs the documents of one DID method in a [crate::registry::Registry]: which
+/// of them it keeps, as given by its [Retention], and [Hook]s which validate changes to them.
+/// The hooks are consulted before the registry's own, for every change to a [DID] of the method,
+/// and may veto it. Set one with [crate::registry::Registry::set_method_policy].
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let mut reg = Registry::default();
+/// reg.set_method_policy("key", MethodPolicy::new(Retention::Resolve)).unwrap();
+/// reg.set_method_policy(
+///   "web",
+///   MethodPolicy::default().with_hook(|event: &Event| match &event.after {
+///     Some(doc) if doc.verification_method.is_none() => {
+///       Err(anyhow::anyhow!("{} has no verification methods", doc.id))
+///     }
+///     _ => Ok(()),
+///   }),
+/// ).unwrap();
+///
+/// let did = DID::parse("did:key:z6Mkfriq1MqLBoPWecGoDLjguo1sB9brj6wT3qZ5BxkKpuP6").unwrap();
+/// assert!(reg.insert(Document{ id: did, ..Default::default() }).is_err());
+///
+/// let did = DID::parse("did:web:example.org").unwrap();
+/// assert!(reg.insert(Document{ id: did.clone(), ..Default::default() }).is_err());
+///
+/// let did = DID::parse("did:mymethod:alice").unwrap();
+/// reg.insert(Document{ id: did, ..Default::default() }).unwrap();
+/// assert_eq!(reg.method_stats()["mymethod"].documents, 1);
+/// ```
+#[derive(Clone, Default)]
+pub struct MethodPolicy {
+    pub retention: Retention,
+    hooks: Vec<Arc<dyn Hook>>,
+}
+
+impl std::fmt::Debug for MethodPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MethodPolicy")
+            .field("retention", &self.retention)
+            .field("hooks", &self.hooks.len())
+            .finish()
+    }
+}
+
+impl MethodPolicy {
+    /// Create a policy with the provided [Retention], and no hooks.
+    pub fn new(retention: Retention) -> Self {
+        Self {
+            retention,
+            hooks: Vec::new(),
+        }
+    }
+
+    /// Add a [Hook] which validates changes to documents of the method.
+    pub fn with_hook(mut self, hook: impl Hook + 'static) -> Self {
+        self.hooks.push(Arc::new(hook));
+        self
+    }
+
+    pub(crate) fn check(&self, event: &Event) -> Result<(), anyhow::Error> {
+        for hook in &self.hooks {
+            hook.check(event)?;
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn has_hooks(&self) -> bool {
+        !self.hooks.is_empty()
+    }
+}
+
+/// MethodStats counts what a [crate::registry::Registry] holds for one DID method. See
+/// [crate::registry::Registry::method_stats].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MethodStats {
+    /// Documents inserted into the registry.
+    pub documents: usize,
+    /// Documents in the remote cache, whether or not they are fresh.
+    pub cached: usize,
+    /// Deactivated [DID]s.
+    pub deactivated: usize,
+}
+
+// the name of the method of a DID, e.g. web for did:web:example.org
+pub(crate) fn method_name(did: &DID) -> String {
+    String::from_utf8_lossy(&did.name).to_string()
+}
+
+/// Namespaces holds the [MethodPolicy] of each DID method for a [crate::registry::Registry],
+/// and the policy for methods without one of their own.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Namespaces {
+    methods: BTreeMap<String, MethodPolicy>,
+    default: MethodPolicy,
+}
+
+impl Namespaces {
+    pub(crate) fn policy(&self, did: &DID) -> &MethodPolicy {
+        self.methods.get(&method_name(did)).unwrap_or(&self.default)
+    }
+
+    pub(crate) fn set(&mut self, method: &str, policy: MethodPolicy) {
+        self.methods.insert(method.to_string(), policy);
+    }
+
+    pub(crate) fn set_default(&mut self, policy: MethodPolicy) {
+        self.default = policy;
+    }
+}
+
+mod tests {
+    #[cfg(test)]
+    fn doc(did: &str) -> crate::document::Document {
+        use crate::{did::DID, document::Document};
+
+        Document {
+            id: DID::parse(did).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    // a registry which fetches each provided (name, DID) document from
+    // https://example.org/<name>.json, and which resolves did:key, caches did:peer and refuses
+    // did:evil
+    #[cfg(test)]
+    fn fixture(documents: &[(&str, &str)]) -> crate::registry::Registry {
+        use super::{MethodPolicy, Retention};
+        use crate::{
+            fetch::{FetchPolicy, Fetcher, StaticTransport},
+            registry::Registry,
+        };
+        use url::Url;
+
+        let mut transport = StaticTransport::default();
+        for (name, did) in documents {
+            let url = Url::parse(&format!("https://example.org/{}.json", name)).unwrap();
+            transport.insert_document(url, &doc(did)).unwrap();
+        }
+
+        let mut reg = Registry::new_with_fetcher(Fetcher::with_transport(
+            FetchPolicy::default(),
+            Box::new(transport),
+        ));
+        reg.set_method_policy("key", MethodPolicy::new(Retention::Resolve))
+            .unwrap();
+        reg.set_method_policy("peer", MethodPolicy::new(Retention::Cache))
+            .unwrap();
+        reg.set_method_policy("evil", MethodPolicy::new(Retention::Refuse))
+            .unwrap();
+
+        reg
+    }
+
+    #[test]
+    fn test_insert() {
+        use super::{MethodPolicy, Retention};
+        use crate::events::Event;
+
+        let mut reg = fixture(&[]);
+        reg.set_method_policy(
+            "web",
+            MethodPolicy::default().with_hook(|event: &Event| {
+                if event.id.id.starts_with(b"example.org") {
+                    Ok(())
+                } else {
+                    Err(anyhow::anyhow!("{} is not ours", event.id))
+                }
+            }),
+        )
+        .unwrap();
+
+        // only methods which may be stored can be inserted, and only if their hooks agree
+        assert!(reg.insert(doc("did:key:z6Mkalice")).is_err());
+        assert!(reg.insert(doc("did:peer:0z6Mkbob")).is_err());
+        assert!(reg.insert(doc("did:evil:mallory")).is_err());
+        reg.insert(doc("did:web:example.org:alice")).unwrap();
+        let err = reg.insert(doc("did:web:example.com")).unwrap_err();
+        assert!(err.to_string().contains("is not ours"));
+        reg.insert(doc("did:testing:u:alice")).unwrap();
+        assert_eq!(
+            reg.method_policy(&doc("did:testing:u:bob").id).retention,
+            Retention::Store
+        );
+
+        // an allow list, by refusing everything without a policy of its own, once nothing is
+        // stored which it would refuse
+        assert!(reg
+            .set_default_method_policy(MethodPolicy::new(Retention::Refuse))
+            .is_err());
+        reg.remove(&doc("did:testing:u:alice").id);
+        reg.set_default_method_policy(MethodPolicy::new(Retention::Refuse))
+            .unwrap();
+        assert!(reg.insert(doc("did:testing:u:bob")).is_err());
+        reg.insert(doc("did:web:example.org:bob")).unwrap();
+    }
+
+    #[test]
+    fn test_remote() {
+        use super::MethodStats;
+        use crate::did::DID;
+        use url::Url;
+
+        let url = |name: &str| Url::parse(&format!("https://example.org/{}.json", name)).unwrap();
+        let mut reg = fixture(&[
+            ("key", "did:key:z6Mkalice"),
+            ("peer", "did:peer:0z6Mkbob"),
+            ("web", "did:web:example.org"),
+            ("evil", "did:evil:mallory"),
+        ]);
+
+        // documents resolved on the fly are returned but not kept
+        let did = DID::parse("did:key:z6Mkalice").unwrap();
+        assert_eq!(reg.cache_document(url("key")).unwrap().id, did);
+        assert!(reg.get(&did).is_none());
+        let did = DID::parse("did:peer:0z6Mkbob").unwrap();
+        assert_eq!(reg.cache_document(url("peer")).unwrap().id, did);
+        assert!(reg.get(&did).is_some());
+        assert!(reg.cache_document(url("evil")).is_err());
+        reg.cache_document(url("web")).unwrap();
+        reg.insert(doc("did:web:example.org:alice")).unwrap();
+
+        let stats = reg.method_stats();
+        assert_eq!(stats.len(), 2);
+        assert!(!stats.contains_key("key"));
+        assert_eq!(
+            stats["web"],
+            MethodStats {
+                documents: 1,
+                cached: 1,
+                deactivated: 0
+            }
+        );
+        assert_eq!(stats["peer"].cached, 1);
+        reg.deactivate(&DID::parse("did:web:example.org:alice").unwrap())
+            .unwrap();
+        assert_eq!(reg.method_stats()["web"].deactivated, 1);
+    }
+
+    #[test]
+    fn test_change_policy() {
+        use super::{MethodPolicy, Retention};
+        use crate::did::DID;
+        use url::Url;
+
+        let url = Url::parse("https://example.org/peer.json").unwrap();
+        let mut reg = fixture(&[("peer", "did:peer:0z6Mkbob")]);
+        reg.insert(doc("did:web:example.org")).unwrap();
+        reg.cache_document(url).unwrap();
+
+        // policies which would orphan stored documents are refused, and cached documents which
+        // may no longer be kept are evicted
+        assert!(reg
+            .set_method_policy("web", MethodPolicy::new(Retention::Cache))
+            .is_err());
+        assert_eq!(
+            reg.method_policy(&doc("did:web:x").id).retention,
+            Retention::Store
+        );
+        assert!(reg
+            .set_default_method_policy(MethodPolicy::new(Retention::Resolve))
+            .is_err());
+
+        let did = DID::parse("did:peer:0z6Mkbob").unwrap();
+        assert!(reg.get(&did).is_some());
+        reg.set_method_policy("peer", MethodPolicy::new(Retention::Resolve))
+            .unwrap();
+        assert!(reg.get(&did).is_none());
+    }
+}
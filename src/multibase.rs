@@ -12,6 +12,18 @@ use serde::{de::Visitor, Deserialize, Serialize};
 #[derive(Clone, Debug, Default, Hash, PartialOrd, Ord, PartialEq, Eq)]
 pub struct MultiBase(Vec<u8>);
 
+impl MultiBase {
+    /// Create a [MultiBase] from raw key bytes.
+    pub fn new(bytes: Vec<u8>) -> Self {
+        MultiBase(bytes)
+    }
+
+    /// Retrieve the raw key bytes.
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+}
+
 impl Serialize for MultiBase {
     fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
     where
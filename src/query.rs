@@ -0,0 +1,290 @@
+use crate::{
+    did::DID,
+    document::{Document, ServiceType, VerificationMethodType, VerificationRelationship},
+};
+use std::collections::BTreeSet;
+
+/// A single predicate over a [Document]. Filters compose with [Filter::And], [Filter::Or] and
+/// [Filter::Not]; most users will want to build them with [Query] instead.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Filter {
+    /// The [DID]'s method name matches exactly.
+    Method(Vec<u8>),
+    /// The [DID]'s method name matches exactly, and its method-specific ID starts with the
+    /// provided one.
+    Prefix(DID),
+    /// The verification relationship is present and not empty.
+    Relationship(VerificationRelationship),
+    /// Any embedded verification method is of the provided type.
+    VerificationMethodType(VerificationMethodType),
+    /// Any service is of the provided type.
+    ServiceType(ServiceType),
+    /// The provided [DID] is named as a controller.
+    Controller(DID),
+    And(Vec<Filter>),
+    Or(Vec<Filter>),
+    Not(Box<Filter>),
+}
+
+impl Filter {
+    /// Evaluate the filter against a [Document].
+    pub fn matches(&self, doc: &Document) -> bool {
+        match self {
+            Self::Method(name) => &doc.id.name == name,
+            Self::Prefix(prefix) => has_prefix(&doc.id, prefix),
+            Self::Relationship(rel) => doc
+                .relationship(*rel)
+                .map(|vms| !vms.0.is_empty())
+                .unwrap_or(false),
+            Self::VerificationMethodType(typ) => {
+                doc.verification_methods().iter().any(|vm| &vm.typ == typ)
+            }
+            Self::ServiceType(typ) => doc
+                .service
+                .iter()
+                .flatten()
+                .any(|service| service.typ.types().contains(typ)),
+            Self::Controller(did) => doc
+                .controller
+                .as_ref()
+                .map(|controller| controller.dids().contains(did))
+                .unwrap_or(false),
+            Self::And(filters) => filters.iter().all(|f| f.matches(doc)),
+            Self::Or(filters) => filters.iter().any(|f| f.matches(doc)),
+            Self::Not(filter) => !filter.matches(doc),
+        }
+    }
+}
+
+/// Query is a composable set of [Filter]s to run against a [crate::registry::Registry] with
+/// [crate::registry::Registry::query]. Each builder method adds a filter which must also match;
+/// use [Query::or] and [Query::negate] to build other combinations.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let mut reg = Registry::default();
+/// reg.insert(Document{
+///   id: DID::parse("did:web:example.org").unwrap(),
+///   ..Default::default()
+/// }).unwrap();
+/// reg.insert(Document{
+///   id: DID::parse("did:key:z6Mk").unwrap(),
+///   ..Default::default()
+/// }).unwrap();
+///
+/// // all did:web documents with no authentication keys
+/// let query = Query::default()
+///     .method("web")
+///     .without_relationship(VerificationRelationship::Authentication);
+///
+/// let page = reg.query(&query, None, 10).unwrap();
+/// assert_eq!(page.documents.len(), 1);
+/// assert!(page.next.is_none());
+/// ```
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Query {
+    pub filters: Vec<Filter>,
+}
+
+impl Query {
+    /// Add an arbitrary [Filter].
+    pub fn filter(mut self, filter: Filter) -> Self {
+        self.filters.push(filter);
+        self
+    }
+
+    /// Match documents whose [DID] uses the provided method name.
+    pub fn method(self, name: &str) -> Self {
+        self.filter(Filter::Method(name.as_bytes().to_vec()))
+    }
+
+    /// Match documents whose [DID] uses the same method name as the provided one, and whose
+    /// method-specific ID starts with the provided one.
+    pub fn prefix(self, prefix: DID) -> Self {
+        self.filter(Filter::Prefix(prefix))
+    }
+
+    /// Match documents which have the provided verification relationship.
+    pub fn with_relationship(self, rel: VerificationRelationship) -> Self {
+        self.filter(Filter::Relationship(rel))
+    }
+
+    /// Match documents which do not have the provided verification relationship.
+    pub fn without_relationship(self, rel: VerificationRelationship) -> Self {
+        self.filter(Filter::Not(Box::new(Filter::Relationship(rel))))
+    }
+
+    /// Match documents which embed a verification method of the provided type.
+    pub fn verification_method_type(self, typ: VerificationMethodType) -> Self {
+        self.filter(Filter::VerificationMethodType(typ))
+    }
+
+    /// Match documents which have a service of the provided type.
+    pub fn service_type(self, typ: ServiceType) -> Self {
+        self.filter(Filter::ServiceType(typ))
+    }
+
+    /// Match documents which name the provided [DID] as a controller.
+    pub fn controller(self, did: DID) -> Self {
+        self.filter(Filter::Controller(did))
+    }
+
+    /// Match documents which match either this query or the other one.
+    pub fn or(self, other: Query) -> Self {
+        Query {
+            filters: vec![Filter::Or(vec![self.into_filter(), other.into_filter()])],
+        }
+    }
+
+    /// Match documents which do not match this query.
+    pub fn negate(self) -> Self {
+        Query {
+            filters: vec![Filter::Not(Box::new(self.into_filter()))],
+        }
+    }
+
+    /// Evaluate the query against a [Document].
+    pub fn matches(&self, doc: &Document) -> bool {
+        self.filters.iter().all(|f| f.matches(doc))
+    }
+
+    /// Collapse the query into a single [Filter].
+    pub fn into_filter(self) -> Filter {
+        Filter::And(self.filters)
+    }
+}
+
+/// A page of results from [crate::registry::Registry::query]. Results are ordered by [DID]. If
+/// `next` is set, pass it to the next call to continue where this page left off.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Page<'a> {
+    pub documents: Vec<&'a Document>,
+    pub next: Option<DID>,
+}
+
+impl<'a> Page<'a> {
+    // fill a page of at least one document from an ordered iterator of documents. `next` is only
+    // set if there is at least one more match past the end of the page.
+    pub(crate) fn fill(
+        iter: impl Iterator<Item = &'a Document>,
+        query: &Query,
+        limit: usize,
+    ) -> Self {
+        let mut page = Page::default();
+
+        for doc in iter.filter(|doc| query.matches(doc)) {
+            if page.documents.len() == limit {
+                page.next = page.documents.last().map(|doc| doc.id.clone());
+                break;
+            }
+
+            page.documents.push(doc);
+        }
+
+        page
+    }
+}
+
+// has_prefix is true if the DID is of the prefix's method and its ID starts with the prefix's.
+pub(crate) fn has_prefix(did: &DID, prefix: &DID) -> bool {
+    did.name == prefix.name && did.id.starts_with(&prefix.id)
+}
+
+// Candidates describes the set of DIDs a filter can possibly match, as narrowed down by the
+// registry's indexes. `All` means no narrowing was possible and a full scan is needed. `Prefix`
+// is a range of the registry walked in place, so it is never collected.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Candidates {
+    All,
+    Prefix(DID),
+    Set(BTreeSet<DID>),
+}
+
+impl Candidates {
+    pub(crate) fn intersect(self, other: Candidates) -> Candidates {
+        match (self, other) {
+            (Self::All, other) | (other, Self::All) => other,
+            (Self::Prefix(a), Self::Prefix(b)) => {
+                if has_prefix(&a, &b) {
+                    Self::Prefix(a)
+                } else if has_prefix(&b, &a) {
+                    Self::Prefix(b)
+                } else {
+                    Self::Set(BTreeSet::new())
+                }
+            }
+            (Self::Prefix(prefix), Self::Set(set)) | (Self::Set(set), Self::Prefix(prefix)) => {
+                Self::Set(
+                    set.into_iter()
+                        .filter(|did| has_prefix(did, &prefix))
+                        .collect(),
+                )
+            }
+            (Self::Set(a), Self::Set(b)) => Self::Set(a.intersection(&b).cloned().collect()),
+        }
+    }
+
+    pub(crate) fn union(self, other: Candidates) -> Candidates {
+        match (self, other) {
+            (Self::All, _) | (_, Self::All) => Self::All,
+            (Self::Prefix(a), Self::Prefix(b)) if has_prefix(&a, &b) => Self::Prefix(b),
+            (Self::Prefix(a), Self::Prefix(b)) if has_prefix(&b, &a) => Self::Prefix(a),
+            // a range cannot be merged with anything else without collecting it
+            (Self::Prefix(_), _) | (_, Self::Prefix(_)) => Self::All,
+            (Self::Set(mut a), Self::Set(b)) => {
+                a.extend(b);
+                Self::Set(a)
+            }
+        }
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_filters() {
+        use super::{Filter, Query};
+        use crate::{
+            did::DID,
+            document::{
+                Document, VerificationMethod, VerificationMethodEither, VerificationMethodType,
+                VerificationMethods, VerificationRelationship,
+            },
+            url::URL,
+        };
+        use either::Either;
+
+        let did = DID::parse("did:web:example.org:alice").unwrap();
+        let doc = Document {
+            id: did.clone(),
+            authentication: Some(VerificationMethods(
+                [VerificationMethodEither(Either::Left(VerificationMethod {
+                    id: URL::parse("did:web:example.org:alice#key-1").unwrap(),
+                    controller: did.clone(),
+                    typ: VerificationMethodType::Ed255192018,
+                    ..Default::default()
+                }))]
+                .into(),
+            )),
+            ..Default::default()
+        };
+
+        assert!(Filter::Method("web".into()).matches(&doc));
+        assert!(!Filter::Method("key".into()).matches(&doc));
+        assert!(Filter::Prefix(DID::parse("did:web:example.org").unwrap()).matches(&doc));
+        assert!(!Filter::Prefix(DID::parse("did:web:example.com").unwrap()).matches(&doc));
+        assert!(Query::default()
+            .with_relationship(VerificationRelationship::Authentication)
+            .without_relationship(VerificationRelationship::KeyAgreement)
+            .verification_method_type(VerificationMethodType::Ed255192018)
+            .matches(&doc));
+        assert!(!Query::default()
+            .verification_method_type(VerificationMethodType::JWK2020)
+            .matches(&doc));
+        assert!(Query::default()
+            .method("key")
+            .or(Query::default().method("web"))
+            .matches(&doc));
+        assert!(!Query::default().method("web").negate().matches(&doc));
+    }
+}
@@ -1,14 +1,38 @@
 use crate::{
+    audit::{self, Finding},
+    cache::{CacheEntry, CachePolicy, Freshness, RemoteCache},
     did::DID,
-    document::{Document, VerificationMethod},
+    diff::{Conflict, ConflictResolver, Diff, DocumentChange},
+    document::{Document, ServiceType, VerificationMethod},
+    equivalence::{EquivalenceIndex, Transitivity},
+    events::{Event, EventKind, Hook, Observers, Subscriber, Subscription},
+    export::{self, ExportFormat},
+    fetch::{FetchRequest, FetchResponse, Fetcher},
+    hashlink::HashLink,
+    index::{KeyMaterial, ReverseIndex},
+    journal::{Journal, JournalEntry, Operation},
+    merkle::MerkleTree,
+    metadata::{Canonicalization, DocumentMetadata, Identifiers, StoredMetadata},
+    method::{method_name, MethodPolicy, MethodStats, Namespaces, Retention},
+    query::{has_prefix, Candidates, Filter, Page, Query},
+    replication::ChangeBatch,
+    resource::{resource_id, Dereferenced, Resource, ResourceMetadata},
+    time::VersionTime,
+    tombstone::{Resurrection, Tombstone},
+    transaction::Transaction,
     url::URL,
 };
 use anyhow::anyhow;
 use either::Either;
+use imbl::{ordmap::DiffItem, OrdMap, Vector};
+use serde::{Deserialize, Serialize};
 use std::{
-    collections::BTreeMap,
-    ops::{Index, IndexMut},
+    borrow::Cow,
+    collections::{BTreeMap, BTreeSet},
+    ops::{Bound, Index, RangeBounds},
     path::PathBuf,
+    sync::Arc,
+    time::SystemTime,
 };
 use url::Url;
 
@@ -21,9 +45,26 @@ use url::Url;
 /// keying is automatically performed based on the [Document] `id` property.
 ///
 /// Accessing the registry is provided by a few methods in the implementation, but can also be
-/// indexed by [DID] reference or [usize]. Iterators are provided as ordered pairs via
-/// [Registry::iter]. The underlying storage is a [BTreeMap] and awareness of the performance
-/// characteristics of this implementation may be important for larger registries.
+/// indexed by [DID] reference or [usize]. Retrieving a document by position takes O(log n) time,
+/// and finding the position of a [DID], which inserts and removals also do to keep the ordering,
+/// takes O(log² n). Iterators are provided as ordered pairs via [Registry::iter] and
+/// [Registry::range], and [Cursor] walks the registry in order across changes to it. The underlying
+/// storage is a persistent ordered map from [imbl], holding each [Document] behind an [Arc];
+/// cloning a registry is cheap and shares its documents and indexes with the original until either
+/// is changed. Awareness of the performance characteristics of this implementation may be important
+/// for larger registries. See [crate::shared::SharedRegistry] for sharing a registry between
+/// threads.
+///
+/// Documents fetched through the remote cache are held apart from the ones inserted directly:
+/// they are returned by lookups such as [Registry::get] and take part in `alsoKnownAs`
+/// equivalence while they are fresh, but are not part of [Registry::iter], [Registry::len],
+/// [Registry::query] or the secondary indexes. See [Registry::cache_document] and
+/// [crate::cache::CachePolicy].
+///
+/// Documents cannot be mutated in place, as the registry maintains indexes over their contents;
+/// use [Registry::update] to replace a stored document instead. Secondary indexes allow lookup of
+/// documents by key material, service and controller without walking the registry; see
+/// [Registry::documents_by_key] and friends.
 ///
 /// There are examples in the apporpriate part of this crate which go into loading documents from
 /// disk.
@@ -53,55 +94,528 @@ use url::Url;
 /// assert_eq!(reg[&did], doc);
 /// ```
 ///
-#[derive(Default)]
+#[derive(Clone, Default)]
 pub struct Registry {
-    r: BTreeMap<DID, Document>,
-    remote_cache: bool,
+    r: OrdMap<DID, Arc<Document>>,
+    // the keys of r in order, for positional access
+    order: Vector<DID>,
+    fetcher: Option<Arc<Fetcher>>,
+    cache: RemoteCache,
+    equivalence: EquivalenceIndex,
+    index: ReverseIndex,
+    versions: OrdMap<DID, Version>,
+    stored: OrdMap<DID, StoredMetadata>,
+    // DIDs named in the equivalentId or canonicalId of stored documents, to the DID they are
+    // stored under
+    aliases: OrdMap<DID, DID>,
+    canonicalization: Canonicalization,
+    sequence: u64,
+    tombstones: OrdMap<DID, Tombstone>,
+    resurrection: Resurrection,
+    observers: Observers,
+    journal: Option<Journal>,
+    merkle: Option<MerkleTree>,
+    namespaces: Namespaces,
+    resources: OrdMap<DID, OrdMap<String, Arc<Resource>>>,
 }
 
-impl<'a> Index<&'a DID> for Registry {
-    type Output = Document;
+/// Version identifies a revision of a document stored in a [Registry]. Every change to a document
+/// is given a new version, drawn from a sequence shared by the whole registry, so a version is
+/// never reused, even if a document is removed and inserted again. Versions display as plain
+/// integers, and are suitable for use as an HTTP entity tag.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Version(pub u64);
 
-    fn index(&self, index: &'a DID) -> &Self::Output {
-        self.r.index(index)
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
     }
 }
 
-impl<'a> IndexMut<&'a DID> for Registry {
-    fn index_mut(&mut self, index: &'a DID) -> &mut Document {
-        self.r.get_mut(index).unwrap()
+impl std::str::FromStr for Version {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(Version(s.parse()?))
     }
 }
 
-impl Index<usize> for Registry {
+/// The result of resolving a [DID] with [Registry::resolve].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Resolution {
+    /// The document. For a deactivated [DID], this is a minimal document carrying only its `id`.
+    pub document: Arc<Document>,
+    /// Whether the [DID] has been deactivated. See [Registry::deactivate].
+    pub deactivated: bool,
+    /// The version of the document, or of its deactivation. Documents in the remote cache have no
+    /// version.
+    pub version: Option<Version>,
+    /// The metadata of the document. See [Registry::document_metadata].
+    pub metadata: DocumentMetadata,
+}
+
+/// The outcome of [Registry::replace] and [Registry::upsert].
+#[derive(Clone, Debug, PartialEq)]
+pub struct Replaced {
+    /// The document as it was before, or [None] if it was inserted.
+    pub previous: Option<Document>,
+    /// The version of the document as it is stored now.
+    pub version: Version,
+    /// The properties which changed, by their JSON names. See [Document::changed_properties].
+    pub changed: BTreeSet<String>,
+}
+
+impl<'a> Index<&'a DID> for Registry {
     type Output = Document;
 
-    fn index(&self, index: usize) -> &Self::Output {
-        self.r
-            .iter()
-            .nth(index)
-            .expect("invalid index dereferencing document in registry")
-            .1
+    fn index(&self, index: &'a DID) -> &Self::Output {
+        self.lookup(index)
+            .expect("invalid DID dereferencing document in registry")
+            .as_ref()
     }
 }
 
-impl IndexMut<usize> for Registry {
-    fn index_mut(&mut self, index: usize) -> &mut Document {
-        self.r
-            .iter_mut()
-            .nth(index)
+impl Index<usize> for Registry {
+    type Output = Document;
+
+    fn index(&self, index: usize) -> &Self::Output {
+        self.get_index(index)
             .expect("invalid index dereferencing document in registry")
             .1
     }
 }
 
 impl Registry {
-    /// Create a [Registry] with the remote cache enabled. Use [Registry::default] for one that
-    /// does not use the remote cache.
+    /// Create a [Registry] with the remote cache enabled, using the default
+    /// [crate::fetch::FetchPolicy]. Use [Registry::default] for one that does not use the remote
+    /// cache.
     pub fn new_with_remote_cache() -> Self {
+        Self::new_with_fetcher(Fetcher::default())
+    }
+
+    /// Create a [Registry] with the remote cache enabled, fetching documents with the provided
+    /// [Fetcher]. This allows the [crate::fetch::FetchPolicy] and [crate::fetch::Transport] to be
+    /// customized.
+    pub fn new_with_fetcher(fetcher: Fetcher) -> Self {
         Self {
-            r: BTreeMap::new(),
-            remote_cache: true,
+            fetcher: Some(Arc::new(fetcher)),
+            ..Default::default()
+        }
+    }
+
+    /// Set the [CachePolicy] used for remotely fetched documents. Entries beyond the new capacity
+    /// are evicted.
+    pub fn set_cache_policy(&mut self, policy: CachePolicy) {
+        for did in self.cache.set_policy(policy) {
+            self.equivalence.remove(&did);
+        }
+    }
+
+    /// Set the [Transitivity] policy used to compute `alsoKnownAs` equivalence classes. See
+    /// [Registry::equivalent_to_did] and [Registry::equivalents].
+    pub fn set_transitivity(&mut self, policy: Transitivity) {
+        self.equivalence.set_policy(policy)
+    }
+
+    /// Set the [Resurrection] policy, which determines whether documents may be inserted for
+    /// deactivated [DID]s. By default they may not.
+    pub fn set_resurrection(&mut self, policy: Resurrection) {
+        self.resurrection = policy
+    }
+
+    /// Start recording changes to the registry in a [Journal]. Enable it before inserting any
+    /// documents for the journal to describe the whole registry; see [Registry::verify_journal].
+    /// Does nothing if the journal is already enabled.
+    pub fn enable_journal(&mut self) {
+        self.journal.get_or_insert_with(Journal::default);
+    }
+
+    /// The [Journal] of changes to the registry, if it is enabled.
+    pub fn journal(&self) -> Option<&Journal> {
+        self.journal.as_ref()
+    }
+
+    /// Read the change feed of the registry, which is its [Journal]: at most `limit` entries,
+    /// starting from the one with sequence number `since`. Fails if the journal is not enabled.
+    /// See [crate::replication::Replicator] for applying the feed to another registry.
+    pub fn changes(&self, since: u64, limit: usize) -> Result<ChangeBatch, anyhow::Error> {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return Err(anyhow!("Registry does not have a journal")),
+        };
+
+        Ok(ChangeBatch {
+            entries: journal.since(since, limit).cloned().collect(),
+            len: journal.len() as u64,
+        })
+    }
+
+    // apply a change replicated from the journal of another registry. Returns false if the
+    // registry is already as the change leaves it.
+    pub(crate) fn apply_change(&mut self, entry: &JournalEntry) -> Result<bool, anyhow::Error> {
+        match entry.operation {
+            Operation::Insert | Operation::Update | Operation::Identify => {
+                let doc = match &entry.document {
+                    Some(doc) if doc.id == entry.id => doc,
+                    _ => {
+                        return Err(anyhow!(
+                            "{:?} of DID {} does not carry its document",
+                            entry.operation,
+                            entry.id
+                        ))
+                    }
+                };
+                let identifiers = entry.identifiers.clone().unwrap_or_default();
+
+                let changed = match self.r.get(&doc.id) {
+                    Some(current) if current.as_ref() == doc => false,
+                    Some(_) => {
+                        self.update_versioned(doc.clone(), None, entry.time.clone())?;
+                        true
+                    }
+                    None => {
+                        let policy = self.resurrection;
+                        self.resurrection = Resurrection::Allow;
+                        let res = self.insert_versioned(
+                            doc.clone(),
+                            None,
+                            StoredMetadata {
+                                identifiers: identifiers.clone(),
+                                ..StoredMetadata::new(entry.time.clone())
+                            },
+                        );
+                        self.resurrection = policy;
+                        res?;
+                        true
+                    }
+                };
+
+                Ok(self.identify(&entry.id, identifiers, None, entry.time.clone())? || changed)
+            }
+            Operation::Remove => Ok(self.try_remove(&entry.id)?.is_some()),
+            Operation::Deactivate => {
+                if self.tombstones.contains_key(&entry.id) {
+                    return Ok(false);
+                }
+
+                if self.r.contains_key(&entry.id) {
+                    self.deactivate_at(&entry.id, entry.time.clone())?;
+                } else {
+                    self.insert_tombstone(Tombstone {
+                        id: entry.id.clone(),
+                        version: Version(self.sequence + 1),
+                        deactivated: entry.time.clone(),
+                    })?;
+                }
+
+                Ok(true)
+            }
+        }
+    }
+
+    /// Start maintaining a [MerkleTree] over the documents in the registry, built from the ones
+    /// it holds now and updated as they change. Fails if a document cannot be digested. Does
+    /// nothing if the tree is already enabled.
+    pub fn enable_merkle_tree(&mut self) -> Result<(), anyhow::Error> {
+        if self.merkle.is_none() {
+            self.merkle = Some(MerkleTree::from_documents(
+                self.r.values().map(|doc| doc.as_ref()),
+            )?);
+        }
+
+        Ok(())
+    }
+
+    /// The [MerkleTree] over the documents in the registry, if it is enabled.
+    pub fn merkle_tree(&self) -> Option<&MerkleTree> {
+        self.merkle.as_ref()
+    }
+
+    // digest a document for the merkle tree before it is changed, so that a failure to do so
+    // leaves the registry as it was
+    fn merkle_digest(&self, doc: &Document) -> Result<Option<[u8; 32]>, anyhow::Error> {
+        self.merkle.as_ref().map(|_| doc.digest()).transpose()
+    }
+
+    fn merkle_insert(&mut self, did: &DID, digest: Option<[u8; 32]>) {
+        if let (Some(tree), Some(digest)) = (&mut self.merkle, digest) {
+            tree.insert(did.clone(), digest);
+        }
+    }
+
+    /// Replay the [Journal] with [Journal::replay], and check that the registry it describes is
+    /// this one: the same documents at the same [Version]s, and the same [Tombstone]s. Fails if
+    /// the journal is not enabled.
+    pub fn verify_journal(&self) -> Result<(), anyhow::Error> {
+        let journal = match &self.journal {
+            Some(journal) => journal,
+            None => return Err(anyhow!("Registry does not have a journal")),
+        };
+
+        let replayed = journal.replay()?;
+        let diff = replayed.diff(self);
+        if !diff.is_empty() {
+            return Err(anyhow!(
+                "Journal does not describe the registry: {} added, {} removed and {} changed documents",
+                diff.added.len(),
+                diff.removed.len(),
+                diff.changed.len()
+            ));
+        }
+
+        let identifiers = |reg: &Registry| {
+            reg.stored
+                .iter()
+                .map(|(did, stored)| (did.clone(), stored.identifiers.clone()))
+                .collect::<BTreeMap<_, _>>()
+        };
+
+        if replayed.versions != self.versions
+            || replayed.tombstones != self.tombstones
+            || identifiers(&replayed) != identifiers(self)
+        {
+            return Err(anyhow!(
+                "Journal does not describe the registry's versions, tombstones and identifiers"
+            ));
+        }
+
+        Ok(())
+    }
+
+    // prepare the journal entry for a change before it is made, so that a failure to do so
+    // leaves the registry as it was
+    fn journal_entry(
+        &self,
+        operation: Operation,
+        did: &DID,
+        doc: Option<&Document>,
+        identifiers: Option<&Identifiers>,
+        version: Option<Version>,
+        time: VersionTime,
+    ) -> Result<Option<JournalEntry>, anyhow::Error> {
+        let identifiers = identifiers.filter(|identifiers| !identifiers.is_empty());
+        self.journal
+            .as_ref()
+            .map(|journal| journal.entry(operation, did, doc, identifiers, version, time))
+            .transpose()
+    }
+
+    fn record(&mut self, entry: Option<JournalEntry>) {
+        if let (Some(journal), Some(entry)) = (&mut self.journal, entry) {
+            journal.append(entry);
+        }
+    }
+
+    // apply a change recorded in a journal
+    pub(crate) fn replay(&mut self, entry: &JournalEntry) -> Result<(), anyhow::Error> {
+        let document = || {
+            entry.document.clone().ok_or(anyhow!(
+                "{:?} of DID {} has no document",
+                entry.operation,
+                entry.id
+            ))
+        };
+
+        let identifiers = entry.identifiers.clone().unwrap_or_default();
+
+        match entry.operation {
+            Operation::Insert => self.insert_versioned(
+                document()?,
+                entry.version,
+                StoredMetadata {
+                    identifiers,
+                    ..StoredMetadata::new(entry.time.clone())
+                },
+            ),
+            Operation::Update => self
+                .update_versioned(document()?, entry.version, entry.time.clone())
+                .map(|_| ()),
+            Operation::Remove => match self.try_remove(&entry.id)? {
+                Some(_) => Ok(()),
+                None => Err(anyhow!("DID {} does not exist in registry", entry.id)),
+            },
+            Operation::Deactivate => {
+                self.unlink(&entry.id);
+                self.insert_tombstone(Tombstone {
+                    id: entry.id.clone(),
+                    version: entry
+                        .version
+                        .ok_or(anyhow!("Deactivation of DID {} has no version", entry.id))?,
+                    deactivated: entry.time.clone(),
+                })
+            }
+            Operation::Identify => {
+                self.identify(&entry.id, identifiers, entry.version, entry.time.clone())?;
+                Ok(())
+            }
+        }
+    }
+
+    /// Register a [Subscriber], which is sent an [Event] after each change to the registry:
+    /// inserting, updating, removing and deactivating documents, changing their [Identifiers],
+    /// and fetching them into the remote cache. Changes made in a [Transaction], or through
+    /// [crate::shared::SharedRegistry::write], are sent once they are applied, and not at all if
+    /// they are discarded.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    /// use std::sync::{Arc, Mutex};
+    ///
+    /// let events = Arc::new(Mutex::new(Vec::new()));
+    /// let seen = events.clone();
+    ///
+    /// let mut reg = Registry::default();
+    /// reg.subscribe(move |event: &Event| seen.lock().unwrap().push(event.kind));
+    /// // only documents with a controller may be inserted
+    /// reg.add_hook(|event: &Event| match &event.after {
+    ///   Some(doc) if doc.controller.is_none() => Err(anyhow::anyhow!("{} has no controller", doc.id)),
+    ///   _ => Ok(()),
+    /// });
+    ///
+    /// let did = DID::parse("did:mymethod:alice").unwrap();
+    /// assert!(reg.insert(Document{ id: did.clone(), ..Default::default() }).is_err());
+    /// reg.insert(Document{
+    ///   id: did.clone(),
+    ///   controller: Some(Controller(either::Either::Left(did.clone()))),
+    ///   ..Default::default()
+    /// }).unwrap();
+    /// reg.remove(&did);
+    ///
+    /// assert_eq!(*events.lock().unwrap(), vec![EventKind::Inserted, EventKind::Removed]);
+    /// ```
+    pub fn subscribe(&mut self, subscriber: impl Subscriber + 'static) -> Subscription {
+        self.observers.subscribe(Arc::new(subscriber))
+    }
+
+    /// Register a [Hook], which is consulted with an [Event] before each change to the registry
+    /// and may veto it. Vetoed changes fail with the hook's error. See [Registry::subscribe] for
+    /// the changes covered.
+    pub fn add_hook(&mut self, hook: impl Hook + 'static) -> Subscription {
+        self.observers.add_hook(Arc::new(hook))
+    }
+
+    /// Remove a [Subscriber] or [Hook]. Returns false if it was not registered.
+    pub fn unsubscribe(&mut self, subscription: Subscription) -> bool {
+        self.observers.unsubscribe(subscription)
+    }
+
+    // consult the hooks of the DID's method, then the registry's, about a change
+    fn check(
+        &self,
+        did: &DID,
+        event: impl FnOnce() -> Event,
+    ) -> Result<Option<Event>, anyhow::Error> {
+        let policy = self.namespaces.policy(did);
+        if !policy.has_hooks() {
+            return self.observers.check(event);
+        }
+
+        let event = event();
+        policy.check(&event)?;
+        self.observers.check(|| event)
+    }
+
+    /// Set the [MethodPolicy] for the [DID]s of a method, e.g. `web`. Documents in the remote
+    /// cache which the policy does not allow to be cached are evicted. Fails, leaving the policy
+    /// as it was, if the registry holds documents which the policy does not allow to be stored.
+    pub fn set_method_policy(
+        &mut self,
+        method: &str,
+        policy: MethodPolicy,
+    ) -> Result<(), anyhow::Error> {
+        let mut namespaces = self.namespaces.clone();
+        namespaces.set(method, policy);
+        self.set_namespaces(namespaces)
+    }
+
+    /// Set the [MethodPolicy] for the [DID]s of methods without a policy of their own. By default,
+    /// documents of every method may be stored. Behaves like [Registry::set_method_policy].
+    pub fn set_default_method_policy(&mut self, policy: MethodPolicy) -> Result<(), anyhow::Error> {
+        let mut namespaces = self.namespaces.clone();
+        namespaces.set_default(policy);
+        self.set_namespaces(namespaces)
+    }
+
+    /// Retrieve the [MethodPolicy] which applies to a [DID].
+    pub fn method_policy(&self, did: &DID) -> &MethodPolicy {
+        self.namespaces.policy(did)
+    }
+
+    fn set_namespaces(&mut self, namespaces: Namespaces) -> Result<(), anyhow::Error> {
+        if let Some(did) = self
+            .r
+            .keys()
+            .find(|did| !namespaces.policy(did).retention.stores())
+        {
+            return Err(anyhow!(
+                "DID {} is in the registry, but the policy for its method does not allow it to be stored",
+                did
+            ));
+        }
+
+        let evicted: Vec<DID> = self
+            .cache
+            .iter()
+            .filter(|(did, _)| !namespaces.policy(did).retention.caches())
+            .map(|(did, _)| did.clone())
+            .collect();
+
+        for did in evicted {
+            self.evict(&did);
+        }
+
+        self.namespaces = namespaces;
+        Ok(())
+    }
+
+    /// Count the documents held for each DID method: inserted, in the remote cache and
+    /// deactivated. Methods with nothing held are not included.
+    pub fn method_stats(&self) -> BTreeMap<String, MethodStats> {
+        let mut stats: BTreeMap<String, MethodStats> = BTreeMap::new();
+
+        for did in self.r.keys() {
+            stats.entry(method_name(did)).or_default().documents += 1;
+        }
+
+        for (did, _) in self.cache.iter() {
+            stats.entry(method_name(did)).or_default().cached += 1;
+        }
+
+        for did in self.tombstones.keys() {
+            stats.entry(method_name(did)).or_default().deactivated += 1;
+        }
+
+        stats
+    }
+
+    // a copy of the registry to stage changes in; its events are held back until it is applied
+    // with Registry::publish
+    pub(crate) fn staged(&self) -> Registry {
+        Registry {
+            observers: self.observers.deferred(),
+            ..self.clone()
+        }
+    }
+
+    // apply a copy made with Registry::staged, sending its events
+    pub(crate) fn publish(&mut self, mut staged: Registry) {
+        let events = staged.take_events();
+        staged.observers.pending = self.observers.pending.take();
+        *self = staged;
+
+        for event in events {
+            self.observers.emit(Some(event));
+        }
+    }
+
+    pub(crate) fn take_events(&mut self) -> Vec<Event> {
+        self.observers.take_pending()
+    }
+
+    pub(crate) fn notify(&self, events: &[Event]) {
+        for event in events {
+            self.observers.notify(event);
         }
     }
 
@@ -123,9 +637,115 @@ impl Registry {
         self.insert(doc)
     }
 
+    /// Load a [Tombstone] from the filesystem as JSON. See [Registry::insert_tombstone].
+    pub fn load_tombstone(&mut self, filename: PathBuf) -> Result<(), anyhow::Error> {
+        let mut file = std::fs::OpenOptions::new();
+        file.read(true);
+        let io = file.open(filename)?;
+        let tombstone: Tombstone = serde_json::from_reader(io)?;
+        self.insert_tombstone(tombstone)
+    }
+
+    /// Write the whole registry to a writer in the provided [ExportFormat]: every document with
+    /// its [Version], followed by every [Tombstone]. Documents in the remote cache are not
+    /// exported. Records are written one at a time, so the writer should be buffered.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    ///
+    /// let mut reg = Registry::default();
+    /// let did = DID::parse("did:mymethod:alice").unwrap();
+    /// reg.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+    ///
+    /// let mut buf = Vec::new();
+    /// reg.export(ExportFormat::JsonLines, &mut buf).unwrap();
+    ///
+    /// let mut imported = Registry::default();
+    /// assert_eq!(imported.import(ExportFormat::JsonLines, buf.as_slice()).unwrap(), 1);
+    /// assert_eq!(imported[&did], reg[&did]);
+    /// assert_eq!(imported.version(&did), reg.version(&did));
+    /// ```
+    pub fn export<W: std::io::Write>(
+        &self,
+        format: ExportFormat,
+        w: W,
+    ) -> Result<(), anyhow::Error> {
+        export::export(self, format, w)
+    }
+
+    /// Read documents and [Tombstone]s in the provided [ExportFormat], as written by
+    /// [Registry::export], into the registry. Records are read one at a time. Returns the number
+    /// of records read. If any record cannot be read or inserted, nothing is imported.
+    ///
+    /// Documents imported into a registry which has never held a document keep their exported
+    /// [Version]s; otherwise they are given new ones, as versions are never reused. They keep
+    /// the times they were created and last updated, and their [Identifiers], either way.
+    pub fn import<R: std::io::Read>(
+        &mut self,
+        format: ExportFormat,
+        r: R,
+    ) -> Result<usize, anyhow::Error> {
+        let keep_versions = self.sequence == 0;
+        let mut staged = self.staged();
+        let count = export::import(&mut staged, format, r, keep_versions)?;
+        self.publish(staged);
+        Ok(count)
+    }
+
+    // insert a document, keeping the version and metadata it was given elsewhere
+    pub(crate) fn restore(
+        &mut self,
+        doc: Document,
+        version: Option<Version>,
+        stored: StoredMetadata,
+    ) -> Result<(), anyhow::Error> {
+        self.insert_versioned(doc, version, stored)
+    }
+
     /// Get an iterator into the ordered pairs of the registry.
     pub fn iter<'a>(&'a self) -> impl Iterator<Item = (&'a DID, &'a Document)> + 'a {
-        self.r.iter()
+        self.r.iter().map(|(did, doc)| (did, doc.as_ref()))
+    }
+
+    /// Iterate over the ordered pairs of the registry whose [DID]s fall within a range.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    ///
+    /// let mut reg = Registry::default();
+    /// for name in ["alice", "bob", "charlie"] {
+    ///   reg.insert(Document{
+    ///     id: DID::parse(&format!("did:mymethod:{}", name)).unwrap(),
+    ///     ..Default::default()
+    ///   }).unwrap();
+    /// }
+    ///
+    /// let bob = DID::parse("did:mymethod:bob").unwrap();
+    /// assert_eq!(reg.range(&bob..).count(), 2);
+    /// assert_eq!(reg.range(..&bob).count(), 1);
+    /// ```
+    pub fn range<'a, R>(&'a self, range: R) -> impl Iterator<Item = (&'a DID, &'a Document)> + 'a
+    where
+        R: RangeBounds<DID> + 'a,
+    {
+        self.r.range(range).map(|(did, doc)| (did, doc.as_ref()))
+    }
+
+    /// Retrieve the ordered pair at a position in the registry's [DID] ordering, in O(log n)
+    /// time. Positions shift as documents are inserted and removed; use a [Cursor] to walk the
+    /// registry across changes.
+    pub fn get_index(&self, index: usize) -> Option<(&DID, &Document)> {
+        let did = self.order.get(index)?;
+        self.r
+            .get_key_value(did)
+            .map(|(did, doc)| (did, doc.as_ref()))
+    }
+
+    /// Determine the position of a [DID] in the registry's ordering, for use with
+    /// [Registry::get_index], in O(log² n) time: a binary search over an ordering whose elements
+    /// each take O(log n) to reach. Returns [None] if the [DID] is not in the registry.
+    pub fn position(&self, did: &DID) -> Option<usize> {
+        self.order.binary_search(did).ok()
     }
 
     /// Compute the size of the registry.
@@ -133,32 +753,1080 @@ impl Registry {
         self.r.len()
     }
 
+    /// Determine if the registry is empty.
+    pub fn is_empty(&self) -> bool {
+        self.r.is_empty()
+    }
+
     /// Insert a document into the registry. The registry will automatically be keyed by the
-    /// [Document]'s `id` property. Will fail if the document already exists.
+    /// [Document]'s `id` property. Will fail if the document already exists, or if its [DID] has
+    /// been deactivated and the [Resurrection] policy does not allow it to be used again. A
+    /// remotely cached copy of the document is discarded in favor of the inserted one. Also fails
+    /// if the [DID] is asserted in the [Identifiers] of another document.
     pub fn insert(&mut self, doc: Document) -> Result<(), anyhow::Error> {
+        self.insert_versioned(doc, None, StoredMetadata::new(VersionTime::now()))
+    }
+
+    // insert a document at the provided version, or the next one, with the provided metadata
+    fn insert_versioned(
+        &mut self,
+        doc: Document,
+        version: Option<Version>,
+        stored: StoredMetadata,
+    ) -> Result<(), anyhow::Error> {
         if self.r.contains_key(&doc.id) {
             return Err(anyhow!("DID {} already exists in registry", doc.id));
         }
 
+        if self.tombstones.contains_key(&doc.id) && self.resurrection == Resurrection::Refuse {
+            return Err(anyhow!("DID {} has been deactivated", doc.id));
+        }
+
+        if !self.namespaces.policy(&doc.id).retention.stores() {
+            return Err(anyhow!(
+                "DID {} may not be stored, by the policy for its method",
+                doc.id
+            ));
+        }
+
+        if let Some(other) = self.aliases.get(&doc.id) {
+            return Err(anyhow!("DID {} is already asserted for {}", doc.id, other));
+        }
+
+        self.check_identifiers(&doc.id, &stored.identifiers)?;
+
+        let doc = Arc::new(doc);
+        let event = self.check(&doc.id, || Event {
+            kind: EventKind::Inserted,
+            id: doc.id.clone(),
+            before: None,
+            after: Some(doc.clone()),
+        })?;
+
+        let version = version.unwrap_or(Version(self.sequence + 1));
+        let entry = self.journal_entry(
+            Operation::Insert,
+            &doc.id,
+            Some(&doc),
+            Some(&stored.identifiers),
+            Some(version),
+            stored.created.clone(),
+        )?;
+        let digest = self.merkle_digest(&doc)?;
+
+        self.tombstones.remove(&doc.id);
+        self.cache.remove(&doc.id);
+        self.equivalence.insert(&doc);
+        self.index.insert(&doc);
+        if let Err(pos) = self.order.binary_search(&doc.id) {
+            self.order.insert(pos, doc.id.clone());
+        }
+
+        self.set_version(&doc.id, version);
+        for alias in stored.identifiers.aliases() {
+            self.aliases.insert(alias.clone(), doc.id.clone());
+        }
+        self.stored.insert(doc.id.clone(), stored);
+        self.merkle_insert(&doc.id, digest);
         self.r.insert(doc.id.clone(), doc);
+        self.record(entry);
+        self.observers.emit(event);
         Ok(())
     }
 
-    /// Remove a document by [DID].
+    /// Replace a document already in the registry, keyed by the [Document]'s `id` property.
+    /// Returns the previous document. Will fail if the document does not exist.
+    pub fn update(&mut self, doc: Document) -> Result<Document, anyhow::Error> {
+        self.update_versioned(doc, None, VersionTime::now())
+    }
+
+    // replace a document, giving it the provided version, or the next one if it has changed, as
+    // updated at the provided time
+    fn update_versioned(
+        &mut self,
+        doc: Document,
+        version: Option<Version>,
+        time: VersionTime,
+    ) -> Result<Document, anyhow::Error> {
+        let before = match self.r.get(&doc.id) {
+            Some(before) => before.clone(),
+            None => return Err(anyhow!("DID {} does not exist in registry", doc.id)),
+        };
+
+        let doc = Arc::new(doc);
+        let event = self.check(&doc.id, || Event {
+            kind: EventKind::Updated,
+            id: doc.id.clone(),
+            before: Some(before.clone()),
+            after: Some(doc.clone()),
+        })?;
+
+        let version = match version {
+            Some(version) => version,
+            None if before != doc => Version(self.sequence + 1),
+            None => self.versions[&doc.id],
+        };
+        let entry = self.journal_entry(
+            Operation::Update,
+            &doc.id,
+            Some(&doc),
+            self.identifiers(&doc.id),
+            Some(version),
+            time.clone(),
+        )?;
+        let digest = self.merkle_digest(&doc)?;
+
+        if before != doc {
+            if let Some(stored) = self.stored.get_mut(&doc.id) {
+                stored.updated = Some(time);
+            }
+        }
+        self.set_version(&doc.id, version);
+        self.equivalence.insert(&doc);
+        self.index.remove(&before);
+        self.index.insert(&doc);
+        self.merkle_insert(&doc.id, digest);
+        self.r.insert(doc.id.clone(), doc);
+        self.record(entry);
+        self.observers.emit(event);
+        Ok(Arc::unwrap_or_clone(before))
+    }
+
+    /// Retrieve the current [Version] of a document in the registry.
+    pub fn version(&self, did: &DID) -> Option<Version> {
+        self.versions.get(did).copied()
+    }
+
+    /// Replace a document in the registry, provided it is still at the expected [Version]. Fails
+    /// if the document does not exist, or has been changed since that version was read. The
+    /// version is unchanged if the document is identical to the stored one.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    ///
+    /// let mut reg = Registry::default();
+    /// let did = DID::parse("did:mymethod:alice").unwrap();
+    /// let doc = Document{ id: did.clone(), ..Default::default() };
+    ///
+    /// let version = reg.upsert(doc.clone()).unwrap().version;
+    ///
+    /// let mut changed = doc.clone();
+    /// changed.controller = Some(Controller(either::Either::Left(did.clone())));
+    /// let replaced = reg.replace(changed.clone(), version).unwrap();
+    /// assert_eq!(replaced.previous, Some(doc));
+    /// assert!(replaced.changed.contains("controller"));
+    ///
+    /// // another writer got there first
+    /// assert!(reg.replace(changed, version).is_err());
+    /// ```
+    pub fn replace(&mut self, doc: Document, expected: Version) -> Result<Replaced, anyhow::Error> {
+        match self.version(&doc.id) {
+            None => Err(anyhow!("DID {} does not exist in registry", doc.id)),
+            Some(version) if version != expected => Err(anyhow!(
+                "DID {} has been modified: expected version {}, found {}",
+                doc.id,
+                expected,
+                version
+            )),
+            Some(_) => self.upsert(doc),
+        }
+    }
+
+    /// Insert a document into the registry, or replace it if it already exists.
+    pub fn upsert(&mut self, doc: Document) -> Result<Replaced, anyhow::Error> {
+        let did = doc.id.clone();
+
+        let (previous, changed) = match self.r.get(&did) {
+            Some(old) => {
+                let changed = old.changed_properties(&doc);
+                (Some(self.update(doc)?), changed)
+            }
+            None => {
+                let changed = Document::default().changed_properties(&doc);
+                self.insert(doc)?;
+                (None, changed)
+            }
+        };
+
+        Ok(Replaced {
+            previous,
+            version: self.versions[&did],
+            changed,
+        })
+    }
+
+    fn set_version(&mut self, did: &DID, version: Version) {
+        // versions must not be reused
+        self.sequence = self.sequence.max(version.0);
+        self.versions.insert(did.clone(), version);
+    }
+
+    /// Begin a [Transaction], which stages changes to the registry and applies them atomically
+    /// once committed.
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        Transaction::new(self)
+    }
+
+    /// Run a closure as a [Transaction]. The transaction is committed if the closure succeeds,
+    /// and discarded otherwise.
+    pub fn transact<T>(
+        &mut self,
+        f: impl FnOnce(&mut Transaction<'_>) -> Result<T, anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        let mut tx = self.transaction();
+        let res = f(&mut tx)?;
+        tx.commit()?;
+        Ok(res)
+    }
+
+    /// Compare the documents in this registry (the left) with those in another (the right). Only
+    /// the parts of the registries which differ are visited, so comparing a registry with a
+    /// recent clone of itself is cheap.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    /// use either::Either;
+    ///
+    /// let did = DID::parse("did:mymethod:alice").unwrap();
+    /// let mut staging = Registry::default();
+    /// staging.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+    ///
+    /// let mut production = staging.clone();
+    /// production.update(Document{
+    ///   id: did.clone(),
+    ///   controller: Some(Controller(Either::Left(did.clone()))),
+    ///   ..Default::default()
+    /// }).unwrap();
+    ///
+    /// let diff = staging.diff(&production);
+    /// assert_eq!(diff.changed[0].properties[0].property, "controller");
+    ///
+    /// staging.merge(&production, &MergePolicy::Newest).unwrap();
+    /// assert!(staging.diff(&production).is_empty());
+    /// ```
+    pub fn diff(&self, other: &Registry) -> Diff {
+        let mut diff = Diff::default();
+
+        for item in self.r.diff(&other.r) {
+            match item {
+                DiffItem::Add(did, _) => {
+                    diff.added.insert(did.clone());
+                }
+                DiffItem::Remove(did, _) => {
+                    diff.removed.insert(did.clone());
+                }
+                DiffItem::Update {
+                    old: (did, left),
+                    new: (_, right),
+                } => diff.changed.push(DocumentChange::new(
+                    left,
+                    self.version(did),
+                    right,
+                    other.version(did),
+                )),
+            }
+        }
+
+        for item in self.stored.diff(&other.stored) {
+            let (did, left, right) = match item {
+                DiffItem::Update {
+                    old: (did, left),
+                    new: (_, right),
+                } if left.identifiers != right.identifiers => (did, left, right),
+                _ => continue,
+            };
+
+            match diff.changed.iter_mut().find(|change| &change.id == did) {
+                Some(change) => change.add_identifiers(&left.identifiers, &right.identifiers),
+                None => {
+                    let mut change = DocumentChange::new(
+                        &self.r[did],
+                        self.version(did),
+                        &other.r[did],
+                        other.version(did),
+                    );
+                    change.add_identifiers(&left.identifiers, &right.identifiers);
+                    diff.changed.push(change);
+                }
+            }
+        }
+
+        diff.changed.sort_by(|a, b| a.id.cmp(&b.id));
+        diff
+    }
+
+    /// Merge the documents of another registry into this one. Documents only in the other
+    /// registry are inserted, and documents in both which differ are settled by the
+    /// [ConflictResolver], such as a [MergePolicy]; documents only in this registry are kept.
+    /// Returns the changes made, as a [Diff] from this registry as it was. If any document cannot
+    /// be merged, nothing is.
+    pub fn merge(
+        &mut self,
+        other: &Registry,
+        resolver: &dyn ConflictResolver,
+    ) -> Result<Diff, anyhow::Error> {
+        let mut staged = self.staged();
+
+        for item in self.r.diff(&other.r) {
+            match item {
+                DiffItem::Add(_, doc) => staged.insert(doc.as_ref().clone())?,
+                DiffItem::Remove(..) => {}
+                DiffItem::Update {
+                    old: (did, left),
+                    new: (_, right),
+                } => {
+                    let doc = resolver.resolve(&Conflict {
+                        left,
+                        left_version: self.version(did),
+                        right,
+                        right_version: other.version(did),
+                    })?;
+
+                    if &doc.id != did {
+                        return Err(anyhow!(
+                            "Conflict for DID {} was resolved with a document for {}",
+                            did,
+                            doc.id
+                        ));
+                    }
+
+                    if doc != **left {
+                        staged.update(doc)?;
+                    }
+                }
+            }
+        }
+
+        let diff = self.diff(&staged);
+        self.publish(staged);
+        Ok(diff)
+    }
+
+    /// Remove a document by [DID]. Returns [None] if the document does not exist, or it could
+    /// not be removed; use [Registry::try_remove] to learn why.
     pub fn remove(&mut self, did: &DID) -> Option<Document> {
-        self.r.remove(did)
+        self.try_remove(did).ok().flatten()
+    }
+
+    /// Remove a document by [DID], returning it, or [None] if it does not exist. Fails, leaving
+    /// the document in place, if a [Hook] or the [MethodPolicy] of its method vetoes the removal,
+    /// or it cannot be recorded in the [Journal].
+    pub fn try_remove(&mut self, did: &DID) -> Result<Option<Document>, anyhow::Error> {
+        let doc = match self.r.get(did) {
+            Some(doc) => doc.clone(),
+            None => return Ok(None),
+        };
+
+        let event = self.check(did, || Event {
+            kind: EventKind::Removed,
+            id: did.clone(),
+            before: Some(doc.clone()),
+            after: None,
+        })?;
+
+        let entry =
+            self.journal_entry(Operation::Remove, did, None, None, None, VersionTime::now())?;
+        self.unlink(did);
+        self.record(entry);
+        self.observers.emit(event);
+        Ok(Some(Arc::unwrap_or_clone(doc)))
+    }
+
+    fn unlink(&mut self, did: &DID) -> Option<Arc<Document>> {
+        let doc = self.r.remove(did)?;
+        if let Ok(pos) = self.order.binary_search(did) {
+            self.order.remove(pos);
+        }
+
+        self.versions.remove(did);
+        if let Some(stored) = self.stored.remove(did) {
+            for alias in stored.identifiers.aliases() {
+                self.aliases.remove(alias);
+            }
+        }
+        self.equivalence.remove(did);
+        self.index.remove(&doc);
+        self.resources.remove(did);
+        if let Some(tree) = &mut self.merkle {
+            tree.remove(did);
+        }
+        Some(doc)
+    }
+
+    /// Deactivate a [DID]: its document is removed from the registry and replaced with a
+    /// [Tombstone], which is reported by [Registry::resolve]. Returns the removed document. Fails
+    /// if the document is not in the registry.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    ///
+    /// let mut reg = Registry::default();
+    /// let did = DID::parse("did:mymethod:alice").unwrap();
+    /// reg.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+    ///
+    /// reg.deactivate(&did).unwrap();
+    /// assert!(reg.get(&did).is_none());
+    /// assert!(reg.resolve(&did).unwrap().deactivated);
+    /// assert!(reg.insert(Document{ id: did.clone(), ..Default::default() }).is_err());
+    ///
+    /// reg.set_resurrection(Resurrection::Allow);
+    /// assert!(reg.insert(Document{ id: did.clone(), ..Default::default() }).is_ok());
+    /// ```
+    pub fn deactivate(&mut self, did: &DID) -> Result<Document, anyhow::Error> {
+        self.deactivate_at(did, VersionTime::now())
+    }
+
+    // deactivate a DID as of the given time, such as that of a replicated journal entry
+    fn deactivate_at(&mut self, did: &DID, time: VersionTime) -> Result<Document, anyhow::Error> {
+        let doc = match self.r.get(did) {
+            Some(doc) => doc.clone(),
+            None => return Err(anyhow!("DID {} does not exist in registry", did)),
+        };
+
+        let event = self.check(did, || Event {
+            kind: EventKind::Deactivated,
+            id: did.clone(),
+            before: Some(doc.clone()),
+            after: None,
+        })?;
+
+        let tombstone = Tombstone {
+            id: did.clone(),
+            version: Version(self.sequence + 1),
+            deactivated: time,
+        };
+        let entry = self.journal_entry(
+            Operation::Deactivate,
+            did,
+            None,
+            None,
+            Some(tombstone.version),
+            tombstone.deactivated.clone(),
+        )?;
+
+        self.unlink(did);
+        self.sequence = tombstone.version.0;
+        self.tombstones.insert(did.clone(), tombstone);
+        self.record(entry);
+        self.observers.emit(event);
+        Ok(Arc::unwrap_or_clone(doc))
+    }
+
+    /// Restore a [Tombstone], such as one previously exported from a registry. Fails if a
+    /// document for the [DID] is in the registry.
+    pub fn insert_tombstone(&mut self, tombstone: Tombstone) -> Result<(), anyhow::Error> {
+        if self.r.contains_key(&tombstone.id) {
+            return Err(anyhow!(
+                "DID {} exists in registry and cannot be deactivated",
+                tombstone.id
+            ));
+        }
+
+        let event = self.check(&tombstone.id, || Event {
+            kind: EventKind::Deactivated,
+            id: tombstone.id.clone(),
+            before: None,
+            after: None,
+        })?;
+
+        let entry = self.journal_entry(
+            Operation::Deactivate,
+            &tombstone.id,
+            None,
+            None,
+            Some(tombstone.version),
+            tombstone.deactivated.clone(),
+        )?;
+
+        // versions must not be reused
+        self.sequence = self.sequence.max(tombstone.version.0);
+        self.cache.remove(&tombstone.id);
+        self.equivalence.remove(&tombstone.id);
+        self.tombstones.insert(tombstone.id.clone(), tombstone);
+        self.record(entry);
+        self.observers.emit(event);
+        Ok(())
+    }
+
+    /// Retrieve the [Tombstone] of a deactivated [DID].
+    pub fn tombstone(&self, did: &DID) -> Option<&Tombstone> {
+        self.tombstones.get(did)
+    }
+
+    /// Determine if a [DID] has been deactivated.
+    pub fn is_deactivated(&self, did: &DID) -> bool {
+        self.tombstones.contains_key(did)
+    }
+
+    /// Iterate over the [Tombstone]s of all deactivated [DID]s, ordered by [DID].
+    pub fn tombstones(&self) -> impl Iterator<Item = &Tombstone> {
+        self.tombstones.values()
+    }
+
+    /// Resolve a [DID] to its document, noting whether it has been deactivated. Returns [None]
+    /// if the [DID] is neither in the registry, nor fresh in the remote cache, nor deactivated.
+    /// The [Canonicalization] policy is applied as in [Registry::get].
+    pub fn resolve(&self, did: &DID) -> Option<Resolution> {
+        if let Some(tombstone) = self.tombstones.get(did) {
+            return Some(Resolution {
+                document: Arc::new(Document {
+                    id: did.clone(),
+                    ..Default::default()
+                }),
+                deactivated: true,
+                version: Some(tombstone.version),
+                metadata: DocumentMetadata::from(tombstone),
+            });
+        }
+
+        self.canonical_lookup(did)
+            .cloned()
+            .map(|document| Resolution {
+                deactivated: false,
+                version: self.version(&document.id),
+                metadata: self.document_metadata(&document.id).unwrap_or_default(),
+                document,
+            })
+    }
+
+    /// Set the [Canonicalization] policy, which determines whether [Registry::get] follows the
+    /// `canonicalId` of documents. By default it does not.
+    pub fn set_canonicalization(&mut self, policy: Canonicalization) {
+        self.canonicalization = policy;
+    }
+
+    /// Assert the `equivalentId` of a document: [DID]s of the same method which identify the same
+    /// subject, such as the short and long forms of a [DID]. Replaces any previously asserted.
+    /// Fails if the document is not in the registry, or a [DID] is of another method, or is
+    /// already asserted for another document, or is itself in the registry.
+    ///
+    /// Changing the [Identifiers] of a document is a change like any other: it is checked by the
+    /// [Hook]s, gives the document a new [Version], is recorded in the [Journal] and is sent to
+    /// [Subscriber]s as [EventKind::Identified].
+    pub fn set_equivalent_ids(
+        &mut self,
+        did: &DID,
+        ids: impl IntoIterator<Item = DID>,
+    ) -> Result<(), anyhow::Error> {
+        let ids = ids.into_iter().filter(|id| id != did).collect();
+        self.set_identifiers(did, |identifiers| identifiers.equivalent_id = ids)
+    }
+
+    /// Assert the `canonicalId` of a document: the [DID] of the same method which its subject
+    /// should be known by, or [None] to remove it. Behaves as [Registry::set_equivalent_ids] does.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    ///
+    /// let mut reg = Registry::default();
+    /// let long = DID::parse("did:mymethod:alice:long-form").unwrap();
+    /// let short = DID::parse("did:mymethod:alice").unwrap();
+    /// reg.insert(Document{ id: long.clone(), ..Default::default() }).unwrap();
+    /// reg.set_canonical_id(&long, Some(short.clone())).unwrap();
+    ///
+    /// assert_eq!(reg.canonical_did(&long), Some(short.clone()));
+    /// assert_eq!(reg.resolve(&long).unwrap().metadata.canonical_id, Some(short.clone()));
+    /// assert!(reg.same_subject(&long, &short));
+    ///
+    /// // the canonical DID only retrieves the document if the registry follows it
+    /// assert!(reg.get(&short).is_none());
+    /// reg.set_canonicalization(Canonicalization::Follow);
+    /// assert_eq!(reg.get(&short).unwrap().id, long);
+    /// ```
+    pub fn set_canonical_id(&mut self, did: &DID, id: Option<DID>) -> Result<(), anyhow::Error> {
+        let id = id.filter(|id| id != did);
+        self.set_identifiers(did, |identifiers| identifiers.canonical_id = id)
+    }
+
+    /// Retrieve the [Identifiers] asserted for a document in the registry.
+    pub fn identifiers(&self, did: &DID) -> Option<&Identifiers> {
+        self.stored.get(did).map(|stored| &stored.identifiers)
+    }
+
+    fn set_identifiers(
+        &mut self,
+        did: &DID,
+        f: impl FnOnce(&mut Identifiers),
+    ) -> Result<(), anyhow::Error> {
+        let mut identifiers = match self.identifiers(did) {
+            Some(identifiers) => identifiers.clone(),
+            None => return Err(anyhow!("DID {} does not exist in registry", did)),
+        };
+        f(&mut identifiers);
+
+        self.identify(did, identifiers, None, VersionTime::now())?;
+        Ok(())
+    }
+
+    // change the identifiers of a stored document, giving it the provided version, or the next
+    // one. Returns false if they are unchanged.
+    fn identify(
+        &mut self,
+        did: &DID,
+        identifiers: Identifiers,
+        version: Option<Version>,
+        time: VersionTime,
+    ) -> Result<bool, anyhow::Error> {
+        let (doc, stored) = match (self.r.get(did), self.stored.get(did)) {
+            (Some(doc), Some(stored)) => (doc.clone(), stored.clone()),
+            _ => return Err(anyhow!("DID {} does not exist in registry", did)),
+        };
+
+        if stored.identifiers == identifiers {
+            return Ok(false);
+        }
+
+        self.check_identifiers(did, &identifiers)?;
+        let event = self.check(did, || Event {
+            kind: EventKind::Identified,
+            id: did.clone(),
+            before: Some(doc.clone()),
+            after: Some(doc.clone()),
+        })?;
+
+        let version = version.unwrap_or(Version(self.sequence + 1));
+        let entry = self.journal_entry(
+            Operation::Identify,
+            did,
+            Some(&doc),
+            Some(&identifiers),
+            Some(version),
+            time.clone(),
+        )?;
+
+        for alias in stored.identifiers.aliases() {
+            self.aliases.remove(alias);
+        }
+        for alias in identifiers.aliases() {
+            self.aliases.insert(alias.clone(), did.clone());
+        }
+        self.set_version(did, version);
+        self.stored.insert(
+            did.clone(),
+            StoredMetadata {
+                updated: Some(time),
+                identifiers,
+                ..stored
+            },
+        );
+        self.record(entry);
+        self.observers.emit(event);
+        Ok(true)
     }
 
-    /// Retreive a document by [DID].
+    // identifiers must be of the DID's method, and neither asserted for nor stored as another
+    // document
+    fn check_identifiers(&self, did: &DID, identifiers: &Identifiers) -> Result<(), anyhow::Error> {
+        for alias in identifiers.aliases() {
+            if method_name(alias) != method_name(did) {
+                return Err(anyhow!(
+                    "DID {} is not of the same method as {}",
+                    alias,
+                    did
+                ));
+            }
+
+            match self.aliases.get(alias) {
+                Some(other) if other != did => {
+                    return Err(anyhow!("DID {} is already asserted for {}", alias, other))
+                }
+                _ => {}
+            }
+
+            if alias != did && self.r.contains_key(alias) {
+                return Err(anyhow!("DID {} already exists in registry", alias));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the canonical [DID] of a subject: the `canonicalId` of its document, or the [DID]
+    /// the document is stored under if it has none. [DID]s named in the `equivalentId` or
+    /// `canonicalId` of a stored document find the canonical [DID] of that document. Returns
+    /// [None] if the [DID] is not known to the registry.
+    pub fn canonical_did(&self, did: &DID) -> Option<DID> {
+        let subject = if self.lookup(did).is_some() || self.tombstones.contains_key(did) {
+            did
+        } else {
+            self.aliases.get(did)?
+        };
+
+        Some(
+            self.stored
+                .get(subject)
+                .and_then(|stored| stored.identifiers.canonical_id.clone())
+                .unwrap_or(subject.clone()),
+        )
+    }
+
+    /// Determine if two [DID]s identify the same subject: if they are the same, or share a
+    /// canonical [DID], or either is asserted in the `equivalentId` of the other. See
+    /// [Registry::canonical_did].
+    pub fn same_subject(&self, did: &DID, other: &DID) -> bool {
+        if did == other {
+            return true;
+        }
+
+        let equivalent = |a: &DID, b: &DID| {
+            self.stored
+                .get(a)
+                .is_some_and(|stored| stored.identifiers.equivalent_id.contains(b))
+        };
+
+        if equivalent(did, other) || equivalent(other, did) {
+            return true;
+        }
+
+        matches!(
+            (self.canonical_did(did), self.canonical_did(other)),
+            (Some(a), Some(b)) if a == b
+        )
+    }
+
+    /// Retrieve the [DocumentMetadata] of a document: when it was created and last updated, and
+    /// its version, or when it was deactivated if it has been. Documents in the remote cache are
+    /// not described by the registry, so their metadata is empty. Returns [None] if the [DID] is
+    /// neither in the registry, nor fresh in the remote cache, nor deactivated.
+    pub fn document_metadata(&self, did: &DID) -> Option<DocumentMetadata> {
+        if let Some(tombstone) = self.tombstones.get(did) {
+            return Some(DocumentMetadata::from(tombstone));
+        }
+
+        match self.stored.get(did) {
+            Some(stored) => Some(stored.metadata(self.version(did))),
+            None => self.lookup(did).map(|_| DocumentMetadata::default()),
+        }
+    }
+
+    /// Retreive a document by [DID]. Documents in the remote cache are returned if they are
+    /// still fresh. If the [Canonicalization] policy is to follow `canonicalId`, the document of
+    /// the canonical [DID] is returned in preference; see [Registry::canonical_did].
     pub fn get(&self, did: &DID) -> Option<Document> {
-        self.r.get(did).cloned()
+        self.canonical_lookup(did)
+            .cloned()
+            .map(Arc::unwrap_or_clone)
+    }
+
+    /// Retrieve a shared reference to a document by the [DID] it is stored under, without
+    /// copying it. Unlike [Registry::get], `canonicalId` is never followed, so the document
+    /// returned always has the requested `id`.
+    pub fn get_arc(&self, did: &DID) -> Option<Arc<Document>> {
+        self.lookup(did).cloned()
+    }
+
+    // look up a document, following canonicalId if the Canonicalization policy says to
+    fn canonical_lookup(&self, did: &DID) -> Option<&Arc<Document>> {
+        if self.canonicalization == Canonicalization::Follow {
+            if let Some(canonical) = self.canonical_did(did) {
+                let stored = self.aliases.get(did).unwrap_or(did);
+                if let Some(doc) = self.lookup(&canonical).or_else(|| self.lookup(stored)) {
+                    return Some(doc);
+                }
+            }
+        }
+
+        self.lookup(did)
     }
 
-    /// Retrieve a document by DID [URL].
+    fn lookup(&self, did: &DID) -> Option<&Arc<Document>> {
+        match self.r.get(did) {
+            Some(doc) => Some(doc),
+            None => self
+                .cache
+                .get(did, SystemTime::now())
+                .map(|entry| &entry.document),
+        }
+    }
+
+    /// Retrieve the remote cache entry for a [DID], whether or not it is fresh.
+    pub fn cached(&self, did: &DID) -> Option<&CacheEntry> {
+        self.cache.entry(did)
+    }
+
+    /// Remove a document from the remote cache.
+    pub fn evict(&mut self, did: &DID) -> Option<Document> {
+        let entry = self.cache.remove(did)?;
+        self.equivalence.remove(did);
+        Some(Arc::unwrap_or_clone(entry.document))
+    }
+
+    /// Remove all documents from the remote cache which are no longer fresh, returning their
+    /// [DID]s. Stale documents are not returned by lookups, but are kept so that they can be
+    /// revalidated by [Registry::refresh].
+    pub fn expire(&mut self) -> Vec<DID> {
+        let expired = self.cache.expire(SystemTime::now());
+        for did in &expired {
+            self.equivalence.remove(did);
+        }
+
+        expired
+    }
+
+    /// Retrieve a document by DID [URL], disregarding its path. See [Registry::dereference] for
+    /// resources named by the path.
     pub fn follow(&self, url: URL) -> Option<Document> {
         self.get(&url.to_did())
     }
 
+    /// Dereference a DID [URL]: a [URL] whose path is `/resources/<id>` dereferences to the
+    /// [Resource] of that id linked to its [DID], and any other to the document of its [DID], as
+    /// with [Registry::get_arc]. Fails if either does not exist.
+    ///
+    /// If the [URL] carries an `hl` parameter, what it dereferences to must match the
+    /// [HashLink]: documents are hashed in their [Document::canonical_json] form, and resources
+    /// by their content. Dereferencing fails if it does not match, or cannot be decoded.
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    ///
+    /// let mut reg = Registry::default();
+    /// let did = DID::parse("did:mymethod:issuer").unwrap();
+    /// reg.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+    ///
+    /// let metadata = reg.attach_resource(
+    ///   &did, "degree", "application/schema+json", br#"{"type":"object"}"#.to_vec(),
+    /// ).unwrap();
+    ///
+    /// let url = URL::parse("did:mymethod:issuer/resources/degree").unwrap();
+    /// assert_eq!(metadata.resource_uri, url);
+    /// match reg.dereference(&url).unwrap() {
+    ///   Dereferenced::Resource(resource) => assert_eq!(resource.metadata.media_type, "application/schema+json"),
+    ///   Dereferenced::Document(_) => unreachable!(),
+    /// }
+    ///
+    /// // pinned to the content by hashlink
+    /// let hl = HashLink::new(br#"{"type":"object"}"#);
+    /// let pinned = URL::parse(&format!("{}?hl={}", url, hl)).unwrap();
+    /// assert!(reg.dereference(&pinned).is_ok());
+    ///
+    /// reg.attach_resource(&did, "degree", "application/schema+json", b"{}".to_vec()).unwrap();
+    /// assert!(reg.dereference(&pinned).is_err());
+    /// ```
+    pub fn dereference(&self, url: &URL) -> Result<Dereferenced, anyhow::Error> {
+        let did = url.to_did();
+        let path = url.parameters.as_ref().and_then(|p| p.path.as_deref());
+
+        let dereferenced = match path {
+            Some(path) if !path.is_empty() && path != b"/" => {
+                let id =
+                    resource_id(path).ok_or(anyhow!("DID URL {} does not name a resource", url))?;
+
+                self.resources
+                    .get(&did)
+                    .and_then(|resources| resources.get(id))
+                    .cloned()
+                    .map(Dereferenced::Resource)
+                    .ok_or(anyhow!("Resource {} does not exist in registry", url))?
+            }
+            _ => self
+                .get_arc(&did)
+                .map(Dereferenced::Document)
+                .ok_or(anyhow!("DID {} does not exist in registry", did))?,
+        };
+
+        if let Some(hl) = url.parameters.as_ref().and_then(|p| p.hash_link.as_ref()) {
+            let hl = HashLink::decode(hl)?;
+            match &dereferenced {
+                Dereferenced::Document(doc) => hl.verify(&doc.canonical_json()?),
+                Dereferenced::Resource(resource) => hl.verify(&resource.content),
+            }
+            .map_err(|_| anyhow!("DID URL {} does not match the hashlink it carries", url))?;
+        }
+
+        Ok(dereferenced)
+    }
+
+    /// Link a [Resource] to a document in the registry, to be dereferenced by the [URL] with the
+    /// path `/resources/<id>`. An existing resource with the same id is replaced, and given the
+    /// next [Version] of that resource. Ids may contain letters, digits, `-`, `_`, `.` and `~`.
+    /// Returns the metadata of the resource. Fails if the document is not in the registry.
+    ///
+    /// Resources are removed with their document. Attaching and detaching them leaves the document
+    /// and its [Version] alone, and is not seen by [Hook]s or [Subscriber]s, nor recorded in the
+    /// [Journal], the [MerkleTree], exports or the change feed.
+    pub fn attach_resource(
+        &mut self,
+        did: &DID,
+        id: &str,
+        media_type: &str,
+        content: Vec<u8>,
+    ) -> Result<ResourceMetadata, anyhow::Error> {
+        if !self.r.contains_key(did) {
+            return Err(anyhow!("DID {} does not exist in registry", did));
+        }
+
+        if id.is_empty()
+            || !id
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_.~".contains(c))
+        {
+            return Err(anyhow!("Resource id {:?} is not valid", id));
+        }
+
+        let resource = Resource::new(did, id, media_type, content, self.resource(did, id))?;
+        let metadata = resource.metadata.clone();
+        self.resources
+            .entry(did.clone())
+            .or_default()
+            .insert(id.to_string(), Arc::new(resource));
+        Ok(metadata)
+    }
+
+    /// Remove a [Resource] from a document, returning it.
+    pub fn detach_resource(&mut self, did: &DID, id: &str) -> Option<Arc<Resource>> {
+        let resources = self.resources.get_mut(did)?;
+        let resource = resources.remove(id);
+        if resources.is_empty() {
+            self.resources.remove(did);
+        }
+
+        resource
+    }
+
+    /// Retrieve a [Resource] linked to a document by its id.
+    pub fn resource(&self, did: &DID, id: &str) -> Option<&Resource> {
+        self.resources
+            .get(did)?
+            .get(id)
+            .map(|resource| resource.as_ref())
+    }
+
+    /// Iterate over the [Resource]s linked to a document, ordered by id.
+    pub fn resources(&self, did: &DID) -> impl Iterator<Item = &Resource> {
+        self.resources
+            .get(did)
+            .into_iter()
+            .flat_map(|resources| resources.values().map(|resource| resource.as_ref()))
+    }
+
+    /// Retrieve the [DID]s of documents carrying the provided [KeyMaterial] in any embedded
+    /// [VerificationMethod].
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    ///
+    /// let mut reg = Registry::default();
+    /// let did = DID::parse("did:mymethod:alice").unwrap();
+    /// let jwk = JWK::new().unwrap();
+    ///
+    /// reg.insert(Document{
+    ///   id: did.clone(),
+    ///   verification_method: Some([VerificationMethod{
+    ///     id: URL::parse("did:mymethod:alice#key-1").unwrap(),
+    ///     controller: did.clone(),
+    ///     public_key_jwk: Some(jwk.clone()),
+    ///     ..Default::default()
+    ///   }].into()),
+    ///   ..Default::default()
+    /// }).unwrap();
+    ///
+    /// let key = KeyMaterial::from_jwk(&jwk).unwrap();
+    /// assert!(reg.documents_by_key(&key).contains(&did));
+    /// ```
+    pub fn documents_by_key(&self, key: &KeyMaterial) -> BTreeSet<DID> {
+        self.index.by_key(key)
+    }
+
+    /// Retrieve the [DID]s of documents which have a service of the provided [ServiceType].
+    pub fn documents_by_service_type(&self, typ: &ServiceType) -> BTreeSet<DID> {
+        self.index.by_service_type(typ)
+    }
+
+    /// Retrieve the [DID]s of documents which have a service naming the provided [Url], either as
+    /// its id, endpoint, or in its `origins` or `registries`.
+    pub fn documents_by_service_endpoint(&self, url: &Url) -> BTreeSet<DID> {
+        self.index.by_service_endpoint(url)
+    }
+
+    /// Retrieve the [DID]s of documents which name the provided [DID] as a controller.
+    pub fn documents_by_controller(&self, controller: &DID) -> BTreeSet<DID> {
+        self.index.by_controller(controller)
+    }
+
+    /// Retrieve the [DID]s of documents which refer to a verification method of the provided
+    /// [DID] by [URL], in any verification relationship.
+    pub fn documents_referencing(&self, did: &DID) -> BTreeSet<DID> {
+        self.index.by_reference(did)
+    }
+
+    /// Run a [Query] against the registry, returning at most `limit` documents ordered by [DID].
+    /// Pass the `next` value of the returned [Page] as `after` to retrieve the following page.
+    /// Fails if `limit` is zero, as a page must hold a document to continue after.
+    ///
+    /// Filters on method name, [DID] prefix, service type and controller are answered from the
+    /// registry's indexes; other filters are evaluated against each candidate document.
+    pub fn query<'a>(
+        &'a self,
+        query: &Query,
+        after: Option<&DID>,
+        limit: usize,
+    ) -> Result<Page<'a>, anyhow::Error> {
+        if limit == 0 {
+            return Err(anyhow!("Query limit must be at least 1"));
+        }
+
+        let start = match after {
+            Some(after) => Bound::Excluded(after.clone()),
+            None => Bound::Unbounded,
+        };
+
+        Ok(match self.candidates(&Filter::And(query.filters.clone())) {
+            Candidates::All => Page::fill(
+                self.r
+                    .range((start, Bound::Unbounded))
+                    .map(|x| x.1.as_ref()),
+                query,
+                limit,
+            ),
+            Candidates::Prefix(prefix) => {
+                // start from whichever of the prefix and the cursor is further on
+                let start = match start {
+                    Bound::Excluded(after) if after >= prefix => Bound::Excluded(after),
+                    _ => Bound::Included(prefix.clone()),
+                };
+
+                Page::fill(
+                    self.r
+                        .range((start, Bound::Unbounded))
+                        .take_while(|x| has_prefix(x.0, &prefix))
+                        .map(|x| x.1.as_ref()),
+                    query,
+                    limit,
+                )
+            }
+            Candidates::Set(set) => Page::fill(
+                set.range((start, Bound::Unbounded))
+                    .filter_map(|did| self.r.get(did).map(|doc| doc.as_ref())),
+                query,
+                limit,
+            ),
+        })
+    }
+
+    fn candidates(&self, filter: &Filter) -> Candidates {
+        match filter {
+            Filter::Method(name) => self.candidates(&Filter::Prefix(DID {
+                name: name.clone(),
+                id: Vec::new(),
+            })),
+            Filter::Prefix(prefix) => Candidates::Prefix(prefix.clone()),
+            Filter::ServiceType(typ) => Candidates::Set(self.index.by_service_type(typ)),
+            Filter::Controller(did) => Candidates::Set(self.index.by_controller(did)),
+            Filter::And(filters) => filters
+                .iter()
+                .fold(Candidates::All, |acc, f| acc.intersect(self.candidates(f))),
+            Filter::Or(filters) => filters
+                .iter()
+                .map(|f| self.candidates(f))
+                .reduce(|acc, c| acc.union(c))
+                .unwrap_or(Candidates::Set(BTreeSet::new())),
+            Filter::Relationship(_) | Filter::VerificationMethodType(_) | Filter::Not(_) => {
+                Candidates::All
+            }
+        }
+    }
+
     /// Looks up a [VerificationMethod] by [URL] for the [DID]. There must be a
     /// [VerificationMethod] in the [DID]'s document, otherwise this will return [None].
     pub fn verification_method_for_url(&self, did: &DID, url: URL) -> Option<VerificationMethod> {
@@ -185,8 +1853,8 @@ impl Registry {
             }
 
             if self.get(controller).is_some() {
-                if did_doc.controller.is_some() {
-                    match did_doc.controller.unwrap().0 {
+                if let Some(did_controller) = did_doc.controller {
+                    match did_controller.0 {
                         Either::Left(did) => return Ok(&did == controller),
                         Either::Right(did_list) => {
                             for did in did_list {
@@ -206,78 +1874,326 @@ impl Registry {
             return Err(anyhow!("DID {} did not exist in the registry", did));
         }
 
-        Ok(false)
-    }
+        Ok(false)
+    }
+
+    /// For two given [DID]s, determine if they can be treated the same according to the rules for
+    /// the `alsoKnownAs` property, which you can read here:
+    /// <https://www.w3.org/TR/did-core/#also-known-as>
+    ///
+    /// Only links that are reciprocated by both documents count. By default only the two
+    /// documents themselves are compared; see [Registry::set_transitivity] to close equivalence
+    /// over chains of links. Hypertext URLs in `alsoKnownAs` are only considered once the
+    /// document they point at has been fetched with [Registry::cache_document].
+    ///
+    /// Both [DID]s must exist in the registry, otherwise an error will be returned. Remote
+    /// documents which are no longer fresh do not count as existing, nor do they link others.
+    pub fn equivalent_to_did(&self, did: &DID, other: &DID) -> Result<bool, anyhow::Error> {
+        for did in [did, other] {
+            if self.lookup(did).is_none() {
+                return Err(anyhow!("DID {} did not exist in the registry", did));
+            }
+        }
+
+        Ok(self.fresh_equivalence(did).equivalent(did, other))
+    }
+
+    /// Retrieve all [DID]s equivalent to the provided one through `alsoKnownAs`, including the
+    /// [DID] itself. Returns [None] if the [DID] is not in the registry. See
+    /// [Registry::equivalent_to_did] for the rules applied.
+    pub fn equivalents(&self, did: &DID) -> Option<BTreeSet<DID>> {
+        self.fresh_equivalence(did).equivalents(did)
+    }
+
+    // the equivalence index as lookups see it: remote documents stay indexed after they go stale,
+    // until Registry::expire drops them, so any stale ones equivalent to the DID are taken out of
+    // a copy of the index
+    fn fresh_equivalence(&self, did: &DID) -> Cow<'_, EquivalenceIndex> {
+        let now = SystemTime::now();
+        let stale: Vec<DID> = self
+            .equivalence
+            .equivalents(did)
+            .unwrap_or_default()
+            .into_iter()
+            .filter(|did| {
+                !self.r.contains_key(did)
+                    && !self
+                        .cache
+                        .entry(did)
+                        .is_some_and(|entry| entry.is_fresh(now))
+            })
+            .collect();
+
+        if stale.is_empty() {
+            return Cow::Borrowed(&self.equivalence);
+        }
+
+        let mut index = self.equivalence.clone();
+        for did in &stale {
+            index.remove(did);
+        }
+
+        Cow::Owned(index)
+    }
+
+    /// Check the registry as a whole for problems which checking each document alone does not
+    /// catch: controllers missing from the registry, `alsoKnownAs` links which are not
+    /// reciprocated, verification relationships referring to methods which do not exist, keys
+    /// which cannot be thumbprinted, key material shared between [DID]s, and controller cycles.
+    /// Every problem is reported: those with individual documents in [DID] order, followed by
+    /// shared key material and controller cycles. See [Finding].
+    ///
+    /// ```
+    /// use did_toolkit::prelude::*;
+    /// use either::Either;
+    ///
+    /// let mut reg = Registry::default();
+    /// let did = DID::parse("did:mymethod:alice").unwrap();
+    /// let missing = DID::parse("did:mymethod:bob").unwrap();
+    /// reg.insert(Document{
+    ///   id: did.clone(),
+    ///   controller: Some(Controller(Either::Left(missing.clone()))),
+    ///   ..Default::default()
+    /// }).unwrap();
+    ///
+    /// assert_eq!(
+    ///   reg.audit(),
+    ///   vec![Finding::MissingController{ id: did, controller: missing }]
+    /// );
+    /// ```
+    pub fn audit(&self) -> Vec<Finding> {
+        audit::audit(self, &self.index)
+    }
+
+    /// Fetch a remote document by hypertext [Url] and store it in the remote cache. The [Url] is
+    /// recorded so that `alsoKnownAs` properties naming it are treated as naming the document's
+    /// [DID]. Fetching a document which is already cached replaces it.
+    ///
+    /// Will fail if the registry was not created with [Registry::new_with_remote_cache] or
+    /// [Registry::new_with_fetcher], if the fetch is refused by the [crate::fetch::FetchPolicy],
+    /// or if the document was inserted into the registry directly.
+    pub fn cache_document(&mut self, url: Url) -> Result<Document, anyhow::Error> {
+        self.cache(url, None)
+    }
+
+    /// Fetch the remote document for a [DID] from a hypertext [Url] and insert it into the
+    /// registry. Behaves like [Registry::cache_document], but additionally fails if the fetched
+    /// document's `id` is not the requested [DID].
+    pub fn cache_document_for(&mut self, did: &DID, url: Url) -> Result<Document, anyhow::Error> {
+        self.cache(url, Some(did))
+    }
+
+    /// Revalidate a document in the remote cache, whether or not it is fresh. A conditional
+    /// request is made if the original response carried an `ETag` or `Last-Modified` header;
+    /// if the remote reports the document as unmodified, it is kept and its lifetime renewed.
+    /// Otherwise the cached document is replaced.
+    pub fn refresh(&mut self, did: &DID) -> Result<Document, anyhow::Error> {
+        let req = self.revalidation_request(did)?;
+        let resp = self.fetcher()?.fetch(&req)?;
+        self.apply_revalidation(did, &req, &resp)
+    }
+
+    fn fetcher(&self) -> Result<&Fetcher, anyhow::Error> {
+        match &self.fetcher {
+            Some(fetcher) => Ok(fetcher),
+            None => Err(anyhow!("Remote caching of documents is disabled")),
+        }
+    }
+
+    fn cache(&mut self, url: Url, expected: Option<&DID>) -> Result<Document, anyhow::Error> {
+        let resp = self.fetcher()?.fetch(&FetchRequest::new(url.clone()))?;
+        self.apply_fetch(url, &resp, expected)
+    }
+
+    // The remote cache is driven in two halves around the network request, so that the request
+    // itself can be made elsewhere, e.g. by [crate::resolver::AsyncRegistry].
+
+    pub(crate) fn revalidation_request(&self, did: &DID) -> Result<FetchRequest, anyhow::Error> {
+        match self.cache.entry(did) {
+            Some(entry) => Ok(entry.revalidation_request()),
+            None => Err(anyhow!("DID {} is not in the remote cache", did)),
+        }
+    }
+
+    pub(crate) fn apply_revalidation(
+        &mut self,
+        did: &DID,
+        req: &FetchRequest,
+        resp: &FetchResponse,
+    ) -> Result<Document, anyhow::Error> {
+        let now = SystemTime::now();
+
+        if resp.status == 304 {
+            let freshness = self.cache.policy().freshness(resp, now);
+            let entry = match self.cache.entry_mut(did) {
+                Some(entry) => entry,
+                None => return Err(anyhow!("DID {} is not in the remote cache", did)),
+            };
+
+            entry.revalidated(
+                resp,
+                now,
+                match freshness {
+                    Freshness::Until(expires) => expires,
+                    Freshness::NoStore => now,
+                },
+            );
+            return Ok(entry.document.as_ref().clone());
+        }
+
+        let doc = Fetcher::parse_document(&req.url, resp, Some(did))?;
+        self.store(req.url.clone(), doc, resp, now)
+    }
+
+    pub(crate) fn apply_fetch(
+        &mut self,
+        url: Url,
+        resp: &FetchResponse,
+        expected: Option<&DID>,
+    ) -> Result<Document, anyhow::Error> {
+        let doc = Fetcher::parse_document(&url, resp, expected)?;
+
+        if self.namespaces.policy(&doc.id).retention == Retention::Refuse {
+            return Err(anyhow!(
+                "DID {} may not be fetched, by the policy for its method",
+                doc.id
+            ));
+        }
+
+        if self.tombstones.contains_key(&doc.id) {
+            return Err(anyhow!(
+                "DID {} has been deactivated and cannot be cached",
+                doc.id
+            ));
+        }
 
-    /// For two given [DID]s, determine if they can be treated the same according to the rules for
-    /// the `alsoKnownAs` property, which you can read here:
-    /// <https://www.w3.org/TR/did-core/#also-known-as>
-    ///
-    /// Both [DID]s must exist in the registry, otherwise an error will be returned.
-    pub fn equivalent_to_did(&mut self, did: &DID, other: &DID) -> Result<bool, anyhow::Error> {
-        // there is probably a better way to represent this stew with Iterator methods, but I
-        // cannot be fucked to deal with that right now.
-        if let Some(doc) = self.get(did) {
-            if let Some(other_doc) = self.get(other) {
-                if let Some(this_aka) = doc.also_known_as {
-                    for this_aka_each in this_aka.0 {
-                        match this_aka_each.0 {
-                            Either::Left(this_did) => {
-                                if self.compare_aka(did, &this_did, &other_doc)? {
-                                    return Ok(true);
-                                }
-                            }
-                            Either::Right(url) => {
-                                let this_doc = self.cache_document(url)?;
-                                if self.compare_aka(did, &this_doc.id, &other_doc)? {
-                                    return Ok(true);
-                                }
-                            }
-                        }
-                    }
-                } else {
-                    return Ok(false);
-                }
-            } else {
-                return Err(anyhow!("DID {} did not exist in the registry", other));
-            }
-        } else {
-            return Err(anyhow!("DID {} did not exist in the registry", did));
+        if self.r.contains_key(&doc.id) {
+            return Err(anyhow!(
+                "DID {} already exists in registry and cannot be cached",
+                doc.id
+            ));
         }
 
-        Ok(false)
+        self.store(url, doc, resp, SystemTime::now())
     }
 
-    fn compare_aka(
+    fn store(
         &mut self,
-        did: &DID,
-        this_did: &DID,
-        other_doc: &Document,
-    ) -> Result<bool, anyhow::Error> {
-        if let Some(other_aka) = &other_doc.also_known_as {
-            for other_aka_each in &other_aka.0 {
-                let other_did = &match &other_aka_each.0 {
-                    Either::Left(other_did) => other_did.clone(),
-                    Either::Right(url) => self.cache_document(url.clone())?.id,
-                };
+        url: Url,
+        doc: Document,
+        resp: &FetchResponse,
+        now: SystemTime,
+    ) -> Result<Document, anyhow::Error> {
+        let freshness = if self.namespaces.policy(&doc.id).retention.caches() {
+            self.cache.policy().freshness(resp, now)
+        } else {
+            Freshness::NoStore
+        };
+
+        match freshness {
+            Freshness::NoStore => {
+                self.evict(&doc.id);
+            }
+            Freshness::Until(expires) => {
+                let event = self.check(&doc.id, || Event {
+                    kind: EventKind::RemoteCached,
+                    id: doc.id.clone(),
+                    before: self
+                        .cache
+                        .entry(&doc.id)
+                        .map(|entry| entry.document.clone()),
+                    after: Some(Arc::new(doc.clone())),
+                })?;
+
+                self.equivalence.insert(&doc);
+                self.equivalence.alias_url(url.clone(), doc.id.clone());
 
-                if other_did == did && this_did == &other_doc.id {
-                    return Ok(true);
+                for did in self
+                    .cache
+                    .insert(CacheEntry::new(url, doc.clone(), resp, now, expires))
+                {
+                    self.equivalence.remove(&did);
                 }
+
+                self.observers.emit(event);
             }
         }
 
-        Ok(false)
+        Ok(doc)
     }
+}
 
-    fn cache_document(&mut self, url: Url) -> Result<Document, anyhow::Error> {
-        if self.remote_cache {
-            let doc = reqwest::blocking::get(url)?.json::<Document>()?;
-            self.insert(doc.clone())?;
-            Ok(doc)
-        } else {
-            Err(anyhow!("Remote caching of documents is disabled"))
+/// Cursor walks a [Registry] in [DID] order. It remembers the last [DID] it returned rather than a
+/// position, so it stays valid while documents are inserted and removed between steps: documents
+/// inserted ahead of the cursor will be visited, and ones inserted behind it will not. Each step
+/// costs O(log n).
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let did = |name| DID::parse(&format!("did:mymethod:{}", name)).unwrap();
+/// let mut reg = Registry::default();
+/// reg.insert(Document{ id: did("alice"), ..Default::default() }).unwrap();
+/// reg.insert(Document{ id: did("charlie"), ..Default::default() }).unwrap();
+///
+/// let mut cursor = Cursor::default();
+/// assert_eq!(cursor.next(&reg).unwrap().id, did("alice"));
+///
+/// reg.insert(Document{ id: did("bob"), ..Default::default() }).unwrap();
+/// reg.insert(Document{ id: did("aaron"), ..Default::default() }).unwrap();
+///
+/// let rest: Vec<DID> = cursor.take(&reg, 10).into_iter().map(|doc| doc.id.clone()).collect();
+/// assert_eq!(rest, vec![did("bob"), did("charlie")]);
+/// assert!(cursor.next(&reg).is_none());
+/// ```
+#[derive(Clone, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Cursor {
+    last: Option<DID>,
+}
+
+impl Cursor {
+    /// Create a cursor which resumes after the provided [DID], whether or not it is in the
+    /// registry.
+    pub fn after(did: DID) -> Self {
+        Self { last: Some(did) }
+    }
+
+    /// The last [DID] returned by the cursor, if any.
+    pub fn last(&self) -> Option<&DID> {
+        self.last.as_ref()
+    }
+
+    /// Return the next document in the registry, advancing the cursor.
+    pub fn next<'a>(&mut self, reg: &'a Registry) -> Option<&'a Document> {
+        let start = match &self.last {
+            Some(last) => Bound::Excluded(last.clone()),
+            None => Bound::Unbounded,
+        };
+
+        let (did, doc) = reg.r.range((start, Bound::Unbounded)).next()?;
+        self.last = Some(did.clone());
+        Some(doc)
+    }
+
+    /// Return up to `n` of the following documents in the registry, advancing the cursor.
+    pub fn take<'a>(&mut self, reg: &'a Registry, n: usize) -> Vec<&'a Document> {
+        let start = match &self.last {
+            Some(last) => Bound::Excluded(last.clone()),
+            None => Bound::Unbounded,
+        };
+
+        let docs: Vec<&Document> = reg
+            .r
+            .range((start, Bound::Unbounded))
+            .take(n)
+            .map(|(_, doc)| doc.as_ref())
+            .collect();
+
+        if let Some(doc) = docs.last() {
+            self.last = Some(doc.id.clone());
         }
+
+        docs
     }
 }
 
@@ -473,4 +2389,600 @@ mod tests {
         assert!(reg.insert(doc2).is_ok());
         assert!(!reg.equivalent_to_did(&did, &did2).unwrap());
     }
+
+    #[test]
+    fn test_equivalents() {
+        use super::Registry;
+        use crate::{
+            did::DID,
+            document::{AlsoKnownAs, AlsoKnownAsEither, Document},
+            equivalence::Transitivity,
+        };
+        use either::Either;
+
+        let aka = |dids: &[&DID]| {
+            Some(AlsoKnownAs(
+                dids.iter()
+                    .map(|did| AlsoKnownAsEither(Either::Left((*did).clone())))
+                    .collect(),
+            ))
+        };
+
+        let mut reg: Registry = Default::default();
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let did2 = DID::parse("did:testing:u:bob").unwrap();
+        let did3 = DID::parse("did:testing:u:charlie").unwrap();
+
+        assert!(reg
+            .insert(Document {
+                id: did.clone(),
+                also_known_as: aka(&[&did2]),
+                ..Default::default()
+            })
+            .is_ok());
+        assert!(reg
+            .insert(Document {
+                id: did2.clone(),
+                also_known_as: aka(&[&did, &did3]),
+                ..Default::default()
+            })
+            .is_ok());
+        assert!(reg
+            .insert(Document {
+                id: did3.clone(),
+                ..Default::default()
+            })
+            .is_ok());
+
+        assert!(!reg.equivalent_to_did(&did2, &did3).unwrap());
+        assert_eq!(reg.equivalents(&did3).unwrap().len(), 1);
+
+        assert!(reg
+            .update(Document {
+                id: did3.clone(),
+                also_known_as: aka(&[&did2]),
+                ..Default::default()
+            })
+            .is_ok());
+
+        assert!(reg.equivalent_to_did(&did2, &did3).unwrap());
+        assert!(!reg.equivalent_to_did(&did, &did3).unwrap());
+        assert_eq!(reg.equivalents(&did2).unwrap().len(), 3);
+
+        reg.set_transitivity(Transitivity::Transitive);
+        assert!(reg.equivalent_to_did(&did, &did3).unwrap());
+        assert_eq!(reg.equivalents(&did).unwrap().len(), 3);
+
+        assert!(reg.remove(&did2).is_some());
+        assert!(reg.equivalent_to_did(&did, &did3).is_ok());
+        assert!(!reg.equivalent_to_did(&did, &did3).unwrap());
+        assert!(reg.equivalents(&did2).is_none());
+        assert!(reg
+            .update(Document {
+                id: did2.clone(),
+                ..Default::default()
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_documents_by() {
+        use super::Registry;
+        use crate::{
+            did::DID,
+            document::{Controller, Document},
+        };
+        use either::Either;
+
+        let mut reg: Registry = Default::default();
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let did2 = DID::parse("did:testing:u:bob").unwrap();
+        let did3 = DID::parse("did:testing:u:charlie").unwrap();
+
+        let doc = Document {
+            id: did.clone(),
+            controller: Some(Controller(Either::Left(did2.clone()))),
+            ..Default::default()
+        };
+
+        assert!(reg.insert(doc.clone()).is_ok());
+        assert_eq!(reg.documents_by_controller(&did2), [did.clone()].into());
+
+        assert!(reg
+            .update(Document {
+                controller: Some(Controller(Either::Right(
+                    [did2.clone(), did3.clone()].into()
+                ))),
+                ..doc.clone()
+            })
+            .is_ok());
+        assert_eq!(reg.documents_by_controller(&did2), [did.clone()].into());
+        assert_eq!(reg.documents_by_controller(&did3), [did.clone()].into());
+
+        assert!(reg
+            .update(Document {
+                controller: Some(Controller(Either::Left(did3.clone()))),
+                ..doc.clone()
+            })
+            .is_ok());
+        assert!(reg.documents_by_controller(&did2).is_empty());
+        assert_eq!(reg.documents_by_controller(&did3), [did.clone()].into());
+
+        assert!(reg.remove(&did).is_some());
+        assert!(reg.documents_by_controller(&did3).is_empty());
+    }
+
+    #[test]
+    fn test_query() {
+        use super::Registry;
+        use crate::{
+            did::DID,
+            document::{Controller, Document},
+            query::Query,
+        };
+        use either::Either;
+
+        let mut reg: Registry = Default::default();
+        let admin = DID::parse("did:testing:u:admin").unwrap();
+
+        for x in 0..10 {
+            assert!(reg
+                .insert(Document {
+                    id: DID::parse(&format!("did:testing:u:user{}", x)).unwrap(),
+                    controller: if x % 2 == 0 {
+                        Some(Controller(Either::Left(admin.clone())))
+                    } else {
+                        None
+                    },
+                    ..Default::default()
+                })
+                .is_ok());
+
+            assert!(reg
+                .insert(Document {
+                    id: DID::parse(&format!("did:other:u:user{}", x)).unwrap(),
+                    ..Default::default()
+                })
+                .is_ok());
+        }
+
+        let query = Query::default().method("testing");
+        let page = reg.query(&query, None, 4).unwrap();
+        assert_eq!(page.documents.len(), 4);
+        assert_eq!(page.next, Some(DID::parse("did:testing:u:user3").unwrap()));
+
+        let page = reg.query(&query, page.next.as_ref(), 4).unwrap();
+        assert_eq!(page.documents.len(), 4);
+        let page = reg.query(&query, page.next.as_ref(), 4).unwrap();
+        assert_eq!(page.documents.len(), 2);
+        assert!(page.next.is_none());
+        assert!(reg.query(&query, None, 0).is_err());
+
+        // a cursor before the prefix starts at the prefix, one past it ends the scan
+        let before = DID::parse("did:other:u:user9").unwrap();
+        let page = reg.query(&query, Some(&before), 4).unwrap();
+        assert_eq!(
+            page.documents[0].id,
+            DID::parse("did:testing:u:user0").unwrap()
+        );
+        let past = DID::parse("did:zzz:u:user0").unwrap();
+        assert!(reg
+            .query(&query, Some(&past), 4)
+            .unwrap()
+            .documents
+            .is_empty());
+
+        let query = Query::default()
+            .prefix(DID::parse("did:testing:u:user").unwrap())
+            .prefix(DID::parse("did:testing:u:user1").unwrap());
+        let page = reg.query(&query, None, 10).unwrap();
+        assert_eq!(page.documents.len(), 1);
+        let query = Query::default()
+            .prefix(DID::parse("did:testing:u:user1").unwrap())
+            .prefix(DID::parse("did:testing:u:user2").unwrap());
+        assert!(reg.query(&query, None, 10).unwrap().documents.is_empty());
+
+        let query = Query::default().controller(admin.clone());
+        let page = reg.query(&query, None, 10).unwrap();
+        assert_eq!(page.documents.len(), 5);
+        assert!(page.next.is_none());
+
+        let query = Query::default()
+            .prefix(DID::parse("did:testing:u:user1").unwrap())
+            .or(Query::default().controller(admin.clone()).negate());
+        let page = reg.query(&query, None, 100).unwrap();
+        assert_eq!(page.documents.len(), 15);
+
+        let query = Query::default()
+            .method("testing")
+            .controller(admin)
+            .negate();
+        let page = reg.query(&query, None, 100).unwrap();
+        assert_eq!(page.documents.len(), 15);
+    }
+
+    #[test]
+    fn test_cache_document() {
+        use super::Registry;
+        use crate::{
+            did::DID,
+            document::{AlsoKnownAs, AlsoKnownAsEither, Document},
+            fetch::{FetchPolicy, Fetcher, StaticTransport},
+        };
+        use either::Either;
+        use url::Url;
+
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let did2 = DID::parse("did:testing:u:bob").unwrap();
+        let url = Url::parse("https://example.org/bob.json").unwrap();
+
+        let mut transport = StaticTransport::default();
+        transport
+            .insert_document(
+                url.clone(),
+                &Document {
+                    id: did2.clone(),
+                    also_known_as: Some(AlsoKnownAs(
+                        [AlsoKnownAsEither(Either::Left(did.clone()))].into(),
+                    )),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let mut reg: Registry = Default::default();
+        assert!(reg.cache_document(url.clone()).is_err());
+
+        let mut reg = Registry::new_with_fetcher(Fetcher::with_transport(
+            FetchPolicy::default(),
+            Box::new(transport),
+        ));
+        assert!(reg
+            .insert(Document {
+                id: did.clone(),
+                also_known_as: Some(AlsoKnownAs(
+                    [AlsoKnownAsEither(Either::Right(url.clone()))].into(),
+                )),
+                ..Default::default()
+            })
+            .is_ok());
+
+        assert!(reg.cache_document_for(&did, url.clone()).is_err());
+        assert!(reg.get(&did2).is_none());
+        assert!(reg
+            .cache_document(Url::parse("http://example.org/bob.json").unwrap())
+            .is_err());
+        assert!(reg.cache_document_for(&did2, url).is_ok());
+        assert!(reg.get(&did2).is_some());
+        assert!(reg.equivalent_to_did(&did, &did2).unwrap());
+    }
+
+    #[test]
+    fn test_stale_equivalents() {
+        use super::Registry;
+        use crate::{
+            did::DID,
+            document::{AlsoKnownAs, AlsoKnownAsEither, Document},
+            equivalence::Transitivity,
+            fetch::{FetchPolicy, Fetcher, StaticTransport},
+        };
+        use either::Either;
+        use url::Url;
+
+        let did = |name: &str| DID::parse(&format!("did:testing:u:{}", name)).unwrap();
+        let doc = |name: &str, aka: &[&str]| Document {
+            id: did(name),
+            also_known_as: Some(AlsoKnownAs(
+                aka.iter()
+                    .map(|name| AlsoKnownAsEither(Either::Left(did(name))))
+                    .collect(),
+            )),
+            ..Default::default()
+        };
+
+        // bob links alice and carol, but is stale as soon as it is cached
+        let url = Url::parse("https://example.org/bob.json").unwrap();
+        let mut transport = StaticTransport::default();
+        transport
+            .insert_document(url.clone(), &doc("bob", &["alice", "carol"]))
+            .unwrap();
+        transport
+            .responses
+            .get_mut(&url)
+            .unwrap()
+            .headers
+            .insert("cache-control".to_string(), "no-cache".to_string());
+
+        let mut reg = Registry::new_with_fetcher(Fetcher::with_transport(
+            FetchPolicy::default(),
+            Box::new(transport),
+        ));
+        reg.set_transitivity(Transitivity::Transitive);
+        reg.insert(doc("alice", &["bob"])).unwrap();
+        reg.insert(doc("carol", &["bob"])).unwrap();
+        reg.cache_document(url).unwrap();
+
+        assert!(reg.get(&did("bob")).is_none());
+        assert!(reg.equivalents(&did("bob")).is_none());
+        assert_eq!(reg.equivalents(&did("alice")), Some([did("alice")].into()));
+        assert!(!reg.equivalent_to_did(&did("alice"), &did("carol")).unwrap());
+        assert!(reg.equivalent_to_did(&did("alice"), &did("bob")).is_err());
+
+        // which is as it is once the stale document has been expired
+        assert_eq!(reg.expire(), vec![did("bob")]);
+        assert_eq!(reg.equivalents(&did("alice")), Some([did("alice")].into()));
+        assert!(!reg.equivalent_to_did(&did("alice"), &did("carol")).unwrap());
+    }
+
+    #[test]
+    fn test_refresh() {
+        use super::Registry;
+        use crate::{
+            cache::CachePolicy,
+            did::DID,
+            document::Document,
+            fetch::{FetchPolicy, FetchRequest, FetchResponse, Fetcher, Transport},
+        };
+        use std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
+        use url::Url;
+
+        // serves a document with an ETag, and honors If-None-Match
+        struct ETagTransport {
+            body: Vec<u8>,
+            cache_control: String,
+            requests: Arc<AtomicUsize>,
+        }
+
+        impl Transport for ETagTransport {
+            fn fetch(
+                &self,
+                request: &FetchRequest,
+                _policy: &FetchPolicy,
+            ) -> Result<FetchResponse, anyhow::Error> {
+                self.requests.fetch_add(1, Ordering::SeqCst);
+                let headers = [
+                    ("etag".to_string(), "\"v1\"".to_string()),
+                    ("cache-control".to_string(), self.cache_control.clone()),
+                ]
+                .into();
+
+                if request.headers.get("if-none-match").map(|s| s.as_str()) == Some("\"v1\"") {
+                    return Ok(FetchResponse {
+                        status: 304,
+                        headers,
+                        body: Vec::new(),
+                    });
+                }
+
+                Ok(FetchResponse {
+                    status: 200,
+                    headers,
+                    body: self.body.clone(),
+                })
+            }
+        }
+
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let url = Url::parse("https://example.org/alice.json").unwrap();
+        let doc = Document {
+            id: did.clone(),
+            ..Default::default()
+        };
+
+        let requests = Arc::new(AtomicUsize::default());
+        let registry = |cache_control: &str| {
+            Registry::new_with_fetcher(Fetcher::with_transport(
+                FetchPolicy::default(),
+                Box::new(ETagTransport {
+                    body: serde_json::to_vec(&doc).unwrap(),
+                    cache_control: cache_control.to_string(),
+                    requests: requests.clone(),
+                }),
+            ))
+        };
+
+        let mut reg = registry("no-cache");
+        assert!(reg.refresh(&did).is_err());
+        assert!(reg.cache_document(url.clone()).is_ok());
+        // stale immediately, but kept for revalidation
+        assert!(reg.get(&did).is_none());
+        assert!(reg.cached(&did).is_some());
+        assert_eq!(reg.len(), 0);
+        // re-fetching replaces rather than colliding
+        assert!(reg.cache_document(url.clone()).is_ok());
+        assert!(reg.refresh(&did).is_ok());
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+        assert_eq!(reg.expire(), vec![did.clone()]);
+        assert!(reg.cached(&did).is_none());
+
+        let mut reg = registry("max-age=60");
+        assert!(reg.cache_document_for(&did, url.clone()).is_ok());
+        assert_eq!(reg.get(&did), Some(doc.clone()));
+        let expires = reg.cached(&did).unwrap().expires;
+        std::thread::sleep(Duration::from_millis(10));
+        assert_eq!(reg.refresh(&did).unwrap(), doc);
+        assert!(reg.cached(&did).unwrap().expires > expires);
+        assert!(reg.expire().is_empty());
+
+        // inserting directly supersedes the cached copy, and blocks further caching
+        assert!(reg.insert(doc.clone()).is_ok());
+        assert!(reg.cached(&did).is_none());
+        assert!(reg.cache_document(url.clone()).is_err());
+        assert!(reg.remove(&did).is_some());
+
+        reg.set_cache_policy(CachePolicy {
+            capacity: 0,
+            ..Default::default()
+        });
+        assert!(reg.cache_document(url.clone()).is_ok());
+        assert!(reg.get(&did).is_none());
+
+        let mut reg = registry("no-store");
+        assert_eq!(reg.cache_document(url).unwrap(), doc);
+        assert!(reg.cached(&did).is_none());
+    }
+
+    #[test]
+    fn test_positional() {
+        use super::{Cursor, Registry};
+        use crate::{did::DID, document::Document};
+
+        let did = |i: usize| DID::parse(&format!("did:testing:u:{:04}", i)).unwrap();
+        let mut reg = Registry::default();
+
+        // insert out of order
+        for i in (0..100).rev().step_by(2).chain((0..100).step_by(2)) {
+            reg.insert(Document {
+                id: did(i),
+                ..Default::default()
+            })
+            .unwrap();
+        }
+
+        for i in 0..100 {
+            assert_eq!(reg[i].id, did(i));
+            assert_eq!(reg.position(&did(i)), Some(i));
+        }
+        assert!(reg.get_index(100).is_none());
+
+        reg.remove(&did(10));
+        assert_eq!(reg[10].id, did(11));
+        assert_eq!(reg.position(&did(10)), None);
+        assert_eq!(reg.position(&did(99)), Some(98));
+
+        assert_eq!(
+            reg.range(did(20)..did(30))
+                .map(|(did, _)| did.clone())
+                .collect::<Vec<DID>>(),
+            (20..30).map(did).collect::<Vec<DID>>()
+        );
+        assert_eq!(reg.range(&did(95)..).count(), 5);
+
+        let mut cursor = Cursor::default();
+        assert_eq!(cursor.take(&reg, 5).len(), 5);
+        assert_eq!(cursor.last(), Some(&did(4)));
+
+        reg.remove(&did(5));
+        reg.insert(Document {
+            id: did(10),
+            ..Default::default()
+        })
+        .unwrap();
+
+        assert_eq!(cursor.next(&reg).unwrap().id, did(6));
+        assert_eq!(cursor.take(&reg, 1000).len(), 93);
+        assert!(cursor.next(&reg).is_none());
+
+        let mut cursor = Cursor::after(did(97));
+        assert_eq!(cursor.next(&reg).unwrap().id, did(98));
+    }
+
+    #[test]
+    fn test_versions() {
+        use super::{Registry, Version};
+        use crate::{
+            did::DID,
+            document::{Controller, Document},
+        };
+        use either::Either;
+
+        let mut reg = Registry::default();
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let doc = Document {
+            id: did.clone(),
+            ..Default::default()
+        };
+
+        assert!(reg.replace(doc.clone(), Version(1)).is_err());
+
+        let inserted = reg.upsert(doc.clone()).unwrap();
+        assert!(inserted.previous.is_none());
+        assert_eq!(inserted.changed, ["id".to_string()].into());
+        assert_eq!(reg.version(&did), Some(inserted.version));
+
+        // writing the same document again changes nothing
+        let same = reg.upsert(doc.clone()).unwrap();
+        assert_eq!(same.version, inserted.version);
+        assert!(same.changed.is_empty());
+
+        let mut changed = doc.clone();
+        changed.controller = Some(Controller(Either::Left(did.clone())));
+        let replaced = reg.replace(changed.clone(), inserted.version).unwrap();
+        assert_eq!(replaced.previous, Some(doc.clone()));
+        assert_eq!(replaced.changed, ["controller".to_string()].into());
+        assert!(replaced.version > inserted.version);
+
+        let err = reg.replace(doc.clone(), inserted.version).unwrap_err();
+        assert!(err.to_string().contains("has been modified"));
+        assert_eq!(reg[&did], changed);
+
+        // versions are not reused after removal
+        reg.remove(&did);
+        assert!(reg.version(&did).is_none());
+        reg.insert(doc).unwrap();
+        assert!(reg.version(&did).unwrap() > replaced.version);
+        assert_eq!(
+            reg.version(&did)
+                .unwrap()
+                .to_string()
+                .parse::<Version>()
+                .unwrap(),
+            reg.version(&did).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_deactivate() {
+        use super::Registry;
+        use crate::{did::DID, document::Document, tombstone::Resurrection};
+
+        let mut reg = Registry::default();
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let doc = Document {
+            id: did.clone(),
+            ..Default::default()
+        };
+
+        assert!(reg.deactivate(&did).is_err());
+        reg.insert(doc.clone()).unwrap();
+        let version = reg.version(&did).unwrap();
+        assert_eq!(reg.deactivate(&did).unwrap(), doc);
+        assert!(reg.is_deactivated(&did));
+        assert!(reg.get(&did).is_none());
+        assert!(reg.version(&did).is_none());
+
+        let res = reg.resolve(&did).unwrap();
+        assert!(res.deactivated);
+        assert_eq!(res.document.id, did);
+        assert!(res.document.verification_method.is_none());
+        assert!(res.version.unwrap() > version);
+
+        let err = reg.insert(doc.clone()).unwrap_err();
+        assert!(err.to_string().contains("has been deactivated"));
+        assert!(reg.upsert(doc.clone()).is_err());
+
+        // tombstones survive a round trip through serialization
+        let tombstone = reg.tombstone(&did).unwrap().clone();
+        let json = serde_json::to_string(&tombstone).unwrap();
+        let mut restored = Registry::default();
+        restored
+            .insert_tombstone(serde_json::from_str(&json).unwrap())
+            .unwrap();
+        assert_eq!(restored.tombstones().collect::<Vec<_>>(), vec![&tombstone]);
+        assert!(restored.insert(doc.clone()).is_err());
+
+        // versions are not reused after restoring
+        restored.set_resurrection(Resurrection::Allow);
+        restored.insert(doc.clone()).unwrap();
+        assert!(!restored.is_deactivated(&did));
+        assert!(restored.version(&did).unwrap() > tombstone.version);
+        assert!(restored.insert_tombstone(tombstone).is_err());
+        assert!(!restored.resolve(&did).unwrap().deactivated);
+    }
 }
@@ -0,0 +1,478 @@
+use crate::{
+    fetch::{FetchRequest, Fetcher},
+    journal::{JournalEntry, GENESIS},
+    registry::Registry,
+    shared::SharedRegistry,
+};
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{mpsc, Arc},
+};
+use url::Url;
+
+/// The number of entries requested at a time by a [Replicator], unless set otherwise.
+pub const DEFAULT_BATCH_SIZE: usize = 100;
+
+/// FeedRequest asks for the change feed of a [Registry] from a position onwards. See
+/// [Registry::changes].
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub struct FeedRequest {
+    /// The sequence number of the first entry wanted.
+    pub since: u64,
+    /// The most entries to return.
+    pub limit: usize,
+}
+
+impl Default for FeedRequest {
+    fn default() -> Self {
+        Self {
+            since: 0,
+            limit: DEFAULT_BATCH_SIZE,
+        }
+    }
+}
+
+impl FeedRequest {
+    /// The [Url] of the request, made by adding `since` and `limit` query parameters to the base
+    /// [Url] of a feed served over HTTP.
+    pub fn to_url(&self, base: &Url) -> Url {
+        let mut url = base.clone();
+        url.query_pairs_mut()
+            .append_pair("since", &self.since.to_string())
+            .append_pair("limit", &self.limit.to_string());
+        url
+    }
+
+    /// Parse a request from the query parameters of a [Url], for serving a feed over HTTP.
+    /// Missing parameters take their default values.
+    pub fn from_url(url: &Url) -> Result<Self, anyhow::Error> {
+        let mut request = Self::default();
+        for (key, value) in url.query_pairs() {
+            match key.as_ref() {
+                "since" => request.since = value.parse()?,
+                "limit" => request.limit = value.parse()?,
+                _ => {}
+            }
+        }
+
+        Ok(request)
+    }
+}
+
+/// ChangeBatch is a page of the change feed of a [Registry]: consecutive entries of its
+/// [crate::journal::Journal], as returned by [Registry::changes].
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChangeBatch {
+    pub entries: Vec<JournalEntry>,
+    /// The length of the journal when the batch was taken, which is the sequence number its next
+    /// entry will have.
+    pub len: u64,
+}
+
+/// FeedTransport carries [FeedRequest]s to the change feed of another [Registry] for a
+/// [Replicator]. [ChannelFeed] serves a registry in the same process, and [HttpFeed] reaches one
+/// served over HTTP.
+pub trait FeedTransport: Send + Sync {
+    fn changes(&self, request: &FeedRequest) -> Result<ChangeBatch, anyhow::Error>;
+}
+
+type ChannelRequest = (
+    FeedRequest,
+    mpsc::Sender<Result<ChangeBatch, anyhow::Error>>,
+);
+
+/// A [FeedTransport] which serves the change feed of a [SharedRegistry] in the same process,
+/// over a channel to a thread which answers requests from the current snapshot of the registry.
+/// The thread exits when the feed is dropped.
+#[derive(Clone, Debug)]
+pub struct ChannelFeed {
+    requests: mpsc::Sender<ChannelRequest>,
+}
+
+impl ChannelFeed {
+    /// Start serving the change feed of the registry.
+    pub fn serve(source: Arc<SharedRegistry>) -> Self {
+        let (requests, rx) = mpsc::channel::<ChannelRequest>();
+
+        std::thread::spawn(move || {
+            for (request, reply) in rx {
+                let _ = reply.send(source.snapshot().changes(request.since, request.limit));
+            }
+        });
+
+        Self { requests }
+    }
+}
+
+impl FeedTransport for ChannelFeed {
+    fn changes(&self, request: &FeedRequest) -> Result<ChangeBatch, anyhow::Error> {
+        let (reply, rx) = mpsc::channel();
+        self.requests
+            .send((*request, reply))
+            .map_err(|_| anyhow!("Change feed is no longer being served"))?;
+        rx.recv()
+            .map_err(|_| anyhow!("Change feed is no longer being served"))?
+    }
+}
+
+/// A [FeedTransport] which requests the change feed of a [Registry] served over HTTP. Requests
+/// are `GET`s of the feed's [Url] with the query parameters given by [FeedRequest::to_url], which
+/// are answered with a JSON-encoded [ChangeBatch]. They are made by a [Fetcher], so its
+/// [crate::fetch::FetchPolicy] applies, and the body size limit must allow for a whole batch.
+#[derive(Debug)]
+pub struct HttpFeed {
+    url: Url,
+    fetcher: Fetcher,
+}
+
+impl HttpFeed {
+    /// Create a feed for the [Url] using the default [Fetcher].
+    pub fn new(url: Url) -> Self {
+        Self::with_fetcher(url, Fetcher::default())
+    }
+
+    /// Create a feed for the [Url] using the provided [Fetcher].
+    pub fn with_fetcher(url: Url, fetcher: Fetcher) -> Self {
+        Self { url, fetcher }
+    }
+}
+
+impl FeedTransport for HttpFeed {
+    fn changes(&self, request: &FeedRequest) -> Result<ChangeBatch, anyhow::Error> {
+        let url = request.to_url(&self.url);
+        let resp = self.fetcher.fetch(&FetchRequest::new(url.clone()))?;
+
+        if !(200..300).contains(&resp.status) {
+            return Err(anyhow!(
+                "Fetching {} returned HTTP status {}",
+                url,
+                resp.status
+            ));
+        }
+
+        Ok(serde_json::from_slice(&resp.body)?)
+    }
+}
+
+/// Replicator pulls the change feed of another [Registry] through a [FeedTransport], and applies
+/// it to a local one. The source must have its [crate::journal::Journal] enabled.
+///
+/// Each entry is checked against the hash chain of the journal before it is applied, so a
+/// replica cannot be fed changes which the source did not make. Entries are pulled in batches,
+/// and as each entry carries the whole state of its [DID], only the last entry for each [DID] in
+/// a batch is applied. Changes are applied idempotently: documents which are already as the entry
+/// leaves them, and [DID]s which are already removed or deactivated, are left alone, so pulling
+/// the same entries again, or starting over with a new replicator, does no harm. Documents are
+/// given [crate::registry::Version]s by the replica, as it may hold documents of its own, but
+/// keep the times of the changes made at the source. Resurrected [DID]s are allowed regardless of
+/// the replica's [crate::tombstone::Resurrection] policy, as the source allowed them.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+/// use std::sync::Arc;
+///
+/// let mut source = Registry::default();
+/// source.enable_journal();
+/// let source = Arc::new(SharedRegistry::from(source));
+///
+/// let did = DID::parse("did:mymethod:alice").unwrap();
+/// source.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+///
+/// let mut replica = Registry::default();
+/// let mut replicator = Replicator::new(ChannelFeed::serve(source.clone()));
+/// assert_eq!(replicator.pull(&mut replica).unwrap(), 1);
+/// assert!(replica.get(&did).is_some());
+///
+/// source.deactivate(&did).unwrap();
+/// assert_eq!(replicator.pull(&mut replica).unwrap(), 1);
+/// assert!(replica.is_deactivated(&did));
+/// assert_eq!(replicator.position(), 2);
+/// ```
+pub struct Replicator {
+    transport: Box<dyn FeedTransport>,
+    position: u64,
+    head: String,
+    batch_size: usize,
+}
+
+impl std::fmt::Debug for Replicator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Replicator")
+            .field("position", &self.position)
+            .field("head", &self.head)
+            .field("batch_size", &self.batch_size)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Replicator {
+    /// Create a [Replicator] which starts from the beginning of the feed.
+    pub fn new(transport: impl FeedTransport + 'static) -> Self {
+        Self::resume(transport, 0, GENESIS.to_string())
+    }
+
+    /// Create a [Replicator] which carries on from a [Replicator::position] and [Replicator::head]
+    /// saved earlier.
+    pub fn resume(transport: impl FeedTransport + 'static, position: u64, head: String) -> Self {
+        Self {
+            transport: Box::new(transport),
+            position,
+            head,
+            batch_size: DEFAULT_BATCH_SIZE,
+        }
+    }
+
+    /// Set the number of entries requested at a time.
+    pub fn set_batch_size(&mut self, batch_size: usize) {
+        self.batch_size = batch_size.max(1)
+    }
+
+    /// The sequence number of the next entry to be pulled.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// The hash of the last entry pulled, or [GENESIS] if there has been none.
+    pub fn head(&self) -> &str {
+        &self.head
+    }
+
+    /// Pull and apply the changes made to the source since the last pull, until the replica has
+    /// caught up with it. Each batch is applied atomically; if one fails, the changes of earlier
+    /// batches are kept, and the next pull starts from the failed batch. Returns the number of
+    /// changes which altered the replica.
+    pub fn pull(&mut self, reg: &mut Registry) -> Result<usize, anyhow::Error> {
+        let mut applied = 0;
+
+        loop {
+            let batch = self.transport.changes(&FeedRequest {
+                since: self.position,
+                limit: self.batch_size,
+            })?;
+
+            if batch.len < self.position {
+                return Err(anyhow!(
+                    "Change feed has {} entries, but {} have been pulled from it",
+                    batch.len,
+                    self.position
+                ));
+            }
+
+            let (mut position, mut head) = (self.position, self.head.clone());
+            // entries carry the whole state of their DID, so only the last for each matters
+            let mut latest = BTreeMap::new();
+
+            for entry in &batch.entries {
+                // entries delivered again
+                if entry.sequence < position {
+                    continue;
+                }
+
+                if entry.sequence != position {
+                    return Err(anyhow!(
+                        "Change feed skipped from entry {} to {}",
+                        position,
+                        entry.sequence
+                    ));
+                }
+
+                if entry.previous != head {
+                    return Err(anyhow!(
+                        "Change feed entry {} does not follow the entry before it",
+                        entry.sequence
+                    ));
+                }
+
+                entry.verify()?;
+                latest.insert(&entry.id, entry);
+                position += 1;
+                head = entry.hash.clone();
+            }
+
+            let mut entries: Vec<&JournalEntry> = latest.into_values().collect();
+            entries.sort_by_key(|entry| entry.sequence);
+
+            let mut staged = reg.staged();
+            for entry in entries {
+                if staged.apply_change(entry)? {
+                    applied += 1;
+                }
+            }
+
+            reg.publish(staged);
+            let progressed = position > self.position;
+            self.position = position;
+            self.head = head;
+
+            if !progressed || self.position >= batch.len {
+                return Ok(applied);
+            }
+        }
+    }
+}
+
+mod tests {
+    #[cfg(test)]
+    fn doc(name: &str) -> crate::document::Document {
+        use crate::{did::DID, document::Document};
+
+        Document {
+            id: DID::parse(&format!("did:testing:u:{}", name)).unwrap(),
+            ..Default::default()
+        }
+    }
+
+    // a journaled source registry holding the named documents, which allows resurrection
+    #[cfg(test)]
+    fn fixture(names: &[&str]) -> std::sync::Arc<crate::shared::SharedRegistry> {
+        use crate::{registry::Registry, shared::SharedRegistry, tombstone::Resurrection};
+        use std::sync::Arc;
+
+        let mut source = Registry::default();
+        source.enable_journal();
+        source.set_resurrection(Resurrection::Allow);
+        let source = Arc::new(SharedRegistry::from(source));
+        for name in names {
+            source.insert(doc(name)).unwrap();
+        }
+
+        source
+    }
+
+    #[test]
+    fn test_pull() {
+        use super::{ChannelFeed, Replicator};
+        use crate::{
+            document::{Controller, Document},
+            registry::Registry,
+        };
+        use either::Either;
+
+        let did = |name: &str| doc(name).id;
+        let source = fixture(&["alice", "bob", "charlie", "dave", "erin"]);
+
+        let mut replica = Registry::default();
+        let mut replicator = Replicator::new(ChannelFeed::serve(source.clone()));
+        replicator.set_batch_size(2);
+        assert_eq!(replicator.pull(&mut replica).unwrap(), 5);
+        assert!(source.snapshot().diff(&replica).is_empty());
+        assert_eq!(replicator.pull(&mut replica).unwrap(), 0);
+
+        source
+            .update(Document {
+                controller: Some(Controller(Either::Left(did("bob")))),
+                ..doc("alice")
+            })
+            .unwrap();
+        source.remove(&did("bob"));
+        source.deactivate(&did("charlie")).unwrap();
+        source.insert(doc("charlie")).unwrap();
+        source.deactivate(&did("dave")).unwrap();
+        // charlie is deactivated and resurrected within a batch, which leaves it as it was
+        assert_eq!(replicator.pull(&mut replica).unwrap(), 3);
+        assert!(source.snapshot().diff(&replica).is_empty());
+        assert!(replica.is_deactivated(&did("dave")));
+        assert!(!replica.is_deactivated(&did("charlie")));
+        assert_eq!(replicator.position(), 10);
+
+        // tombstones keep the time of the deactivation at the source
+        let deactivated = |reg: &Registry| reg.tombstone(&did("dave")).unwrap().deactivated.clone();
+        assert_eq!(deactivated(&replica), deactivated(&source.snapshot()));
+
+        // starting over changes nothing
+        let mut again = Replicator::new(ChannelFeed::serve(source.clone()));
+        assert_eq!(again.pull(&mut replica).unwrap(), 0);
+        assert!(source.snapshot().diff(&replica).is_empty());
+        assert_eq!(again.head(), replicator.head());
+
+        // and a new replica, which never saw dave, still gets the source's tombstone
+        let mut fresh = Registry::default();
+        assert_eq!(
+            Replicator::new(ChannelFeed::serve(source.clone()))
+                .pull(&mut fresh)
+                .unwrap(),
+            4
+        );
+        assert!(source.snapshot().diff(&fresh).is_empty());
+        assert_eq!(deactivated(&fresh), deactivated(&source.snapshot()));
+    }
+
+    #[test]
+    fn test_refused() {
+        use super::{ChannelFeed, Replicator};
+        use crate::{registry::Registry, shared::SharedRegistry};
+        use std::sync::Arc;
+
+        let source = fixture(&["alice", "bob", "charlie", "dave", "erin", "frank"]);
+
+        let mut replica = Registry::default();
+        let mut replicator = Replicator::new(ChannelFeed::serve(source.clone()));
+        replicator.set_batch_size(3);
+        assert_eq!(replicator.pull(&mut replica).unwrap(), 6);
+
+        // a replicator resumed with the wrong head refuses the feed
+        let mut wrong = Replicator::resume(
+            ChannelFeed::serve(source.clone()),
+            3,
+            replicator.head().to_string(),
+        );
+        assert!(wrong.pull(&mut replica).is_err());
+        assert_eq!(wrong.position(), 3);
+
+        // as does one pulling from a registry without a journal
+        let plain = Arc::new(SharedRegistry::default());
+        assert!(Replicator::new(ChannelFeed::serve(plain))
+            .pull(&mut replica)
+            .is_err());
+    }
+
+    #[test]
+    fn test_http_feed() {
+        use super::{FeedRequest, FeedTransport, HttpFeed, Replicator};
+        use crate::{
+            fetch::{FetchPolicy, FetchRequest, FetchResponse, Fetcher, Transport},
+            registry::Registry,
+            shared::SharedRegistry,
+        };
+        use std::sync::Arc;
+        use url::Url;
+
+        // a transport standing in for a server
+        struct Server(Arc<SharedRegistry>);
+
+        impl Transport for Server {
+            fn fetch(
+                &self,
+                request: &FetchRequest,
+                _: &FetchPolicy,
+            ) -> Result<FetchResponse, anyhow::Error> {
+                let req = FeedRequest::from_url(&request.url)?;
+                let batch = self.0.snapshot().changes(req.since, req.limit)?;
+                Ok(FetchResponse {
+                    status: 200,
+                    body: serde_json::to_vec(&batch)?,
+                    ..Default::default()
+                })
+            }
+        }
+
+        let source = fixture(&["alice", "bob", "charlie"]);
+
+        let url = Url::parse("https://example.org/changes").unwrap();
+        let fetcher =
+            Fetcher::with_transport(FetchPolicy::default(), Box::new(Server(source.clone())));
+        let feed = HttpFeed::with_fetcher(url.clone(), fetcher);
+        assert_eq!(feed.changes(&FeedRequest::default()).unwrap().len, 3);
+
+        let mut replica = Registry::default();
+        let mut replicator = Replicator::new(feed);
+        assert_eq!(replicator.pull(&mut replica).unwrap(), 3);
+        assert!(source.snapshot().diff(&replica).is_empty());
+
+        let req = FeedRequest { since: 3, limit: 7 };
+        assert_eq!(FeedRequest::from_url(&req.to_url(&url)).unwrap(), req);
+        assert_eq!(FeedRequest::from_url(&url).unwrap(), FeedRequest::default());
+    }
+}
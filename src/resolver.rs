@@ -0,0 +1,604 @@
+use crate::{
+    cache::CachePolicy,
+    did::DID,
+    document::Document,
+    fetch::{
+        response_headers, too_large, FetchPolicy, FetchRequest, FetchResponse, Fetcher,
+        StaticTransport, Transport,
+    },
+    metadata::DocumentMetadata,
+    registry::{Registry, Resolution},
+};
+use anyhow::anyhow;
+use std::{
+    collections::BTreeMap,
+    future::Future,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{OnceCell, RwLock, RwLockReadGuard, RwLockWriteGuard};
+use url::{Host, Url};
+
+/// A boxed, [Send]able future, as returned by the object-safe async traits in this module.
+pub type BoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+/// AsyncTransport is the non-blocking counterpart of [Transport], used by an [AsyncFetcher].
+/// Implementations carry the same responsibilities for enforcing the network-level portions of
+/// the [FetchPolicy].
+pub trait AsyncTransport: Send + Sync {
+    fn fetch<'a>(
+        &'a self,
+        request: &'a FetchRequest,
+        policy: &'a FetchPolicy,
+    ) -> BoxFuture<'a, Result<FetchResponse, anyhow::Error>>;
+}
+
+/// The default [AsyncTransport], built on [reqwest]'s async client. It behaves like
+/// [crate::fetch::ReqwestTransport]: redirects are checked hop by hop, and connections are pinned
+/// to the checked addresses. Unlike it, it is safe to use from within a tokio runtime.
+#[derive(Clone, Debug, Default)]
+pub struct AsyncReqwestTransport;
+
+impl AsyncTransport for AsyncReqwestTransport {
+    fn fetch<'a>(
+        &'a self,
+        request: &'a FetchRequest,
+        policy: &'a FetchPolicy,
+    ) -> BoxFuture<'a, Result<FetchResponse, anyhow::Error>> {
+        Box::pin(async move {
+            let mut url = request.url.clone();
+
+            for _ in 0..=policy.max_redirects {
+                policy.check_url(&url)?;
+
+                let mut builder = reqwest::Client::builder()
+                    .no_proxy()
+                    .redirect(reqwest::redirect::Policy::none())
+                    .timeout(policy.timeout)
+                    .connect_timeout(policy.connect_timeout);
+
+                if let Some(Host::Domain(domain)) = url.host() {
+                    let port = url.port_or_known_default().unwrap_or(443);
+                    let addrs: Vec<SocketAddr> =
+                        tokio::net::lookup_host((domain, port)).await?.collect();
+                    if addrs.is_empty() {
+                        return Err(anyhow!("Host {} did not resolve", domain));
+                    }
+
+                    for addr in &addrs {
+                        policy.check_addr(&addr.ip())?;
+                    }
+
+                    builder = builder.resolve_to_addrs(domain, &addrs);
+                }
+
+                let mut req = builder.build()?.get(url.clone());
+                for (key, value) in &request.headers {
+                    req = req.header(key, value);
+                }
+
+                let mut resp = req.send().await?;
+
+                if resp.status().is_redirection() {
+                    if let Some(location) = resp.headers().get(reqwest::header::LOCATION) {
+                        url = url.join(location.to_str()?)?;
+                        continue;
+                    }
+                }
+
+                if let Some(len) = resp.content_length() {
+                    if len as usize > policy.max_body_size {
+                        return Err(too_large(&url, policy));
+                    }
+                }
+
+                let headers = response_headers(resp.headers());
+                let status = resp.status().as_u16();
+                let mut body = Vec::new();
+
+                while let Some(chunk) = resp.chunk().await? {
+                    if body.len() + chunk.len() > policy.max_body_size {
+                        return Err(too_large(&url, policy));
+                    }
+
+                    body.extend_from_slice(&chunk);
+                }
+
+                return Ok(FetchResponse {
+                    status,
+                    headers,
+                    body,
+                });
+            }
+
+            Err(anyhow!(
+                "Too many redirects fetching {}; limit is {}",
+                request.url,
+                policy.max_redirects
+            ))
+        })
+    }
+}
+
+impl AsyncTransport for StaticTransport {
+    fn fetch<'a>(
+        &'a self,
+        request: &'a FetchRequest,
+        policy: &'a FetchPolicy,
+    ) -> BoxFuture<'a, Result<FetchResponse, anyhow::Error>> {
+        Box::pin(async move { Transport::fetch(self, request, policy) })
+    }
+}
+
+/// AsyncFetcher retrieves remote [Document]s on behalf of an [AsyncRegistry], according to a
+/// [FetchPolicy] and using an [AsyncTransport]. It is the non-blocking counterpart of [Fetcher].
+pub struct AsyncFetcher {
+    policy: FetchPolicy,
+    transport: Box<dyn AsyncTransport>,
+}
+
+impl Default for AsyncFetcher {
+    fn default() -> Self {
+        Self::new(FetchPolicy::default())
+    }
+}
+
+impl std::fmt::Debug for AsyncFetcher {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncFetcher")
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncFetcher {
+    /// Create an [AsyncFetcher] using the [AsyncReqwestTransport].
+    pub fn new(policy: FetchPolicy) -> Self {
+        Self::with_transport(policy, Box::new(AsyncReqwestTransport))
+    }
+
+    /// Create an [AsyncFetcher] using the provided [AsyncTransport].
+    pub fn with_transport(policy: FetchPolicy, transport: Box<dyn AsyncTransport>) -> Self {
+        Self { policy, transport }
+    }
+
+    /// Retrieve the [FetchPolicy] in use.
+    pub fn policy(&self) -> &FetchPolicy {
+        &self.policy
+    }
+
+    /// Perform a request through the [AsyncTransport], after checking it against the
+    /// [FetchPolicy].
+    pub async fn fetch(&self, request: &FetchRequest) -> Result<FetchResponse, anyhow::Error> {
+        self.policy.check_url(&request.url)?;
+        self.transport.fetch(request, &self.policy).await
+    }
+
+    /// Fetch a JSON [Document] from the [Url]. If a [DID] is expected, the document's `id` must
+    /// match it.
+    pub async fn fetch_document(
+        &self,
+        url: &Url,
+        expected: Option<&DID>,
+    ) -> Result<Document, anyhow::Error> {
+        let resp = self.fetch(&FetchRequest::new(url.clone())).await?;
+        Fetcher::parse_document(url, &resp, expected)
+    }
+}
+
+/// Locator determines the hypertext [Url] a [DID]'s document can be fetched from, for DID methods
+/// which are resolved over HTTP. Closures taking a [DID] and returning an [`Option<Url>`] are
+/// Locators.
+pub trait Locator: Send + Sync {
+    fn locate(&self, did: &DID) -> Option<Url>;
+}
+
+impl<F> Locator for F
+where
+    F: Fn(&DID) -> Option<Url> + Send + Sync,
+{
+    fn locate(&self, did: &DID) -> Option<Url> {
+        self(did)
+    }
+}
+
+/// Locates `did:web` documents according to <https://w3c-ccg.github.io/did-method-web/>. Other
+/// methods are not located.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let locate = |s| WebLocator.locate(&DID::parse(s).unwrap()).map(|u| u.to_string());
+/// assert_eq!(locate("did:web:example.org").unwrap(), "https://example.org/.well-known/did.json");
+/// assert_eq!(locate("did:web:example.org%3A8443:u:alice").unwrap(), "https://example.org:8443/u/alice/did.json");
+/// assert!(locate("did:key:z6Mk").is_none());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct WebLocator;
+
+impl Locator for WebLocator {
+    fn locate(&self, did: &DID) -> Option<Url> {
+        if did.name != b"web" {
+            return None;
+        }
+
+        let id = String::from_utf8(did.id.clone()).ok()?;
+        let mut parts = id.split(':');
+        let host = parts.next()?.replace("%3A", ":").replace("%3a", ":");
+        let path: Vec<&str> = parts.collect();
+
+        let s = if path.is_empty() {
+            format!("https://{}/.well-known/did.json", host)
+        } else {
+            format!("https://{}/{}/did.json", host, path.join("/"))
+        };
+
+        Url::parse(&s).ok()
+    }
+}
+
+/// AsyncResolver resolves a [DID] to its [Document] without blocking.
+pub trait AsyncResolver: Send + Sync {
+    fn resolve<'a>(&'a self, did: &'a DID) -> BoxFuture<'a, Result<Document, anyhow::Error>>;
+}
+
+// requests which are de-duplicated while in flight
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Inflight {
+    Fetch(Url, Option<DID>),
+    Refresh(DID),
+}
+
+type InflightResult = Arc<OnceCell<Result<Document, String>>>;
+
+/// AsyncRegistry wraps a [Registry] for use from async code, fetching remote documents with an
+/// [AsyncFetcher] instead of the blocking [Fetcher]. The [Registry] itself is held behind a
+/// [tokio::sync::RwLock]; its full API is available through [AsyncRegistry::read] and
+/// [AsyncRegistry::write]. Locks are not held while fetching.
+///
+/// Concurrent fetches are de-duplicated: while a document is being fetched or refreshed, other
+/// callers asking for the same one wait for that request to finish and share its result, rather
+/// than making their own.
+///
+/// Available with the `async` feature.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+/// use url::Url;
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let did = DID::parse("did:web:example.org").unwrap();
+/// let mut transport = StaticTransport::default();
+/// transport.insert_document(
+///     Url::parse("https://example.org/.well-known/did.json").unwrap(),
+///     &Document{ id: did.clone(), ..Default::default() },
+/// ).unwrap();
+///
+/// let reg = AsyncRegistry::new_with_fetcher(
+///     AsyncFetcher::with_transport(FetchPolicy::default(), Box::new(transport)),
+/// );
+/// assert_eq!(reg.resolve(&did).await.unwrap().id, did);
+/// assert!(reg.read().await.cached(&did).is_some());
+/// # });
+/// ```
+pub struct AsyncRegistry {
+    registry: RwLock<Registry>,
+    fetcher: AsyncFetcher,
+    locator: Box<dyn Locator>,
+    inflight: Mutex<BTreeMap<Inflight, InflightResult>>,
+}
+
+impl Default for AsyncRegistry {
+    fn default() -> Self {
+        Self::new_with_fetcher(AsyncFetcher::default())
+    }
+}
+
+impl std::fmt::Debug for AsyncRegistry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AsyncRegistry")
+            .field("fetcher", &self.fetcher)
+            .finish_non_exhaustive()
+    }
+}
+
+impl AsyncRegistry {
+    /// Create an empty registry which fetches remote documents with the provided [AsyncFetcher],
+    /// locating `did:web` documents with the [WebLocator].
+    pub fn new_with_fetcher(fetcher: AsyncFetcher) -> Self {
+        Self::from_registry(Registry::default(), fetcher)
+    }
+
+    /// Wrap an existing [Registry]. Any blocking [Fetcher] it was created with is not used by
+    /// the [AsyncRegistry].
+    pub fn from_registry(registry: Registry, fetcher: AsyncFetcher) -> Self {
+        Self {
+            registry: RwLock::new(registry),
+            fetcher,
+            locator: Box::new(WebLocator),
+            inflight: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Replace the [Locator] used by [AsyncRegistry::resolve].
+    pub fn set_locator(&mut self, locator: Box<dyn Locator>) {
+        self.locator = locator;
+    }
+
+    /// Change the [CachePolicy] of the remote cache.
+    pub async fn set_cache_policy(&self, policy: CachePolicy) {
+        self.registry.write().await.set_cache_policy(policy)
+    }
+
+    /// Lock the [Registry] for reading.
+    pub async fn read(&self) -> RwLockReadGuard<'_, Registry> {
+        self.registry.read().await
+    }
+
+    /// Lock the [Registry] for writing.
+    pub async fn write(&self) -> RwLockWriteGuard<'_, Registry> {
+        self.registry.write().await
+    }
+
+    /// Unwrap the [Registry].
+    pub fn into_inner(self) -> Registry {
+        self.registry.into_inner()
+    }
+
+    /// See [Registry::get].
+    pub async fn get(&self, did: &DID) -> Option<Document> {
+        self.registry.read().await.get(did)
+    }
+
+    /// See [Registry::insert].
+    pub async fn insert(&self, doc: Document) -> Result<(), anyhow::Error> {
+        self.registry.write().await.insert(doc)
+    }
+
+    /// See [Registry::update].
+    pub async fn update(&self, doc: Document) -> Result<Document, anyhow::Error> {
+        self.registry.write().await.update(doc)
+    }
+
+    /// See [Registry::remove].
+    pub async fn remove(&self, did: &DID) -> Option<Document> {
+        self.registry.write().await.remove(did)
+    }
+
+    /// See [Registry::deactivate].
+    pub async fn deactivate(&self, did: &DID) -> Result<Document, anyhow::Error> {
+        self.registry.write().await.deactivate(did)
+    }
+
+    /// Resolve a [DID]: documents in the registry, or fresh in the remote cache, are returned
+    /// directly. Stale cached documents are refreshed, and any others are fetched from the [Url]
+    /// provided by the [Locator]. Deactivated [DID]s are never fetched; their minimal document is
+    /// returned instead. See [Registry::resolve].
+    pub async fn resolve(&self, did: &DID) -> Result<Document, anyhow::Error> {
+        let stale = {
+            let reg = self.registry.read().await;
+            if let Some(res) = reg.resolve(did) {
+                return Ok(Arc::unwrap_or_clone(res.document));
+            }
+
+            reg.cached(did).is_some()
+        };
+
+        if stale {
+            return self.refresh(did).await;
+        }
+
+        match self.locator.locate(did) {
+            Some(url) => self.cache_document_for(did, url).await,
+            None => Err(anyhow!("DID {} cannot be located", did)),
+        }
+    }
+
+    /// Resolve a [DID] as [AsyncRegistry::resolve] does, returning the [Resolution] with the
+    /// metadata of the document, such as its `canonicalId`. Documents which the policy for their
+    /// method does not allow to be cached are returned with empty metadata.
+    pub async fn resolution(&self, did: &DID) -> Result<Resolution, anyhow::Error> {
+        let document = self.resolve(did).await?;
+
+        Ok(match self.registry.read().await.resolve(did) {
+            Some(res) => res,
+            None => Resolution {
+                document: Arc::new(document),
+                deactivated: false,
+                version: None,
+                metadata: DocumentMetadata::default(),
+            },
+        })
+    }
+
+    /// See [Registry::canonical_did].
+    pub async fn canonical_did(&self, did: &DID) -> Option<DID> {
+        self.registry.read().await.canonical_did(did)
+    }
+
+    /// Fetch a remote document from a hypertext [Url] and insert it into the remote cache. See
+    /// [Registry::cache_document].
+    pub async fn cache_document(&self, url: Url) -> Result<Document, anyhow::Error> {
+        self.once(Inflight::Fetch(url.clone(), None), self.cache(url, None))
+            .await
+    }
+
+    /// Fetch the remote document for a [DID] from a hypertext [Url] and insert it into the remote
+    /// cache. See [Registry::cache_document_for].
+    pub async fn cache_document_for(&self, did: &DID, url: Url) -> Result<Document, anyhow::Error> {
+        self.once(
+            Inflight::Fetch(url.clone(), Some(did.clone())),
+            self.cache(url, Some(did)),
+        )
+        .await
+    }
+
+    /// Revalidate a document in the remote cache. See [Registry::refresh].
+    pub async fn refresh(&self, did: &DID) -> Result<Document, anyhow::Error> {
+        self.once(Inflight::Refresh(did.clone()), async {
+            let req = self.registry.read().await.revalidation_request(did)?;
+            let resp = self.fetcher.fetch(&req).await?;
+            self.registry
+                .write()
+                .await
+                .apply_revalidation(did, &req, &resp)
+        })
+        .await
+    }
+
+    async fn cache(&self, url: Url, expected: Option<&DID>) -> Result<Document, anyhow::Error> {
+        // another caller may have just finished fetching this document
+        if let Some(did) = expected {
+            if let Some(entry) = self.registry.read().await.cached(did) {
+                if entry.url == url && entry.is_fresh(std::time::SystemTime::now()) {
+                    return Ok(entry.document.as_ref().clone());
+                }
+            }
+        }
+
+        let resp = self.fetcher.fetch(&FetchRequest::new(url.clone())).await?;
+        self.registry
+            .write()
+            .await
+            .apply_fetch(url, &resp, expected)
+    }
+
+    // run the future unless an identical one is already running, in which case wait for its
+    // result instead. Errors are shared as their rendered message.
+    async fn once(
+        &self,
+        key: Inflight,
+        fut: impl Future<Output = Result<Document, anyhow::Error>>,
+    ) -> Result<Document, anyhow::Error> {
+        let cell = self
+            .inflight
+            .lock()
+            .unwrap()
+            .entry(key.clone())
+            .or_default()
+            .clone();
+
+        let res = cell
+            .get_or_init(|| async { fut.await.map_err(|e| format!("{:#}", e)) })
+            .await
+            .clone();
+
+        let mut inflight = self.inflight.lock().unwrap();
+        if inflight
+            .get(&key)
+            .map(|current| Arc::ptr_eq(current, &cell))
+            .unwrap_or(false)
+        {
+            inflight.remove(&key);
+        }
+
+        res.map_err(|e| anyhow!(e))
+    }
+}
+
+impl AsyncResolver for AsyncRegistry {
+    fn resolve<'a>(&'a self, did: &'a DID) -> BoxFuture<'a, Result<Document, anyhow::Error>> {
+        Box::pin(AsyncRegistry::resolve(self, did))
+    }
+}
+
+// the tokio test macros are only available to test builds
+#[cfg(test)]
+mod tests {
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn test_deduplicated_resolve() {
+        use super::{AsyncFetcher, AsyncRegistry, AsyncResolver, AsyncTransport, BoxFuture};
+        use crate::{
+            did::DID,
+            document::Document,
+            fetch::{FetchPolicy, FetchRequest, FetchResponse, StaticTransport, Transport},
+        };
+        use std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
+        use url::Url;
+
+        struct SlowTransport {
+            inner: StaticTransport,
+            count: Arc<AtomicUsize>,
+        }
+
+        impl AsyncTransport for SlowTransport {
+            fn fetch<'a>(
+                &'a self,
+                request: &'a FetchRequest,
+                policy: &'a FetchPolicy,
+            ) -> BoxFuture<'a, Result<FetchResponse, anyhow::Error>> {
+                Box::pin(async move {
+                    self.count.fetch_add(1, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Transport::fetch(&self.inner, request, policy)
+                })
+            }
+        }
+
+        let did = DID::parse("did:web:example.org:u:alice").unwrap();
+        let mut inner = StaticTransport::default();
+        inner
+            .insert_document(
+                Url::parse("https://example.org/u/alice/did.json").unwrap(),
+                &Document {
+                    id: did.clone(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+
+        let count = Arc::new(AtomicUsize::new(0));
+        let reg = Arc::new(AsyncRegistry::new_with_fetcher(
+            AsyncFetcher::with_transport(
+                FetchPolicy::default(),
+                Box::new(SlowTransport {
+                    inner,
+                    count: count.clone(),
+                }),
+            ),
+        ));
+
+        let mut tasks = Vec::new();
+        for _ in 0..10 {
+            let reg = reg.clone();
+            let did = did.clone();
+            tasks.push(tokio::spawn(async move {
+                AsyncResolver::resolve(reg.as_ref(), &did).await
+            }));
+        }
+
+        for task in tasks {
+            assert_eq!(task.await.unwrap().unwrap().id, did);
+        }
+
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+        assert!(reg.inflight.lock().unwrap().is_empty());
+
+        // cached documents carry no metadata of their own
+        let res = reg.resolution(&did).await.unwrap();
+        assert_eq!(res.document.id, did);
+        assert!(res.metadata.canonical_id.is_none());
+        assert_eq!(reg.canonical_did(&did).await, Some(did.clone()));
+        assert_eq!(count.load(Ordering::SeqCst), 1);
+
+        // failures are shared too, and not remembered
+        let missing = DID::parse("did:web:example.org:u:bob").unwrap();
+        let (a, b) = tokio::join!(reg.resolve(&missing), reg.resolve(&missing));
+        assert!(a.is_err() && b.is_err());
+        assert_eq!(count.load(Ordering::SeqCst), 2);
+        assert!(reg.resolve(&missing).await.is_err());
+        assert_eq!(count.load(Ordering::SeqCst), 3);
+
+        assert!(reg
+            .resolve(&DID::parse("did:key:z6Mk").unwrap())
+            .await
+            .is_err());
+    }
+}
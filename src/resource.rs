@@ -0,0 +1,257 @@
+use crate::{did::DID, document::Document, registry::Version, time::VersionTime, url::URL};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+
+/// The path segment under which the resources of a [DID] are dereferenced, as in
+/// `did:example:123/resources/<id>`.
+pub const RESOURCE_PATH: &str = "resources";
+
+/// ResourceMetadata describes a [Resource] linked to a [DID]. It serializes with camelCase names,
+/// in the manner of DID document metadata.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceMetadata {
+    /// The [URL] the resource is dereferenced by.
+    #[serde(rename = "resourceURI")]
+    pub resource_uri: URL,
+    /// The identifier of the resource, unique for its [DID].
+    pub resource_id: String,
+    /// The media type of the content, e.g. `application/schema+json`.
+    pub media_type: String,
+    /// The SHA-256 checksum of the content, hex-encoded.
+    pub checksum: String,
+    /// The [Version] of this content of the resource, starting from 1 when it is attached and
+    /// counting up each time it is replaced. Resources are versioned apart from documents.
+    pub version: Version,
+    /// When the resource was first attached.
+    pub created: VersionTime,
+    /// When the content of the resource was last replaced, if it has been.
+    #[serde(skip_serializing_if = "Option::is_none", default)]
+    pub updated: Option<VersionTime>,
+}
+
+/// Resource is arbitrary content, such as a credential schema, status list or image, linked to a
+/// [DID] in a [crate::registry::Registry]. Attach one with
+/// [crate::registry::Registry::attach_resource], and dereference it by a [URL] with the path
+/// `/resources/<id>` with [crate::registry::Registry::dereference].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Resource {
+    pub metadata: ResourceMetadata,
+    pub content: Vec<u8>,
+}
+
+impl Resource {
+    pub(crate) fn new(
+        did: &DID,
+        id: &str,
+        media_type: &str,
+        content: Vec<u8>,
+        previous: Option<&Resource>,
+    ) -> Result<Self, anyhow::Error> {
+        let now = VersionTime::now();
+        let (version, created, updated) = match previous {
+            Some(previous) => (
+                Version(previous.metadata.version.0 + 1),
+                previous.metadata.created.clone(),
+                Some(now),
+            ),
+            None => (Version(1), now, None),
+        };
+
+        Ok(Self {
+            metadata: ResourceMetadata {
+                resource_uri: URL::parse(&format!("{}/{}/{}", did, RESOURCE_PATH, id))?,
+                resource_id: id.to_string(),
+                media_type: media_type.to_string(),
+                checksum: checksum(&content),
+                version,
+                created,
+                updated,
+            },
+            content,
+        })
+    }
+
+    /// Check that the content matches the checksum in its metadata.
+    pub fn verify(&self) -> bool {
+        checksum(&self.content) == self.metadata.checksum
+    }
+}
+
+fn checksum(content: &[u8]) -> String {
+    multibase::Base::Base16Lower.encode(Sha256::digest(content))
+}
+
+// the resource named by the path of a DID URL, if it names one
+pub(crate) fn resource_id(path: &[u8]) -> Option<&str> {
+    let path = std::str::from_utf8(path).ok()?;
+    match path.trim_start_matches('/').split_once('/') {
+        Some((RESOURCE_PATH, id)) if !id.is_empty() && !id.contains('/') => Some(id),
+        _ => None,
+    }
+}
+
+/// The result of dereferencing a [URL] with [crate::registry::Registry::dereference]: the
+/// document of its [DID], or a [Resource] linked to it.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Dereferenced {
+    Document(Arc<Document>),
+    Resource(Arc<Resource>),
+}
+
+mod tests {
+    // a registry holding alice's document, and her DID
+    #[cfg(test)]
+    fn fixture() -> (crate::registry::Registry, crate::did::DID) {
+        use crate::{did::DID, document::Document, registry::Registry};
+
+        let did = DID::parse("did:testing:u:alice").unwrap();
+        let mut reg = Registry::default();
+        reg.insert(Document {
+            id: did.clone(),
+            ..Default::default()
+        })
+        .unwrap();
+
+        (reg, did)
+    }
+
+    #[test]
+    fn test_attach() {
+        use super::ResourceMetadata;
+        use crate::{did::DID, registry::Version, url::URL};
+
+        let (mut reg, did) = fixture();
+        let schema = br#"{"type":"object"}"#.to_vec();
+
+        // resources can only be attached to documents in the registry
+        let bob = DID::parse("did:testing:u:bob").unwrap();
+        assert!(reg
+            .attach_resource(&bob, "schema", "application/schema+json", schema.clone())
+            .is_err());
+
+        let metadata = reg
+            .attach_resource(&did, "schema", "application/schema+json", schema.clone())
+            .unwrap();
+        assert_eq!(
+            metadata.resource_uri,
+            URL::parse("did:testing:u:alice/resources/schema").unwrap()
+        );
+        assert!(metadata.updated.is_none());
+        assert_eq!(metadata.version, Version(1));
+        for id in ["", "a/b", "a b", "ü"] {
+            assert!(reg
+                .attach_resource(&did, id, "text/plain", Vec::new())
+                .is_err());
+        }
+        reg.attach_resource(&did, "logo", "image/png", vec![0x89, 0x50])
+            .unwrap();
+
+        let resource = reg.resource(&did, "schema").unwrap();
+        assert_eq!(resource.metadata, metadata);
+        assert_eq!(resource.content, schema);
+        assert!(resource.verify());
+
+        // replacing a resource gives it a new version, and leaves the document's alone
+        let version = reg.version(&did);
+        let replaced = reg
+            .attach_resource(&did, "schema", "application/schema+json", b"{}".to_vec())
+            .unwrap();
+        assert_eq!(replaced.version, Version(2));
+        assert_eq!(reg.version(&did), version);
+        assert_eq!(replaced.created, metadata.created);
+        assert!(replaced.updated.is_some());
+
+        let json = serde_json::to_value(&replaced).unwrap();
+        assert!(json.get("resourceURI").is_some());
+        assert_eq!(
+            serde_json::from_value::<ResourceMetadata>(json).unwrap(),
+            replaced
+        );
+
+        assert_eq!(
+            reg.resources(&did)
+                .map(|r| r.metadata.resource_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["logo", "schema"]
+        );
+        assert!(reg.detach_resource(&did, "logo").is_some());
+        assert!(reg.detach_resource(&did, "logo").is_none());
+
+        // and they go with the document
+        reg.remove(&did);
+        assert!(reg.resource(&did, "schema").is_none());
+    }
+
+    #[test]
+    fn test_dereference() {
+        use super::Dereferenced;
+        use crate::url::URL;
+
+        let (mut reg, did) = fixture();
+        let metadata = reg
+            .attach_resource(&did, "schema", "application/schema+json", b"{}".to_vec())
+            .unwrap();
+
+        match reg.dereference(&metadata.resource_uri).unwrap() {
+            Dereferenced::Resource(resource) => {
+                assert_eq!(resource.metadata, metadata);
+                assert_eq!(resource.content, b"{}");
+            }
+            other => panic!("expected a resource, got {:?}", other),
+        }
+
+        // URLs without a resource path dereference to the document
+        for url in ["did:testing:u:alice", "did:testing:u:alice#key-1"] {
+            assert!(matches!(
+                reg.dereference(&URL::parse(url).unwrap()).unwrap(),
+                Dereferenced::Document(doc) if doc.id == did
+            ));
+        }
+
+        for url in [
+            "did:testing:u:alice/resources/missing",
+            "did:testing:u:alice/other/schema",
+            "did:testing:u:bob/resources/schema",
+        ] {
+            assert!(reg.dereference(&URL::parse(url).unwrap()).is_err());
+        }
+    }
+
+    #[test]
+    fn test_hashlinks() {
+        use crate::{hashlink::HashLink, url::URL};
+
+        let (mut reg, did) = fixture();
+        let schema = br#"{"type":"object"}"#.to_vec();
+        reg.attach_resource(&did, "schema", "application/schema+json", schema.clone())
+            .unwrap();
+
+        // hashlinks pin the content: documents in canonical form, resources by their content
+        let doc = reg.get(&did).unwrap().canonical_json().unwrap();
+        let pinned = |url: &str, hl: &str| URL::parse(&format!("{}?hl={}", url, hl)).unwrap();
+        assert!(reg
+            .dereference(&pinned(
+                "did:testing:u:alice",
+                &HashLink::new(&doc).to_string()
+            ))
+            .is_ok());
+        assert!(reg
+            .dereference(&pinned(
+                "did:testing:u:alice/resources/schema",
+                &HashLink::new(&schema).to_string()
+            ))
+            .is_ok());
+        for (url, hl) in [
+            ("did:testing:u:alice", HashLink::new(&schema).to_string()),
+            (
+                "did:testing:u:alice/resources/schema",
+                HashLink::new(&doc).to_string(),
+            ),
+            ("did:testing:u:alice", "zNotAHashlink".to_string()),
+        ] {
+            assert!(reg.dereference(&pinned(url, &hl)).is_err());
+        }
+    }
+}
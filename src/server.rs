@@ -0,0 +1,881 @@
+use crate::{
+    document::Document,
+    journal::Operation,
+    metadata::DocumentMetadata,
+    method::Retention,
+    registry::{Registry, Resolution},
+    resource::Dereferenced,
+    shared::SharedRegistry,
+    string::url_decoded,
+    time::VersionTime,
+    url::URL,
+};
+use serde_json::json;
+use std::{
+    fmt::Display,
+    io::{BufRead, BufReader, Read, Write},
+    net::{TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// The path under which [ResolutionServer] resolves DIDs and dereferences DID URLs, as in
+/// `/1.0/identifiers/did:example:123`.
+pub const IDENTIFIERS_PATH: &str = "/1.0/identifiers/";
+
+/// The JSON-LD context of DID resolution results.
+pub const RESOLUTION_CONTEXT: &str = "https://w3id.org/did-resolution/v1";
+
+/// The media type of DID documents represented in JSON.
+pub const DID_JSON: &str = "application/did+json";
+/// The media type of DID documents represented in CBOR.
+pub const DID_CBOR: &str = "application/did+cbor";
+/// The media type of DID resolution results.
+pub const DID_RESOLUTION: &str = "application/did-resolution";
+
+/// The number of connections a [ResolutionServer] answers at once, unless it is given another
+/// with [ResolutionServer::set_max_connections].
+pub const DEFAULT_MAX_CONNECTIONS: usize = 64;
+
+// the most the request line and headers of a request may occupy
+const MAX_REQUEST_HEAD: u64 = 16 * 1024;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+// connections beyond the limit are turned away from the accepting thread, so they must not hold
+// it up for long
+const BUSY_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// ResolutionError is the error of a failed resolution or dereferencing, as described in
+/// <https://w3c.github.io/did-resolution/#errors>, each of which [ResolutionServer] answers with
+/// its own HTTP status.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResolutionError {
+    /// The DID or DID URL could not be parsed. `400 Bad Request`.
+    InvalidDid,
+    /// The DID or DID URL does not exist. `404 Not Found`.
+    NotFound,
+    /// The DID has been deactivated. `410 Gone`.
+    Deactivated,
+    /// None of the media types the client accepts can represent the result. `406 Not
+    /// Acceptable`.
+    RepresentationNotSupported,
+    /// The DID method is refused by the policy of the registry. `501 Not Implemented`.
+    MethodNotSupported,
+    /// The result could not be represented. `500 Internal Server Error`.
+    InternalError,
+}
+
+impl ResolutionError {
+    /// The error code, as it appears in the `error` property of resolution metadata.
+    pub fn code(&self) -> &'static str {
+        match self {
+            Self::InvalidDid => "invalidDid",
+            Self::NotFound => "notFound",
+            Self::Deactivated => "deactivated",
+            Self::RepresentationNotSupported => "representationNotSupported",
+            Self::MethodNotSupported => "methodNotSupported",
+            Self::InternalError => "internalError",
+        }
+    }
+
+    /// The HTTP status the error is answered with.
+    pub fn status(&self) -> u16 {
+        match self {
+            Self::InvalidDid => 400,
+            Self::NotFound => 404,
+            Self::Deactivated => 410,
+            Self::RepresentationNotSupported => 406,
+            Self::MethodNotSupported => 501,
+            Self::InternalError => 500,
+        }
+    }
+}
+
+impl Display for ResolutionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl std::error::Error for ResolutionError {}
+
+/// Representation is a form in which [ResolutionServer] answers with a document, chosen by
+/// content negotiation over the `Accept` header of the request.
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Representation {
+    /// The document alone, in JSON: [DID_JSON].
+    Json,
+    /// The document alone, in CBOR: [DID_CBOR].
+    Cbor,
+    /// A full DID resolution result in JSON, carrying the document with its resolution and
+    /// [crate::metadata::DocumentMetadata]: [DID_RESOLUTION]. This is the default, as with the DIF Universal
+    /// Resolver.
+    #[default]
+    Resolution,
+}
+
+impl Representation {
+    /// Choose the [Representation] for the value of an `Accept` header: the one the client
+    /// prefers most, by quality value. Clients which accept anything, or send no header, get the
+    /// default. Returns [None] if no media type the client accepts is supported.
+    pub fn negotiate(accept: Option<&str>) -> Option<Self> {
+        let accept = match accept.map(str::trim) {
+            None | Some("") => return Some(Self::default()),
+            Some(accept) => accept,
+        };
+
+        let mut ranges = Vec::new();
+        for (pos, range) in accept.split(',').enumerate() {
+            let mut params = range.split(';').map(str::trim);
+            let media_type = params.next().unwrap_or_default().to_ascii_lowercase();
+            let mut quality: f32 = 1.0;
+            let mut profile = None;
+
+            for param in params {
+                match param.split_once('=').map(|(k, v)| (k.trim(), v.trim())) {
+                    Some(("q", q)) => quality = q.parse().unwrap_or(0.0),
+                    Some(("profile", p)) => profile = Some(p.trim_matches('"').to_string()),
+                    _ => {}
+                }
+            }
+
+            let representation = match (media_type.as_str(), profile.as_deref()) {
+                (DID_RESOLUTION, _) => Some(Self::Resolution),
+                ("application/ld+json", Some(p))
+                    if p.starts_with("https://w3id.org/did-resolution") =>
+                {
+                    Some(Self::Resolution)
+                }
+                (DID_JSON | "application/did+ld+json" | "application/json", _) => Some(Self::Json),
+                (DID_CBOR | "application/cbor", _) => Some(Self::Cbor),
+                ("*/*" | "application/*", _) => Some(Self::default()),
+                _ => None,
+            };
+
+            if let Some(representation) = representation.filter(|_| quality > 0.0) {
+                ranges.push((quality, pos, representation));
+            }
+        }
+
+        // most preferred first, and in the order given when equally preferred
+        ranges.sort_by(|a, b| b.0.total_cmp(&a.0).then(a.1.cmp(&b.1)));
+        ranges.first().map(|(_, _, representation)| *representation)
+    }
+
+    /// The media type of the representation.
+    pub fn media_type(&self) -> &'static str {
+        match self {
+            Self::Json => DID_JSON,
+            Self::Cbor => DID_CBOR,
+            Self::Resolution => DID_RESOLUTION,
+        }
+    }
+}
+
+/// HttpResponse is the answer of a [ResolutionServer] to a request.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct HttpResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl HttpResponse {
+    fn new(status: u16, content_type: &str, body: Vec<u8>) -> Self {
+        Self {
+            status,
+            headers: vec![("Content-Type".to_string(), content_type.to_string())],
+            body,
+        }
+    }
+
+    /// Retrieve the value of a header, by case-insensitive name.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    fn write_to(&self, w: &mut impl Write) -> Result<(), anyhow::Error> {
+        let mut head = format!("HTTP/1.1 {} {}\r\n", self.status, reason(self.status));
+        for (name, value) in &self.headers {
+            head += &format!("{}: {}\r\n", name, value);
+        }
+        head += &format!(
+            "Content-Length: {}\r\nConnection: close\r\n\r\n",
+            self.body.len()
+        );
+
+        w.write_all(head.as_bytes())?;
+        w.write_all(&self.body)?;
+        Ok(w.flush()?)
+    }
+}
+
+fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        406 => "Not Acceptable",
+        410 => "Gone",
+        501 => "Not Implemented",
+        503 => "Service Unavailable",
+        _ => "Internal Server Error",
+    }
+}
+
+// the resolution of a DID as it was at the provided time: the current one if the DID has not
+// changed since, and otherwise as the journal of the registry describes it, if it keeps one
+fn resolution_at(reg: &Registry, current: Resolution, time: &VersionTime) -> Option<Resolution> {
+    let metadata = &current.metadata;
+    if metadata
+        .updated
+        .as_ref()
+        .or(metadata.created.as_ref())
+        .is_some_and(|changed| changed <= time)
+    {
+        return Some(current);
+    }
+
+    // changes to identifiers leave the document as it was
+    let entry = reg
+        .journal()?
+        .history(&current.document.id)
+        .into_iter()
+        .rfind(|entry| &entry.time <= time && entry.operation != Operation::Identify)?;
+    let metadata = DocumentMetadata {
+        updated: Some(entry.time.clone()),
+        version_id: entry.version.map(|version| version.to_string()),
+        ..Default::default()
+    };
+
+    match entry.operation {
+        Operation::Deactivate => Some(Resolution {
+            document: Arc::new(Document {
+                id: entry.id.clone(),
+                ..Default::default()
+            }),
+            deactivated: true,
+            version: entry.version,
+            metadata: DocumentMetadata {
+                deactivated: Some(true),
+                ..metadata
+            },
+        }),
+        _ => Some(Resolution {
+            document: Arc::new(entry.document.clone()?),
+            deactivated: false,
+            version: entry.version,
+            metadata,
+        }),
+    }
+}
+
+/// ResolutionServer serves a [SharedRegistry] over HTTP, implementing the DID resolution
+/// endpoint of the [DIF Universal Resolver](https://github.com/decentralized-identity/universal-resolver):
+/// `GET /1.0/identifiers/{did}`. Each request is answered from a snapshot of the registry, so
+/// the registry may be written to while it is served.
+///
+/// A [crate::did::DID] is resolved with [Registry::resolve], and the document represented as
+/// negotiated by [Representation::negotiate]. A DID URL with a path or parameters is dereferenced
+/// with [Registry::dereference]: documents are represented as for a [crate::did::DID], and
+/// [crate::resource::Resource]s are answered with their content and media type. A `versionId`
+/// parameter must name the current version of the document. A `versionTime` parameter is
+/// answered with the document as it was at that time: the current document if it has not changed
+/// since, or else the one recorded in the [crate::journal::Journal] of the registry, if it keeps
+/// one. The identifier may be
+/// percent-encoded; the query of the request is taken to be that of the DID URL.
+///
+/// Failures are answered with a resolution result carrying the [ResolutionError], with its HTTP
+/// status. Documents in the registry carry their [crate::registry::Version] as an `ETag`.
+/// The [crate::metadata::DocumentMetadata] of a document is carried by resolution results.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+/// use std::sync::Arc;
+///
+/// let reg = Arc::new(SharedRegistry::default());
+/// let did = DID::parse("did:mymethod:alice").unwrap();
+/// reg.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+///
+/// let server = ResolutionServer::new(reg.clone());
+/// let resp = server.respond("GET", "/1.0/identifiers/did:mymethod:alice", Some(DID_JSON));
+/// assert_eq!(resp.status, 200);
+/// assert_eq!(resp.header("content-type"), Some(DID_JSON));
+/// assert_eq!(serde_json::from_slice::<Document>(&resp.body).unwrap().id, did);
+///
+/// let resp = server.respond("GET", "/1.0/identifiers/did:mymethod:bob", None);
+/// assert_eq!(resp.status, 404);
+///
+/// // to serve it, on a thread of its own:
+/// // std::thread::spawn(move || server.serve(std::net::TcpListener::bind("127.0.0.1:8080")?));
+/// ```
+#[derive(Clone)]
+pub struct ResolutionServer {
+    registry: Arc<SharedRegistry>,
+    max_connections: usize,
+    // the connections being answered, shared by the clones handed to each connection's thread
+    active: Arc<AtomicUsize>,
+}
+
+// a connection's place among those being answered, given up when it is dropped
+struct Slot(Arc<AtomicUsize>);
+
+impl Drop for Slot {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+impl std::fmt::Debug for ResolutionServer {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ResolutionServer")
+            .field("max_connections", &self.max_connections)
+            .finish_non_exhaustive()
+    }
+}
+
+impl ResolutionServer {
+    /// Create a server for the registry.
+    pub fn new(registry: Arc<SharedRegistry>) -> Self {
+        Self {
+            registry,
+            max_connections: DEFAULT_MAX_CONNECTIONS,
+            active: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Set the number of connections answered at once, which is [DEFAULT_MAX_CONNECTIONS] unless
+    /// set. It is at least one.
+    pub fn set_max_connections(&mut self, max_connections: usize) {
+        self.max_connections = max_connections.max(1)
+    }
+
+    /// Accept connections on the listener, answering each on a thread of its own. Only returns
+    /// if the listener fails. Connections are closed after one request. Connections accepted
+    /// while as many as the server answers at once are open are answered with `503 Service
+    /// Unavailable`, without reading their request.
+    pub fn serve(&self, listener: TcpListener) -> Result<(), anyhow::Error> {
+        for stream in listener.incoming() {
+            let stream = stream?;
+            let busy = self.active.fetch_add(1, Ordering::SeqCst) >= self.max_connections;
+            let slot = Slot(self.active.clone());
+            if busy {
+                drop(slot);
+                // the client is turned away either way, so a failure to tell it is no matter
+                let _ = stream
+                    .set_write_timeout(Some(BUSY_TIMEOUT))
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| {
+                        let mut resp =
+                            HttpResponse::new(503, "text/plain", b"Server is busy".to_vec());
+                        resp.headers
+                            .push(("Retry-After".to_string(), "1".to_string()));
+                        resp.write_to(&mut &stream)
+                    });
+                continue;
+            }
+
+            let server = self.clone();
+            std::thread::spawn(move || {
+                let _slot = slot;
+                // the client has gone away, or sent something unreadable: nothing to be done
+                let _ = server.handle(stream);
+            });
+        }
+
+        Ok(())
+    }
+
+    fn handle(&self, stream: TcpStream) -> Result<(), anyhow::Error> {
+        stream.set_read_timeout(Some(READ_TIMEOUT))?;
+        let mut reader = BufReader::new((&stream).take(MAX_REQUEST_HEAD));
+
+        let mut line = String::new();
+        reader.read_line(&mut line)?;
+        let mut parts = line.split_whitespace();
+        let (method, target) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(method), Some(target), Some(version)) if version.starts_with("HTTP/") => {
+                (method.to_string(), target.to_string())
+            }
+            _ => {
+                return HttpResponse::new(400, "text/plain", b"Malformed request".to_vec())
+                    .write_to(&mut &stream)
+            }
+        };
+
+        let mut accept: Option<String> = None;
+        loop {
+            line.clear();
+            if reader.read_line(&mut line)? == 0 || line.trim().is_empty() {
+                break;
+            }
+
+            if let Some((name, value)) = line.split_once(':') {
+                if name.trim().eq_ignore_ascii_case("accept") {
+                    // repeated headers are equivalent to one with their values joined
+                    let value = value.trim();
+                    accept = Some(match accept {
+                        Some(accept) => format!("{}, {}", accept, value),
+                        None => value.to_string(),
+                    });
+                }
+            }
+        }
+
+        self.respond(&method, &target, accept.as_deref())
+            .write_to(&mut &stream)
+    }
+
+    /// Answer a request, given its method, target (the path and query of the request) and
+    /// `Accept` header.
+    pub fn respond(&self, method: &str, target: &str, accept: Option<&str>) -> HttpResponse {
+        let identifier = match target.strip_prefix(IDENTIFIERS_PATH) {
+            Some(identifier) if !identifier.is_empty() => identifier,
+            _ => return HttpResponse::new(404, "text/plain", b"Not Found".to_vec()),
+        };
+
+        if method != "GET" {
+            let mut resp = HttpResponse::new(405, "text/plain", b"Method Not Allowed".to_vec());
+            resp.headers.push(("Allow".to_string(), "GET".to_string()));
+            return resp;
+        }
+
+        let reg = self.registry.snapshot();
+        match Representation::negotiate(accept) {
+            Some(representation) => self
+                .resolve(&reg, identifier, representation)
+                .unwrap_or_else(|resp| resp),
+            None => error_response(ResolutionError::RepresentationNotSupported, None),
+        }
+    }
+
+    fn resolve(
+        &self,
+        reg: &Registry,
+        identifier: &str,
+        representation: Representation,
+    ) -> Result<HttpResponse, HttpResponse> {
+        // the identifier is percent-decoded, but the query of the request is already that of
+        // the DID URL
+        let (identifier, query) = match identifier.split_once('?') {
+            Some((identifier, query)) => (identifier, Some(query)),
+            None => (identifier, None),
+        };
+        let mut identifier = String::from_utf8(url_decoded(identifier.as_bytes()))
+            .map_err(|_| error_response(ResolutionError::InvalidDid, None))?;
+        if let Some(query) = query {
+            identifier = format!("{}?{}", identifier, query);
+        }
+
+        let url = URL::parse(&identifier)
+            .map_err(|_| error_response(ResolutionError::InvalidDid, None))?;
+        let did = url.to_did();
+
+        if reg.method_policy(&did).retention == Retention::Refuse {
+            return Err(error_response(ResolutionError::MethodNotSupported, None));
+        }
+
+        let mut resolution = reg
+            .resolve(&did)
+            .ok_or_else(|| error_response(ResolutionError::NotFound, None))?;
+        let parameters = url.parameters.as_ref();
+        if let Some(time) = parameters.and_then(|p| p.version_time.as_ref()) {
+            resolution = resolution_at(reg, resolution, time)
+                .ok_or_else(|| error_response(ResolutionError::NotFound, None))?;
+        }
+
+        if resolution.deactivated {
+            return Err(error_response(
+                ResolutionError::Deactivated,
+                Some(&resolution),
+            ));
+        }
+
+        if let Some(version_id) = parameters.and_then(|p| p.version_id.as_ref()) {
+            if resolution.metadata.version_id.as_ref() != Some(version_id) {
+                return Err(error_response(ResolutionError::NotFound, None));
+            }
+        }
+
+        if parameters.is_some() {
+            match reg.dereference(&url) {
+                Ok(Dereferenced::Resource(resource)) => {
+                    return Ok(HttpResponse::new(
+                        200,
+                        &resource.metadata.media_type,
+                        resource.content.clone(),
+                    ))
+                }
+                Ok(Dereferenced::Document(_)) => {}
+                Err(_) => return Err(error_response(ResolutionError::NotFound, None)),
+            }
+        }
+
+        let mut resp = represent(&resolution, representation)
+            .map_err(|_| error_response(ResolutionError::InternalError, None))?;
+        if let Some(version) = resolution.version {
+            resp.headers
+                .push(("ETag".to_string(), format!("\"{}\"", version)));
+        }
+
+        Ok(resp)
+    }
+}
+
+fn represent(
+    resolution: &Resolution,
+    representation: Representation,
+) -> Result<HttpResponse, anyhow::Error> {
+    let body = match representation {
+        Representation::Json => serde_json::to_vec(resolution.document.as_ref())?,
+        Representation::Cbor => {
+            let mut body = Vec::new();
+            ciborium::into_writer(resolution.document.as_ref(), &mut body)?;
+            body
+        }
+        Representation::Resolution => serde_json::to_vec(&json!({
+            "@context": RESOLUTION_CONTEXT,
+            "didDocument": resolution.document.as_ref(),
+            "didResolutionMetadata": { "contentType": DID_JSON },
+            "didDocumentMetadata": resolution.metadata,
+        }))?,
+    };
+
+    Ok(HttpResponse::new(200, representation.media_type(), body))
+}
+
+// a resolution result carrying the error, with the document and its metadata if there are any,
+// as for deactivated DIDs
+fn error_response(error: ResolutionError, resolution: Option<&Resolution>) -> HttpResponse {
+    let body = json!({
+        "@context": RESOLUTION_CONTEXT,
+        "didDocument": resolution.map(|res| res.document.as_ref()),
+        "didResolutionMetadata": { "error": error.code() },
+        "didDocumentMetadata": resolution.map(|res| res.metadata.clone()).unwrap_or_default(),
+    });
+
+    HttpResponse::new(
+        error.status(),
+        DID_RESOLUTION,
+        serde_json::to_vec(&body).unwrap_or_default(),
+    )
+}
+
+mod tests {
+    // a journaled shared registry holding alice's document, with a schema resource, and bob's,
+    // which is deactivated, and refusing did:evil
+    #[cfg(test)]
+    fn fixture() -> std::sync::Arc<crate::shared::SharedRegistry> {
+        use crate::{
+            did::DID,
+            document::Document,
+            method::{MethodPolicy, Retention},
+            shared::SharedRegistry,
+        };
+        use std::sync::Arc;
+
+        let did = |s: &str| DID::parse(s).unwrap();
+        let reg = Arc::new(SharedRegistry::default());
+        reg.write(|reg| {
+            reg.enable_journal();
+            for id in ["did:testing:u:alice", "did:testing:u:bob"] {
+                reg.insert(Document {
+                    id: did(id),
+                    ..Default::default()
+                })?;
+            }
+            reg.attach_resource(
+                &did("did:testing:u:alice"),
+                "schema",
+                "application/schema+json",
+                b"{}".to_vec(),
+            )?;
+            reg.deactivate(&did("did:testing:u:bob"))?;
+            reg.set_method_policy("evil", MethodPolicy::new(Retention::Refuse))
+        })
+        .unwrap();
+
+        reg
+    }
+
+    #[test]
+    fn test_negotiate() {
+        use super::{Representation, DID_JSON};
+
+        for (accept, expected) in [
+            (None, Some(Representation::Resolution)),
+            (Some("*/*"), Some(Representation::Resolution)),
+            (Some(DID_JSON), Some(Representation::Json)),
+            (
+                Some("application/did+cbor, application/did+json"),
+                Some(Representation::Cbor),
+            ),
+            (
+                Some("application/did+json;q=0.5, application/did+cbor;q=0.9"),
+                Some(Representation::Cbor),
+            ),
+            (
+                Some(r#"application/ld+json;profile="https://w3id.org/did-resolution""#),
+                Some(Representation::Resolution),
+            ),
+            (Some("text/html, application/did+json;q=0"), None),
+            (Some("text/html"), None),
+        ] {
+            assert_eq!(Representation::negotiate(accept), expected, "{:?}", accept);
+        }
+    }
+
+    #[test]
+    fn test_resolve() {
+        use super::{ResolutionServer, DID_CBOR, DID_JSON, DID_RESOLUTION};
+        use crate::{did::DID, document::Document};
+
+        let did = |s: &str| DID::parse(s).unwrap();
+        let reg = fixture();
+        let version = reg.snapshot().version(&did("did:testing:u:alice")).unwrap();
+
+        let server = ResolutionServer::new(reg.clone());
+        let get = |target: &str, accept: Option<&str>| server.respond("GET", target, accept);
+
+        // documents, in each representation
+        let resp = get("/1.0/identifiers/did:testing:u:alice", Some(DID_JSON));
+        assert_eq!(resp.status, 200);
+        assert_eq!(
+            resp.header("ETag"),
+            Some(format!("\"{}\"", version).as_str())
+        );
+        assert_eq!(
+            serde_json::from_slice::<Document>(&resp.body).unwrap().id,
+            did("did:testing:u:alice")
+        );
+
+        let resp = get("/1.0/identifiers/did%3Atesting%3Au%3Aalice", Some(DID_CBOR));
+        assert_eq!(resp.header("Content-Type"), Some(DID_CBOR));
+        assert_eq!(
+            ciborium::from_reader::<Document, _>(resp.body.as_slice())
+                .unwrap()
+                .id,
+            did("did:testing:u:alice")
+        );
+
+        let resp = get("/1.0/identifiers/did:testing:u:alice", None);
+        assert_eq!(resp.header("Content-Type"), Some(DID_RESOLUTION));
+        let result: serde_json::Value = serde_json::from_slice(&resp.body).unwrap();
+        assert_eq!(result["didDocument"]["id"], "did:testing:u:alice");
+        assert_eq!(result["didResolutionMetadata"]["contentType"], DID_JSON);
+        assert_eq!(
+            result["didDocumentMetadata"]["versionId"],
+            version.to_string()
+        );
+
+        let resp = get(
+            &format!("/1.0/identifiers/did:testing:u:alice?versionId={}", version),
+            Some(DID_JSON),
+        );
+        assert_eq!(resp.status, 200);
+
+        // deactivated documents are gone, but their metadata says why
+        let resp = get("/1.0/identifiers/did:testing:u:bob", None);
+        assert_eq!(resp.status, 410);
+        let result: serde_json::Value = serde_json::from_slice(&resp.body).unwrap();
+        assert_eq!(result["didDocumentMetadata"]["deactivated"], true);
+    }
+
+    #[test]
+    fn test_dereference() {
+        use super::ResolutionServer;
+
+        let server = ResolutionServer::new(fixture());
+        let resp = server.respond(
+            "GET",
+            "/1.0/identifiers/did:testing:u:alice/resources/schema",
+            None,
+        );
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.header("Content-Type"), Some("application/schema+json"));
+        assert_eq!(resp.body, b"{}");
+    }
+
+    #[test]
+    fn test_errors() {
+        use super::ResolutionServer;
+
+        let server = ResolutionServer::new(fixture());
+        let get = |target: &str, accept: Option<&str>| server.respond("GET", target, accept);
+
+        for (target, accept, status, error) in [
+            (
+                "/1.0/identifiers/did:testing:u:carol",
+                None,
+                404,
+                "notFound",
+            ),
+            (
+                "/1.0/identifiers/did:testing:u:alice/resources/missing",
+                None,
+                404,
+                "notFound",
+            ),
+            (
+                "/1.0/identifiers/did:testing:u:alice?versionId=0",
+                None,
+                404,
+                "notFound",
+            ),
+            ("/1.0/identifiers/not-a-did", None, 400, "invalidDid"),
+            (
+                "/1.0/identifiers/did:testing:u:bob",
+                None,
+                410,
+                "deactivated",
+            ),
+            (
+                "/1.0/identifiers/did:evil:mallory",
+                None,
+                501,
+                "methodNotSupported",
+            ),
+            (
+                "/1.0/identifiers/did:testing:u:alice",
+                Some("text/html"),
+                406,
+                "representationNotSupported",
+            ),
+        ] {
+            let resp = get(target, accept);
+            assert_eq!(resp.status, status, "{}", target);
+            let result: serde_json::Value = serde_json::from_slice(&resp.body).unwrap();
+            assert_eq!(result["didResolutionMetadata"]["error"], error);
+        }
+
+        assert_eq!(
+            get("/2.0/identifiers/did:testing:u:alice", None).status,
+            404
+        );
+        assert_eq!(
+            server
+                .respond("POST", "/1.0/identifiers/did:testing:u:alice", None)
+                .status,
+            405
+        );
+    }
+
+    #[test]
+    fn test_version_time() {
+        use super::{ResolutionServer, DID_JSON};
+        use crate::{
+            did::DID,
+            document::{Controller, Document},
+            shared::SharedRegistry,
+            time::VersionTime,
+        };
+        use either::Either;
+        use std::sync::Arc;
+
+        let did = |s: &str| DID::parse(s).unwrap();
+        let get = |server: &ResolutionServer, time: &VersionTime| {
+            server.respond(
+                "GET",
+                &format!("/1.0/identifiers/did:testing:u:alice?versionTime={}", time),
+                Some(DID_JSON),
+            )
+        };
+
+        let reg = fixture();
+        let server = ResolutionServer::new(reg.clone());
+
+        let inserted = VersionTime::now();
+        let updated = Document {
+            controller: Some(Controller(Either::Left(did("did:testing:u:bob")))),
+            ..reg
+                .get(&did("did:testing:u:alice"))
+                .unwrap()
+                .as_ref()
+                .clone()
+        };
+        reg.update(updated.clone()).unwrap();
+
+        // the document as it is now, or as the journal says it was
+        let resp = get(&server, &VersionTime::now());
+        assert_eq!(resp.status, 200);
+        assert_eq!(
+            serde_json::from_slice::<Document>(&resp.body).unwrap(),
+            updated
+        );
+
+        let resp = get(&server, &inserted);
+        assert_eq!(resp.status, 200);
+        let document: Document = serde_json::from_slice(&resp.body).unwrap();
+        assert_eq!(document.id, did("did:testing:u:alice"));
+        assert!(document.controller.is_none());
+
+        // and not at all before it was created
+        let resp = get(
+            &server,
+            &VersionTime::parse("2000-01-01T00:00:00Z").unwrap(),
+        );
+        assert_eq!(resp.status, 404);
+
+        // without a journal, only the current document can be answered for
+        let plain = Arc::new(SharedRegistry::default());
+        plain
+            .insert(Document {
+                id: did("did:testing:u:alice"),
+                ..Default::default()
+            })
+            .unwrap();
+        let inserted = VersionTime::now();
+        plain.update(updated).unwrap();
+        let server = ResolutionServer::new(plain);
+        assert_eq!(get(&server, &inserted).status, 404);
+        assert_eq!(get(&server, &VersionTime::now()).status, 200);
+    }
+
+    #[test]
+    fn test_serve() {
+        use super::ResolutionServer;
+        use crate::{did::DID, document::Document};
+        use std::{
+            io::{Read, Write},
+            net::{TcpListener, TcpStream},
+        };
+
+        let did = DID::parse("did:testing:u:alice").unwrap();
+
+        // one connection at a time, turning away the rest
+        let mut server = ResolutionServer::new(fixture());
+        server.set_max_connections(1);
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        std::thread::spawn(move || server.serve(listener));
+
+        let mut stream = TcpStream::connect(addr).unwrap();
+        let mut busy = TcpStream::connect(addr).unwrap();
+        let mut resp = String::new();
+        busy.read_to_string(&mut resp).unwrap();
+        assert!(
+            resp.starts_with("HTTP/1.1 503 Service Unavailable\r\n"),
+            "{}",
+            resp
+        );
+        assert!(resp.contains("Retry-After: 1\r\n"));
+
+        stream
+            .write_all(
+                b"GET /1.0/identifiers/did:testing:u:alice HTTP/1.1\r\nHost: localhost\r\nAccept: application/did+json\r\n\r\n",
+            )
+            .unwrap();
+        let mut resp = String::new();
+        stream.read_to_string(&mut resp).unwrap();
+        assert!(resp.starts_with("HTTP/1.1 200 OK\r\n"), "{}", resp);
+        assert!(resp.contains("Content-Type: application/did+json\r\n"));
+        let (_, body) = resp.split_once("\r\n\r\n").unwrap();
+        assert_eq!(serde_json::from_str::<Document>(body).unwrap().id, did);
+    }
+}
@@ -0,0 +1,217 @@
+use crate::{
+    did::DID,
+    document::Document,
+    registry::{Registry, Replaced, Version},
+};
+use std::sync::{Arc, Mutex, PoisonError, RwLock};
+
+/// SharedRegistry is a [Registry] which may be shared between threads without wrapping it in a
+/// [Mutex]. Readers take immutable snapshots of the registry with [SharedRegistry::snapshot],
+/// which stay consistent for as long as they are held, and lookups return [`Arc<Document>`]
+/// rather than copies of the document.
+///
+/// Writes are copy-on-write: [SharedRegistry::write] applies its changes to a copy of the
+/// current registry and then publishes it, so readers are never blocked by a write in progress
+/// and never observe a partial one. Only the registry's structure is copied; the documents
+/// themselves are shared between versions. Writers are serialized, and each write pays for one
+/// copy, so batch changes into a single [SharedRegistry::write] where possible.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+/// use std::sync::Arc;
+///
+/// let reg = Arc::new(SharedRegistry::default());
+/// let did = DID::parse("did:mymethod:alice").unwrap();
+///
+/// let before = reg.snapshot();
+/// reg.insert(Document{ id: did.clone(), ..Default::default() }).unwrap();
+///
+/// let handle = std::thread::spawn({
+///     let reg = reg.clone();
+///     let did = did.clone();
+///     move || reg.get(&did).map(|doc| doc.id.clone())
+/// });
+///
+/// assert_eq!(handle.join().unwrap(), Some(did.clone()));
+/// assert!(before.get(&did).is_none());
+/// ```
+#[derive(Default)]
+pub struct SharedRegistry {
+    current: RwLock<Arc<Registry>>,
+    writer: Mutex<()>,
+}
+
+impl From<Registry> for SharedRegistry {
+    fn from(registry: Registry) -> Self {
+        Self {
+            current: RwLock::new(Arc::new(registry)),
+            writer: Mutex::new(()),
+        }
+    }
+}
+
+impl SharedRegistry {
+    /// Take a snapshot of the registry as it is now. Later writes are not visible through it.
+    pub fn snapshot(&self) -> Arc<Registry> {
+        self.current
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    /// Retrieve a document by [DID] from the current registry. See [Registry::get_arc].
+    pub fn get(&self, did: &DID) -> Option<Arc<Document>> {
+        self.snapshot().get_arc(did)
+    }
+
+    /// Apply changes to the registry. They are published together once the closure returns
+    /// successfully; if it fails, none of them are. [crate::events::Subscriber]s are notified
+    /// after the changes are published, while other writers wait, so they must not write to this
+    /// registry themselves.
+    pub fn write<T>(
+        &self,
+        f: impl FnOnce(&mut Registry) -> Result<T, anyhow::Error>,
+    ) -> Result<T, anyhow::Error> {
+        let _guard = self.writer.lock().unwrap_or_else(PoisonError::into_inner);
+
+        let mut next = self.snapshot().staged();
+        let res = f(&mut next)?;
+
+        let events = next.take_events();
+        let next = Arc::new(next);
+        *self.current.write().unwrap_or_else(PoisonError::into_inner) = next.clone();
+        next.notify(&events);
+        Ok(res)
+    }
+
+    /// See [Registry::insert].
+    pub fn insert(&self, doc: Document) -> Result<(), anyhow::Error> {
+        self.write(|reg| reg.insert(doc))
+    }
+
+    /// See [Registry::update].
+    pub fn update(&self, doc: Document) -> Result<Document, anyhow::Error> {
+        self.write(|reg| reg.update(doc))
+    }
+
+    /// See [Registry::replace]. The check and the write happen atomically with respect to other
+    /// writers.
+    pub fn replace(&self, doc: Document, expected: Version) -> Result<Replaced, anyhow::Error> {
+        self.write(|reg| reg.replace(doc, expected))
+    }
+
+    /// See [Registry::upsert].
+    pub fn upsert(&self, doc: Document) -> Result<Replaced, anyhow::Error> {
+        self.write(|reg| reg.upsert(doc))
+    }
+
+    /// See [Registry::remove].
+    pub fn remove(&self, did: &DID) -> Option<Document> {
+        self.write(|reg| Ok(reg.remove(did))).ok().flatten()
+    }
+
+    /// See [Registry::deactivate].
+    pub fn deactivate(&self, did: &DID) -> Result<Document, anyhow::Error> {
+        self.write(|reg| reg.deactivate(did))
+    }
+
+    /// Unwrap the current [Registry]. It is copied if snapshots of it are still held.
+    pub fn into_inner(self) -> Registry {
+        Arc::unwrap_or_clone(
+            self.current
+                .into_inner()
+                .unwrap_or_else(PoisonError::into_inner),
+        )
+    }
+}
+
+mod tests {
+    #[test]
+    fn test_shared_registry() {
+        use super::SharedRegistry;
+        use crate::{
+            did::DID,
+            document::Document,
+            registry::{Registry, Replaced, Version},
+        };
+        use std::sync::Arc;
+
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Registry>();
+        assert_send_sync::<SharedRegistry>();
+
+        let reg = Arc::new(SharedRegistry::default());
+        let did = |i: usize| DID::parse(&format!("did:testing:u:{}", i)).unwrap();
+
+        let threads: Vec<_> = (0..4)
+            .map(|t| {
+                let reg = reg.clone();
+                std::thread::spawn(move || {
+                    for i in 0..25 {
+                        reg.insert(Document {
+                            id: did(t * 25 + i),
+                            ..Default::default()
+                        })
+                        .unwrap();
+                        let snapshot = reg.snapshot();
+                        assert!(snapshot.get_arc(&did(t * 25 + i)).is_some());
+                    }
+                })
+            })
+            .collect();
+
+        for thread in threads {
+            thread.join().unwrap();
+        }
+
+        let snapshot = reg.snapshot();
+        assert_eq!(snapshot.len(), 100);
+
+        // a failed write publishes nothing
+        assert!(reg
+            .write(|reg| {
+                reg.remove(&did(0));
+                reg.insert(Document {
+                    id: did(1),
+                    ..Default::default()
+                })
+            })
+            .is_err());
+        assert!(reg.get(&did(0)).is_some());
+
+        assert!(reg.remove(&did(0)).is_some());
+        assert!(reg.get(&did(0)).is_none());
+        assert!(snapshot.get_arc(&did(0)).is_some());
+        assert!(Arc::ptr_eq(
+            &snapshot.get_arc(&did(1)).unwrap(),
+            &reg.get(&did(1)).unwrap()
+        ));
+
+        // of two writers racing from the same version, only one wins
+        let version: Version = reg.snapshot().version(&did(1)).unwrap();
+        let results: Vec<Result<Replaced, anyhow::Error>> = (0..2)
+            .map(|t| {
+                let reg = reg.clone();
+                std::thread::spawn(move || {
+                    reg.replace(
+                        Document {
+                            id: did(1),
+                            also_known_as: Some(Default::default()),
+                            controller: (t == 1).then(Default::default),
+                            ..Default::default()
+                        },
+                        version,
+                    )
+                })
+            })
+            .collect::<Vec<_>>()
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+
+        assert_eq!(results.iter().filter(|res| res.is_ok()).count(), 1);
+        let replaced = results.into_iter().find_map(|res| res.ok()).unwrap();
+        assert!(replaced.version > version);
+        assert_eq!(reg.snapshot().version(&did(1)), Some(replaced.version));
+    }
+}
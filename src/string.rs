@@ -85,7 +85,7 @@ pub(crate) fn url_decoded(s: &[u8]) -> Vec<u8> {
 #[inline]
 pub(crate) fn validate_method_name(s: &[u8]) -> Result<(), anyhow::Error> {
     for idx in s {
-        if !(&0x61..=&0x7a).contains(&idx) && !('0'..='9').contains(&(*idx as char)) {
+        if !(&0x61..=&0x7a).contains(&idx) && !(*idx as char).is_ascii_digit() {
             return Err(anyhow!(
                 "Method name has invalid characters (not in 0x61 - 0x7a)"
             ));
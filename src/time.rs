@@ -1,16 +1,36 @@
 use anyhow::anyhow;
 use serde::{de::Visitor, Deserialize, Serialize};
-use std::fmt::Display;
-use time::{
-    format_description::FormatItem, macros::format_description, OffsetDateTime, PrimitiveDateTime,
+use std::{
+    fmt::Display,
+    ops::{Add, AddAssign, Sub, SubAssign},
+    time::SystemTime,
 };
+use time::{Date, Duration, Month, OffsetDateTime, PrimitiveDateTime, Time, UtcOffset};
 
-static VERSION_TIME_FORMAT: &[FormatItem<'static>] =
-    format_description!("[year]-[month]-[day]T[hour]:[minute]:[second]Z");
-
-/// VersionTime is a specific section of the query string in DID [crate::url::URL]s. It is required to be a
-/// certain format, based in UTC. See <https://www.w3.org/TR/did-core/#did-parameters> for more
-/// information. Formatting is provided by the [time] crate.
+/// VersionTime is a specific section of the query string in DID [crate::url::URL]s, and the
+/// timestamp of DID document metadata. See <https://www.w3.org/TR/did-core/#did-parameters> for
+/// more information. Formatting is provided by the [time] crate.
+///
+/// Parsing is lenient, accepting any XML Schema `dateTime`: fractional seconds, offsets from UTC,
+/// or no offset at all, which is taken to be UTC. Times are normalized to UTC, and displayed in
+/// a canonical form: `YYYY-MM-DDTHH:MM:SSZ`, with a fraction of a second only if there is one,
+/// without trailing zeros.
+///
+/// [Duration]s, from the [time] crate or [std::time], may be added to and subtracted from a
+/// [VersionTime]. As with [OffsetDateTime], this panics if the result is out of range, which
+/// [VersionTime::checked_add] and [VersionTime::checked_sub] report as [None] instead.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+///
+/// let t = VersionTime::parse("2023-01-01T02:00:00.120+02:00").unwrap();
+/// assert_eq!(t.to_string(), "2023-01-01T00:00:00.12Z");
+/// assert_eq!(t, VersionTime::parse("2023-01-01T00:00:00.12Z").unwrap());
+///
+/// let later = t.clone() + std::time::Duration::from_millis(880);
+/// assert_eq!(later.to_string(), "2023-01-01T00:00:01Z");
+/// assert_eq!((later - t).whole_milliseconds(), 880);
+/// ```
 #[derive(Clone, Debug, Hash, PartialOrd, Ord, Eq, PartialEq)]
 pub struct VersionTime(pub OffsetDateTime);
 
@@ -22,15 +42,26 @@ impl Default for VersionTime {
 
 impl Display for VersionTime {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let t = self.0.to_offset(UtcOffset::UTC);
+        let year = t.year();
+
         f.write_str(&format!(
-            "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
-            self.0.year(),
-            u8::from(self.0.month()),
-            self.0.day(),
-            self.0.hour(),
-            self.0.minute(),
-            self.0.second()
-        ))
+            "{}{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            if year < 0 { "-" } else { "" },
+            year.abs(),
+            u8::from(t.month()),
+            t.day(),
+            t.hour(),
+            t.minute(),
+            t.second()
+        ))?;
+
+        if t.nanosecond() != 0 {
+            let fraction = format!("{:09}", t.nanosecond());
+            f.write_str(&format!(".{}", fraction.trim_end_matches('0')))?;
+        }
+
+        f.write_str("Z")
     }
 }
 
@@ -47,7 +78,7 @@ impl Visitor<'_> for VersionTime {
     type Value = VersionTime;
 
     fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("Expecting a datetime in DID specification format")
+        formatter.write_str("Expecting a datetime in XML Schema dateTime format")
     }
 
     fn visit_str<E>(self, v: &str) -> Result<Self::Value, E>
@@ -70,12 +101,405 @@ impl<'de> Deserialize<'de> for VersionTime {
     }
 }
 
+impl From<OffsetDateTime> for VersionTime {
+    fn from(value: OffsetDateTime) -> Self {
+        VersionTime(value.to_offset(UtcOffset::UTC))
+    }
+}
+
+impl From<VersionTime> for OffsetDateTime {
+    fn from(value: VersionTime) -> Self {
+        value.0
+    }
+}
+
+impl From<SystemTime> for VersionTime {
+    fn from(value: SystemTime) -> Self {
+        VersionTime(value.into())
+    }
+}
+
+impl From<VersionTime> for SystemTime {
+    fn from(value: VersionTime) -> Self {
+        value.0.into()
+    }
+}
+
+impl Add<Duration> for VersionTime {
+    type Output = Self;
+
+    fn add(self, rhs: Duration) -> Self::Output {
+        VersionTime(self.0 + rhs)
+    }
+}
+
+impl Add<std::time::Duration> for VersionTime {
+    type Output = Self;
+
+    fn add(self, rhs: std::time::Duration) -> Self::Output {
+        VersionTime(self.0 + rhs)
+    }
+}
+
+impl AddAssign<Duration> for VersionTime {
+    fn add_assign(&mut self, rhs: Duration) {
+        self.0 += rhs
+    }
+}
+
+impl AddAssign<std::time::Duration> for VersionTime {
+    fn add_assign(&mut self, rhs: std::time::Duration) {
+        self.0 += rhs
+    }
+}
+
+impl Sub<Duration> for VersionTime {
+    type Output = Self;
+
+    fn sub(self, rhs: Duration) -> Self::Output {
+        VersionTime(self.0 - rhs)
+    }
+}
+
+impl Sub<std::time::Duration> for VersionTime {
+    type Output = Self;
+
+    fn sub(self, rhs: std::time::Duration) -> Self::Output {
+        VersionTime(self.0 - rhs)
+    }
+}
+
+impl SubAssign<Duration> for VersionTime {
+    fn sub_assign(&mut self, rhs: Duration) {
+        self.0 -= rhs
+    }
+}
+
+impl SubAssign<std::time::Duration> for VersionTime {
+    fn sub_assign(&mut self, rhs: std::time::Duration) {
+        self.0 -= rhs
+    }
+}
+
+impl Sub for VersionTime {
+    type Output = Duration;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        self.0 - rhs.0
+    }
+}
+
 impl VersionTime {
-    /// Parse a [VersionTime] from string.
+    /// The current time, in UTC.
+    pub fn now() -> Self {
+        VersionTime(OffsetDateTime::now_utc())
+    }
+
+    /// The time with its fraction of a second discarded, for comparison with times given to
+    /// whole seconds, as they usually are in a [crate::url::URL].
+    pub fn truncated(&self) -> Self {
+        VersionTime(
+            self.0
+                .replace_nanosecond(0)
+                .expect("zero is a valid nanosecond"),
+        )
+    }
+
+    /// Add a [Duration], or return [None] if the result is out of range.
+    pub fn checked_add(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_add(duration).map(VersionTime)
+    }
+
+    /// Subtract a [Duration], or return [None] if the result is out of range.
+    pub fn checked_sub(&self, duration: Duration) -> Option<Self> {
+        self.0.checked_sub(duration).map(VersionTime)
+    }
+
+    /// Parse a [VersionTime] from an XML Schema `dateTime` string, such as
+    /// `2023-01-01T00:00:00Z`, `2023-01-01T00:00:00.123Z` or `2023-01-01T02:00:00+02:00`. A time
+    /// without an offset is taken to be in UTC, and `24:00:00` is the start of the following day.
     pub fn parse(s: &str) -> Result<Self, anyhow::Error> {
-        match PrimitiveDateTime::parse(s, VERSION_TIME_FORMAT) {
-            Ok(dt) => Ok(VersionTime(dt.assume_utc())),
-            Err(e) => Err(anyhow!(e)),
+        parse_date_time(s).map_err(|e| anyhow!("Invalid dateTime {:?}: {}", s, e))
+    }
+}
+
+// a cursor over the bytes of a dateTime
+struct Scanner<'a> {
+    input: &'a [u8],
+    pos: usize,
+}
+
+impl Scanner<'_> {
+    fn peek(&self) -> Option<u8> {
+        self.input.get(self.pos).copied()
+    }
+
+    fn expect(&mut self, c: u8) -> Result<(), anyhow::Error> {
+        match self.peek() {
+            Some(x) if x.eq_ignore_ascii_case(&c) => {
+                self.pos += 1;
+                Ok(())
+            }
+            _ => Err(anyhow!("expected {:?} at {}", c as char, self.pos)),
+        }
+    }
+
+    // a run of at least min digits, returned with the number of digits read
+    fn digits(&mut self, min: usize, max: usize) -> Result<(u64, usize), anyhow::Error> {
+        let start = self.pos;
+        let mut value: u64 = 0;
+
+        while let Some(c @ b'0'..=b'9') = self.peek() {
+            if self.pos - start == max {
+                return Err(anyhow!("too many digits at {}", start));
+            }
+
+            value = value.saturating_mul(10).saturating_add((c - b'0') as u64);
+            self.pos += 1;
+        }
+
+        let len = self.pos - start;
+        if len < min {
+            return Err(anyhow!("expected {} digits at {}", min, start));
+        }
+
+        Ok((value, len))
+    }
+
+    fn two_digits(&mut self) -> Result<u8, anyhow::Error> {
+        Ok(self.digits(2, 2)?.0 as u8)
+    }
+}
+
+fn parse_date_time(s: &str) -> Result<VersionTime, anyhow::Error> {
+    let mut scanner = Scanner {
+        input: s.as_bytes(),
+        pos: 0,
+    };
+
+    let negative = scanner.peek() == Some(b'-');
+    if negative {
+        scanner.pos += 1;
+    }
+
+    let (year, len) = scanner.digits(4, 6)?;
+    if len > 4 && s.as_bytes()[negative as usize] == b'0' {
+        return Err(anyhow!(
+            "years of more than four digits may not begin with 0"
+        ));
+    }
+    let year = if negative {
+        -(year as i32)
+    } else {
+        year as i32
+    };
+
+    scanner.expect(b'-')?;
+    let month = Month::try_from(scanner.two_digits()?)?;
+    scanner.expect(b'-')?;
+    let day = scanner.two_digits()?;
+    scanner.expect(b'T')?;
+    let hour = scanner.two_digits()?;
+    scanner.expect(b':')?;
+    let minute = scanner.two_digits()?;
+    scanner.expect(b':')?;
+    let second = scanner.two_digits()?;
+
+    let mut nanosecond = 0;
+    if scanner.peek() == Some(b'.') {
+        scanner.pos += 1;
+        let start = scanner.pos;
+        scanner.digits(1, usize::MAX)?;
+
+        // precision beyond nanoseconds is discarded
+        let fraction = &s[start..scanner.pos];
+        let fraction = &fraction[..fraction.len().min(9)];
+        nanosecond = fraction.parse::<u32>()? * 10_u32.pow(9 - fraction.len() as u32);
+    }
+
+    let offset = match scanner.peek() {
+        None => UtcOffset::UTC,
+        Some(b'Z' | b'z') => {
+            scanner.pos += 1;
+            UtcOffset::UTC
+        }
+        Some(sign @ (b'+' | b'-')) => {
+            scanner.pos += 1;
+            let hours = scanner.two_digits()? as i8;
+            scanner.expect(b':')?;
+            let minutes = scanner.two_digits()? as i8;
+            if hours > 14 || minutes > 59 || (hours == 14 && minutes != 0) {
+                return Err(anyhow!("offset is out of range"));
+            }
+
+            if sign == b'-' {
+                UtcOffset::from_hms(-hours, -minutes, 0)?
+            } else {
+                UtcOffset::from_hms(hours, minutes, 0)?
+            }
         }
+        Some(_) => {
+            return Err(anyhow!(
+                "unexpected {:?} at {}",
+                s[scanner.pos..].chars().next().unwrap_or_default(),
+                scanner.pos
+            ))
+        }
+    };
+
+    if scanner.pos != s.len() {
+        return Err(anyhow!("trailing characters at {}", scanner.pos));
+    }
+
+    let date = Date::from_calendar_date(year, month, day)?;
+
+    // 24:00:00 is the end of the day, which is to say the start of the next
+    let dt = if hour == 24 {
+        if minute != 0 || second != 0 || nanosecond != 0 {
+            return Err(anyhow!("24:00:00 is the only time in hour 24"));
+        }
+
+        PrimitiveDateTime::new(date, Time::MIDNIGHT)
+            .checked_add(Duration::DAY)
+            .ok_or(anyhow!("date is out of range"))?
+    } else {
+        PrimitiveDateTime::new(date, Time::from_hms_nano(hour, minute, second, nanosecond)?)
+    };
+
+    Ok(VersionTime::from(dt.assume_offset(offset)))
+}
+
+mod tests {
+    #[test]
+    fn test_parse() {
+        use super::VersionTime;
+
+        for (input, canonical) in [
+            ("1978-04-06T06:00:00Z", "1978-04-06T06:00:00Z"),
+            ("2023-01-01T00:00:00.123Z", "2023-01-01T00:00:00.123Z"),
+            ("2023-01-01T00:00:00.500000Z", "2023-01-01T00:00:00.5Z"),
+            ("2023-01-01T00:00:00.000Z", "2023-01-01T00:00:00Z"),
+            (
+                "2023-01-01T00:00:00.1234567891234Z",
+                "2023-01-01T00:00:00.123456789Z",
+            ),
+            ("2023-01-01T02:30:00+02:30", "2023-01-01T00:00:00Z"),
+            ("2022-12-31T23:00:00-01:00", "2023-01-01T00:00:00Z"),
+            ("2023-01-01T00:00:00+00:00", "2023-01-01T00:00:00Z"),
+            ("2023-01-01T00:00:00", "2023-01-01T00:00:00Z"),
+            ("2023-01-01t00:00:00z", "2023-01-01T00:00:00Z"),
+            ("2022-12-31T24:00:00Z", "2023-01-01T00:00:00Z"),
+            ("0001-01-01T00:00:00Z", "0001-01-01T00:00:00Z"),
+        ] {
+            let t = VersionTime::parse(input).unwrap();
+            assert_eq!(t.to_string(), canonical, "{}", input);
+            assert_eq!(VersionTime::parse(canonical).unwrap(), t);
+        }
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        use super::VersionTime;
+
+        for bad in [
+            "",
+            "foo",
+            "2023-01-01",
+            "2023-1-01T00:00:00Z",
+            "2023-01-01 00:00:00Z",
+            "2023-01-01T00:00Z",
+            "2023-13-01T00:00:00Z",
+            "2023-02-29T00:00:00Z",
+            "2023-01-01T25:00:00Z",
+            "2023-01-01T24:00:01Z",
+            "2023-01-01T00:60:00Z",
+            "2023-01-01T00:00:00.Z",
+            "2023-01-01T00:00:00+0200",
+            "2023-01-01T00:00:00+15:00",
+            "2023-01-01T00:00:00ZZ",
+            "02023-01-01T00:00:00Z",
+        ] {
+            assert!(VersionTime::parse(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn test_ordering() {
+        use super::VersionTime;
+
+        // ordering is by instant, whatever the offset it was given in
+        let mut times = [
+            "2023-01-01T00:00:01Z",
+            "2023-01-01T01:00:00.5+01:00",
+            "2022-12-31T23:59:59-00:00",
+        ]
+        .map(|s| VersionTime::parse(s).unwrap());
+        times.sort();
+        assert_eq!(
+            times.map(|t| t.to_string()),
+            [
+                "2022-12-31T23:59:59Z",
+                "2023-01-01T00:00:00.5Z",
+                "2023-01-01T00:00:01Z"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        use super::VersionTime;
+        use std::time::Duration;
+
+        let t = VersionTime::parse("2023-01-01T00:00:00Z").unwrap();
+        let mut later = t.clone() + Duration::from_millis(1500);
+        assert_eq!(later.to_string(), "2023-01-01T00:00:01.5Z");
+        assert_eq!(later.truncated().to_string(), "2023-01-01T00:00:01Z");
+        assert_eq!(
+            later.clone() - t.clone(),
+            time::Duration::milliseconds(1500)
+        );
+        later -= time::Duration::hours(1);
+        assert_eq!(later.to_string(), "2022-12-31T23:00:01.5Z");
+        assert!(t.checked_add(time::Duration::MAX).is_none());
+        assert!(t.checked_sub(time::Duration::MAX).is_none());
+        assert!(std::panic::catch_unwind(|| t.clone() + time::Duration::MAX).is_err());
+    }
+
+    #[test]
+    fn test_conversions() {
+        use super::VersionTime;
+        use std::time::SystemTime;
+        use time::OffsetDateTime;
+
+        let now = SystemTime::now();
+        let t = VersionTime::from(now);
+        assert_eq!(SystemTime::from(t.clone()), now);
+        assert_eq!(
+            VersionTime::from(SystemTime::UNIX_EPOCH),
+            VersionTime::default()
+        );
+        let offset = OffsetDateTime::from(t.clone()).to_offset(time::macros::offset!(+5));
+        assert_eq!(VersionTime::from(offset).to_string(), t.to_string());
+        let now = VersionTime::now();
+        assert!(now.truncated() <= now);
+        assert_eq!(now.truncated().0.nanosecond(), 0);
+    }
+
+    #[test]
+    fn test_serde() {
+        use super::VersionTime;
+
+        // through the canonical form
+        let t = VersionTime::parse("2023-01-01T02:00:00.25+02:00").unwrap();
+        let json = serde_json::to_string(&t).unwrap();
+        assert_eq!(json, r#""2023-01-01T00:00:00.25Z""#);
+        assert_eq!(serde_json::from_str::<VersionTime>(&json).unwrap(), t);
+
+        let mut cbor = Vec::new();
+        ciborium::into_writer(&t, &mut cbor).unwrap();
+        assert_eq!(
+            ciborium::from_reader::<VersionTime, _>(cbor.as_slice()).unwrap(),
+            t
+        );
     }
 }
@@ -0,0 +1,26 @@
+use crate::{did::DID, registry::Version, time::VersionTime};
+use serde::{Deserialize, Serialize};
+
+/// Tombstone records that a [DID] has been deactivated, as described by the `deactivated`
+/// metadata property in <https://www.w3.org/TR/did-core/#did-document-metadata>. Tombstones are
+/// kept by the [crate::registry::Registry] in place of the document; see
+/// [crate::registry::Registry::deactivate].
+#[derive(Clone, Debug, Hash, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Tombstone {
+    pub id: DID,
+    /// The version given to the deactivation.
+    pub version: Version,
+    /// When the [DID] was deactivated.
+    pub deactivated: VersionTime,
+}
+
+/// Resurrection determines whether a deactivated [DID] may be used again. See
+/// [crate::registry::Registry::set_resurrection].
+#[derive(Clone, Copy, Debug, Default, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Resurrection {
+    /// Inserting a document for a deactivated [DID] fails.
+    #[default]
+    Refuse,
+    /// Inserting a document for a deactivated [DID] removes its tombstone.
+    Allow,
+}
@@ -0,0 +1,235 @@
+use crate::{did::DID, document::Document, registry::Registry};
+use anyhow::anyhow;
+use std::collections::BTreeSet;
+
+/// Transaction stages a batch of inserts, updates and removals against a [Registry], and applies
+/// them all at once or not at all. Create one with [Registry::transaction], or use
+/// [Registry::transact] to run a closure as a transaction.
+///
+/// Changes are staged against a copy of the registry, so each one sees the effect of those staged
+/// before it, and lookups through [Transaction::get] see the staged state. Staging a change which
+/// cannot be applied (such as inserting a [DID] which already exists) fails immediately, and
+/// leaves the transaction as it was.
+///
+/// [Transaction::commit] validates the batch before applying it: every staged document, and every
+/// document which refers to a verification method of a staged or removed one, is checked with
+/// [Document::valid] against the staged state. If any are invalid, nothing is applied. Dropping a
+/// transaction without committing it discards it.
+///
+/// ```
+/// use did_toolkit::prelude::*;
+/// use either::Either;
+///
+/// let alice = DID::parse("did:mymethod:alice").unwrap();
+/// let bob = DID::parse("did:mymethod:bob").unwrap();
+/// let key = URL::parse("did:mymethod:bob#key-1").unwrap();
+///
+/// let mut reg = Registry::default();
+///
+/// // alice's authentication key lives in bob's document, which is staged in the same batch
+/// reg.transact(|tx| {
+///   tx.insert(Document{
+///     id: alice.clone(),
+///     authentication: Some(VerificationMethods([VerificationMethodEither(Either::Right(key.clone()))].into())),
+///     ..Default::default()
+///   })?;
+///   tx.insert(Document{
+///     id: bob.clone(),
+///     verification_method: Some([VerificationMethod{
+///       id: key.clone(),
+///       controller: bob.clone(),
+///       ..Default::default()
+///     }].into()),
+///     ..Default::default()
+///   })
+/// }).unwrap();
+///
+/// assert_eq!(reg.len(), 2);
+///
+/// // removing bob would leave alice's reference dangling, so nothing is removed
+/// let mut tx = reg.transaction();
+/// tx.remove(&bob).unwrap();
+/// assert!(tx.commit().is_err());
+/// assert_eq!(reg.len(), 2);
+/// ```
+pub struct Transaction<'a> {
+    registry: &'a mut Registry,
+    staged: Registry,
+    touched: BTreeSet<DID>,
+}
+
+impl<'a> Transaction<'a> {
+    pub(crate) fn new(registry: &'a mut Registry) -> Self {
+        Self {
+            staged: registry.staged(),
+            registry,
+            touched: BTreeSet::new(),
+        }
+    }
+
+    /// Stage the insertion of a document. See [Registry::insert].
+    pub fn insert(&mut self, doc: Document) -> Result<(), anyhow::Error> {
+        let did = doc.id.clone();
+        self.staged.insert(doc)?;
+        self.touched.insert(did);
+        Ok(())
+    }
+
+    /// Stage the replacement of a document. Returns the document as it was staged before. See
+    /// [Registry::update].
+    pub fn update(&mut self, doc: Document) -> Result<Document, anyhow::Error> {
+        let did = doc.id.clone();
+        let old = self.staged.update(doc)?;
+        self.touched.insert(did);
+        Ok(old)
+    }
+
+    /// Stage the removal of a document. Fails if the document does not exist in the staged state.
+    pub fn remove(&mut self, did: &DID) -> Result<Document, anyhow::Error> {
+        match self.staged.try_remove(did)? {
+            Some(doc) => {
+                self.touched.insert(did.clone());
+                Ok(doc)
+            }
+            None => Err(anyhow!("DID {} does not exist in registry", did)),
+        }
+    }
+
+    /// Retrieve a document by [DID] from the staged state.
+    pub fn get(&self, did: &DID) -> Option<Document> {
+        self.staged.get(did)
+    }
+
+    /// The registry as it will be once the transaction is committed.
+    pub fn staged(&self) -> &Registry {
+        &self.staged
+    }
+
+    /// The [DID]s of all documents inserted, updated or removed by the transaction.
+    pub fn touched(&self) -> &BTreeSet<DID> {
+        &self.touched
+    }
+
+    /// Validate the staged state without committing it. All invalid documents are reported.
+    pub fn validate(&self) -> Result<(), anyhow::Error> {
+        let mut check = BTreeSet::new();
+        for did in &self.touched {
+            check.insert(did.clone());
+            check.extend(self.staged.documents_referencing(did));
+        }
+
+        let errors: Vec<String> = check
+            .iter()
+            .filter_map(|did| self.staged.get_arc(did))
+            .filter_map(|doc| {
+                doc.valid(Some(&self.staged))
+                    .err()
+                    .map(|e| format!("{}: {}", doc.id, e))
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(anyhow!(
+                "Transaction failed validation: {}",
+                errors.join("; ")
+            ))
+        }
+    }
+
+    /// Validate the staged state and, if it is valid, apply it to the registry. Nothing is
+    /// applied if validation fails.
+    pub fn commit(self) -> Result<(), anyhow::Error> {
+        self.validate()?;
+        self.registry.publish(self.staged);
+        Ok(())
+    }
+
+    /// Discard the transaction. This is the same as dropping it.
+    pub fn rollback(self) {}
+}
+
+mod tests {
+    #[test]
+    fn test_transaction() {
+        use crate::{
+            did::DID,
+            document::{
+                Document, VerificationMethod, VerificationMethodEither, VerificationMethods,
+            },
+            registry::Registry,
+            url::URL,
+        };
+        use either::Either;
+
+        let did = |name: &str| DID::parse(&format!("did:testing:u:{}", name)).unwrap();
+        let referencing = |name: &str, key: &str| Document {
+            id: did(name),
+            assertion_method: Some(VerificationMethods(
+                [VerificationMethodEither(Either::Right(
+                    URL::parse(&format!("{}#{}", did(key), "key-1")).unwrap(),
+                ))]
+                .into(),
+            )),
+            ..Default::default()
+        };
+        let keyed = |name: &str| Document {
+            id: did(name),
+            verification_method: Some(
+                [VerificationMethod {
+                    id: URL::parse(&format!("{}#key-1", did(name))).unwrap(),
+                    controller: did(name),
+                    ..Default::default()
+                }]
+                .into(),
+            ),
+            ..Default::default()
+        };
+
+        let mut reg = Registry::default();
+        reg.insert(keyed("alice")).unwrap();
+
+        // a failing batch leaves nothing behind
+        assert!(reg
+            .transact(|tx| {
+                tx.insert(referencing("bob", "alice"))?;
+                tx.insert(referencing("charlie", "nobody"))
+            })
+            .is_err());
+        assert_eq!(reg.len(), 1);
+
+        // a failed operation does not poison the transaction
+        let mut tx = reg.transaction();
+        tx.insert(referencing("bob", "alice")).unwrap();
+        assert!(tx.insert(keyed("alice")).is_err());
+        assert!(tx.remove(&did("nobody")).is_err());
+        assert!(tx.get(&did("bob")).is_some());
+        tx.commit().unwrap();
+        assert_eq!(reg.len(), 2);
+
+        // replacing a referenced document with one missing the key invalidates the referrer
+        let mut tx = reg.transaction();
+        tx.update(Document {
+            id: did("alice"),
+            ..Default::default()
+        })
+        .unwrap();
+        let err = tx.commit().unwrap_err().to_string();
+        assert!(err.contains("did:testing:u:bob"));
+        assert!(reg[&did("alice")].verification_method.is_some());
+
+        // removing both together is fine
+        let mut tx = reg.transaction();
+        tx.remove(&did("alice")).unwrap();
+        tx.remove(&did("bob")).unwrap();
+        assert_eq!(tx.touched().len(), 2);
+        tx.commit().unwrap();
+        assert!(reg.is_empty());
+
+        let mut tx = reg.transaction();
+        tx.insert(keyed("alice")).unwrap();
+        tx.rollback();
+        assert!(reg.is_empty());
+    }
+}
@@ -216,9 +216,9 @@ impl URL {
                         Self::split_fragment(method_name.as_bytes(), right)
                     }
                 }
-                None => return Err(anyhow!("DID did not contain method specific ID")),
+                None => Err(anyhow!("DID did not contain method specific ID")),
             },
-            None => return Err(anyhow!("DID did not start with `did:` scheme")),
+            None => Err(anyhow!("DID did not start with `did:` scheme")),
         }
     }
 
@@ -369,8 +369,8 @@ impl URL {
             }),
         };
 
-        if query.is_some() {
-            url.parse_query(query.unwrap())?;
+        if let Some(query) = query {
+            url.parse_query(query)?;
         }
 
         Ok(url)
@@ -434,7 +434,8 @@ impl URL {
             }
             "versionId" => params.version_id = Some(String::from_utf8(right.to_vec())?),
             "versionTime" => {
-                params.version_time = Some(VersionTime::parse(&String::from_utf8(right.to_vec())?)?)
+                params.version_time =
+                    Some(VersionTime::parse(&String::from_utf8(url_decoded(right))?)?)
             }
             "hl" => params.hash_link = Some(String::from_utf8(right.to_vec())?),
             _ => {